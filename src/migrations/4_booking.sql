@@ -0,0 +1,25 @@
+CREATE TABLE "staff" (
+  staff_id uuid PRIMARY KEY default gen_random_uuid(),
+  business uuid NOT NULL REFERENCES "business" (business_id) ON DELETE CASCADE,
+  name varchar(40) COLLATE "case_insensitive" NOT NULL,
+  location varchar(40),
+  services text[] NOT NULL default '{}',
+  UNIQUE (business, name)
+);
+
+CREATE TABLE "booking" (
+  booking_id uuid PRIMARY KEY default gen_random_uuid(),
+  business uuid NOT NULL REFERENCES "business" (business_id) ON DELETE CASCADE,
+  staff uuid NOT NULL REFERENCES "staff" (staff_id) ON DELETE CASCADE,
+  service varchar(80) NOT NULL,
+  variant varchar(80),
+  customer varchar(80) NOT NULL,
+  starts_at timestamptz NOT NULL,
+  ends_at timestamptz NOT NULL,
+  status varchar(20) NOT NULL default 'confirmed',
+  created_at timestamptz NOT NULL default now(),
+  CHECK (ends_at > starts_at)
+);
+
+CREATE INDEX booking_staff_starts_at ON "booking" (staff, starts_at) WHERE status = 'confirmed';
+CREATE INDEX booking_customer ON "booking" (business, customer);
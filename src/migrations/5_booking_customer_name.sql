@@ -0,0 +1 @@
+ALTER TABLE "booking" ADD COLUMN customer_name varchar(80);
@@ -0,0 +1,13 @@
+CREATE TABLE "busy_time" (
+  busy_time_id uuid PRIMARY KEY default gen_random_uuid(),
+  business uuid NOT NULL REFERENCES "business" (business_id) ON DELETE CASCADE,
+  staff uuid REFERENCES "staff" (staff_id) ON DELETE CASCADE,
+  source varchar(200) NOT NULL,
+  uid varchar(255) NOT NULL,
+  summary text NOT NULL,
+  starts_at timestamptz NOT NULL,
+  ends_at timestamptz NOT NULL,
+  CHECK (ends_at > starts_at)
+);
+
+CREATE INDEX busy_time_business_starts_at ON "busy_time" (business, starts_at);
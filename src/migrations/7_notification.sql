@@ -0,0 +1,7 @@
+CREATE TABLE "notification" (
+  booking uuid NOT NULL REFERENCES "booking" (booking_id) ON DELETE CASCADE,
+  kind varchar(40) NOT NULL,
+  status varchar(20) NOT NULL,
+  created_at timestamptz NOT NULL default now(),
+  PRIMARY KEY (booking, kind)
+);
@@ -0,0 +1,786 @@
+use chrono::{DateTime, Duration, NaiveDate, NaiveDateTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use serde_derive::{Serialize, Deserialize};
+use sqlx::postgres::{PgPool, PgPoolOptions, PgRow};
+use sqlx::{PgExecutor, Postgres, Row, Transaction};
+use uuid::Uuid;
+
+use crate::calendar::{booking_uid, CalendarEvent};
+use crate::catalog::Catalog;
+use crate::error::AppError;
+use crate::hours::{Location, OpeningHours};
+use crate::read_input;
+
+fn default_slot_minutes() -> u32 {
+    15
+}
+
+fn default_min_notice_minutes() -> u32 {
+    60
+}
+
+fn default_search_days() -> u32 {
+    14
+}
+
+// Kept in booking.json in the business's data directory, bookings are off for businesses without one
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BookingConfig {
+    // The business's row in the database
+    pub business_id: Uuid,
+    // Only in files saved before the timezone moved to clock.json, read until the business has one
+    #[serde(default, skip_serializing)]
+    pub timezone: Option<String>,
+    // Kept free before and after every appointment
+    #[serde(default)]
+    pub buffer_minutes: u32,
+    // Offered start times are this far apart
+    #[serde(default = "default_slot_minutes")]
+    pub slot_minutes: u32,
+    // How soon from now a customer can book
+    #[serde(default = "default_min_notice_minutes")]
+    pub min_notice_minutes: u32,
+    #[serde(default = "default_search_days")]
+    pub search_days: u32,
+}
+
+impl BookingConfig {
+    // None when the business doesn't want the assistant taking bookings
+    pub fn collect() -> Result<Option<Self>, AppError> {
+        println!("Would you like the assistant to take appointment bookings? (y/N)");
+        if !read_input()?.eq_ignore_ascii_case("y") {
+            return Ok(None);
+        }
+
+        let business_id = loop {
+            println!("What is the business's ID in the database?");
+            match read_input()?.parse::<Uuid>() {
+                Ok(business_id) => break business_id,
+                Err(e) => println!("That isn't a valid ID: {}", e),
+            }
+        };
+
+        let buffer_minutes = loop {
+            println!("How many minutes should be kept free between appointments? (leave blank for none)");
+            let answer = read_input()?;
+            if answer.is_empty() {
+                break 0;
+            }
+            match answer.parse::<u32>() {
+                Ok(minutes) => break minutes,
+                Err(_) => println!("Please give a number of minutes."),
+            }
+        };
+
+        Ok(Some(BookingConfig {
+            business_id,
+            timezone: None,
+            buffer_minutes,
+            slot_minutes: default_slot_minutes(),
+            min_notice_minutes: default_min_notice_minutes(),
+            search_days: default_search_days(),
+        }))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Staff {
+    pub staff_id: Uuid,
+    pub name: String,
+    // Which of the business's locations they work at, the first one if not set
+    pub location: Option<String>,
+    // The catalog services they do, all of them if empty
+    pub services: Vec<String>,
+}
+
+impl Staff {
+    fn from_row(row: &PgRow) -> Result<Self, AppError> {
+        Ok(Staff {
+            staff_id: row.try_get("staff_id")?,
+            name: row.try_get("name")?,
+            location: row.try_get("location")?,
+            services: row.try_get("services")?,
+        })
+    }
+
+    fn does(&self, service: &str) -> bool {
+        self.services.is_empty() || self.services.iter().any(|name| name.eq_ignore_ascii_case(service))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BookingStatus {
+    Confirmed,
+    Cancelled,
+}
+
+impl BookingStatus {
+    fn as_str(&self) -> &'static str {
+        match self {
+            BookingStatus::Confirmed => "confirmed",
+            BookingStatus::Cancelled => "cancelled",
+        }
+    }
+
+    fn parse(status: &str) -> Result<Self, AppError> {
+        match status {
+            "confirmed" => Ok(BookingStatus::Confirmed),
+            "cancelled" => Ok(BookingStatus::Cancelled),
+            other => Err(AppError::Storage(format!("unknown booking status '{}'", other))),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Booking {
+    pub booking_id: Uuid,
+    pub staff_id: Uuid,
+    pub service: String,
+    pub variant: Option<String>,
+    // The customer's identity key, see CustomerIdentity
+    pub customer: String,
+    // What the customer asked for the booking to be under, if they were asked
+    pub customer_name: Option<String>,
+    pub starts_at: DateTime<Utc>,
+    pub ends_at: DateTime<Utc>,
+    pub status: BookingStatus,
+}
+
+impl Booking {
+    fn from_row(row: &PgRow) -> Result<Self, AppError> {
+        let status: String = row.try_get("status")?;
+
+        Ok(Booking {
+            booking_id: row.try_get("booking_id")?,
+            staff_id: row.try_get("staff")?,
+            service: row.try_get("service")?,
+            variant: row.try_get("variant")?,
+            customer: row.try_get("customer")?,
+            customer_name: row.try_get("customer_name")?,
+            starts_at: row.try_get("starts_at")?,
+            ends_at: row.try_get("ends_at")?,
+            status: BookingStatus::parse(&status)?,
+        })
+    }
+}
+
+const BOOKING_COLUMNS: &str = "booking_id, staff, service, variant, customer, customer_name, starts_at, ends_at, status";
+
+// How much of the diary goes into an exported calendar
+const EXPORT_PAST_DAYS: i64 = 30;
+const EXPORT_FUTURE_DAYS: i64 = 365;
+
+// Time someone can't be booked for, from a booking or a calendar kept elsewhere
+struct BusyPeriod {
+    // None blocks everyone, for a calendar imported for the whole business
+    staff_id: Option<Uuid>,
+    starts_at: DateTime<Utc>,
+    ends_at: DateTime<Utc>,
+}
+
+impl From<&Booking> for BusyPeriod {
+    fn from(booking: &Booking) -> Self {
+        BusyPeriod {
+            staff_id: Some(booking.staff_id),
+            starts_at: booking.starts_at,
+            ends_at: booking.ends_at,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Slot {
+    pub staff_name: String,
+    pub starts_at: DateTime<Tz>,
+    pub ends_at: DateTime<Tz>,
+}
+
+// What a customer wants booked, before it has a time
+pub struct BookingRequest<'a> {
+    pub service: &'a str,
+    pub variant: Option<&'a str>,
+    // Any staff member who does the service if not given
+    pub staff_id: Option<Uuid>,
+}
+
+// Appointments for one business, worked out from its staff, catalog durations, opening hours and what's
+// already booked, and kept in Postgres
+pub struct BookingService {
+    pool: PgPool,
+    config: BookingConfig,
+    timezone: Tz,
+    opening_hours: OpeningHours,
+    catalog: Catalog,
+}
+
+impl BookingService {
+    // DATABASE_URL says where Postgres is
+    pub async fn connect(config: BookingConfig, timezone: Tz, opening_hours: OpeningHours, catalog: Catalog) -> Result<Self, AppError> {
+        let database_url = std::env::var("DATABASE_URL")
+            .map_err(|_| AppError::Config("DATABASE_URL must be set to take bookings".to_string()))?;
+
+        let pool = PgPoolOptions::new()
+            .max_connections(5)
+            .connect(&database_url)
+            .await?;
+
+        Ok(BookingService { pool, config, timezone, opening_hours, catalog })
+    }
+
+    pub fn timezone(&self) -> Tz {
+        self.timezone
+    }
+
+    pub async fn staff(&self) -> Result<Vec<Staff>, AppError> {
+        sqlx::query("SELECT staff_id, name, location, services FROM staff WHERE business = $1 ORDER BY name")
+            .bind(self.config.business_id)
+            .fetch_all(&self.pool)
+            .await?
+            .iter()
+            .map(Staff::from_row)
+            .collect()
+    }
+
+    pub async fn add_staff(&self, name: &str, location: Option<&str>, services: &[String]) -> Result<Staff, AppError> {
+        if let Some(location) = location {
+            self.location(Some(location))?;
+        }
+        for service in services {
+            self.catalog.find(service).ok_or_else(|| AppError::UserInput(format!("'{}' isn't in the catalog", service)))?;
+        }
+
+        let row = sqlx::query(
+            "INSERT INTO staff (business, name, location, services) VALUES ($1, $2, $3, $4) RETURNING staff_id, name, location, services"
+        )
+            .bind(self.config.business_id)
+            .bind(name)
+            .bind(location)
+            .bind(services)
+            .fetch_one(&self.pool)
+            .await?;
+
+        Staff::from_row(&row)
+    }
+
+    fn location(&self, name: Option<&str>) -> Result<&Location, AppError> {
+        let location = match name {
+            Some(name) => self.opening_hours.locations.iter().find(|location| location.name.eq_ignore_ascii_case(name)),
+            None => self.opening_hours.locations.first(),
+        };
+
+        location.ok_or_else(|| match name {
+            Some(name) => AppError::Booking(format!("there are no opening hours for '{}'", name)),
+            None => AppError::Booking("the business has no opening hours to book within".to_string()),
+        })
+    }
+
+    fn duration(&self, request: &BookingRequest) -> Result<Duration, AppError> {
+        let item = self.catalog
+            .find(request.service)
+            .ok_or_else(|| AppError::Booking(format!("'{}' isn't a service we offer", request.service)))?;
+
+        let minutes = item.duration_minutes(request.variant)?
+            .ok_or_else(|| AppError::Booking(format!("{} has no duration set, so it can't be booked", item.name)))?;
+
+        Ok(Duration::minutes(minutes as i64))
+    }
+
+    fn earliest_start(&self) -> DateTime<Utc> {
+        Utc::now() + Duration::minutes(self.config.min_notice_minutes as i64)
+    }
+
+    fn local(&self, at: NaiveDateTime) -> Option<DateTime<Utc>> {
+        // A time skipped by a clock change has no instant, and the first of a repeated one is as good as any
+        self.timezone.from_local_datetime(&at).earliest().map(|at| at.with_timezone(&Utc))
+    }
+
+    // Free means inside opening hours at the staff member's location and clear of other bookings and imported busy
+    // times, buffers included
+    fn is_free(&self, staff: &Staff, starts_at: DateTime<Utc>, duration: Duration, busy: &[BusyPeriod]) -> Result<bool, AppError> {
+        let ends_at = starts_at + duration;
+        let buffer = Duration::minutes(self.config.buffer_minutes as i64);
+
+        let local_start = starts_at.with_timezone(&self.timezone).naive_local();
+        let local_end = ends_at.with_timezone(&self.timezone).naive_local();
+        let within_hours = self.location(staff.location.as_deref())?.is_open_throughout(local_start, local_end);
+
+        let clashes = busy.iter().any(|period| {
+            period.staff_id.is_none_or(|staff_id| staff_id == staff.staff_id)
+                && starts_at < period.ends_at + buffer
+                && period.starts_at - buffer < ends_at
+        });
+
+        Ok(within_hours && !clashes)
+    }
+
+    pub async fn confirmed_between(&self, from: DateTime<Utc>, to: DateTime<Utc>) -> Result<Vec<Booking>, AppError> {
+        sqlx::query(&format!(
+            "SELECT {} FROM booking WHERE business = $1 AND status = 'confirmed' AND starts_at < $3 AND ends_at > $2", BOOKING_COLUMNS
+        ))
+            .bind(self.config.business_id)
+            .bind(from)
+            .bind(to)
+            .fetch_all(&self.pool)
+            .await?
+            .iter()
+            .map(Booking::from_row)
+            .collect()
+    }
+
+    // Busy times imported from other calendars, for one staff member (and the whole business) or everyone
+    async fn imported_between<'e>(&self, executor: impl PgExecutor<'e>, staff_id: Option<Uuid>, from: DateTime<Utc>, to: DateTime<Utc>) -> Result<Vec<BusyPeriod>, AppError> {
+        sqlx::query(
+            "SELECT staff, starts_at, ends_at FROM busy_time WHERE business = $1 AND starts_at < $3 AND ends_at > $2 AND ($4::uuid IS NULL OR staff IS NULL OR staff = $4)"
+        )
+            .bind(self.config.business_id)
+            .bind(from)
+            .bind(to)
+            .bind(staff_id)
+            .fetch_all(executor)
+            .await?
+            .iter()
+            .map(|row| Ok(BusyPeriod {
+                staff_id: row.try_get("staff")?,
+                starts_at: row.try_get("starts_at")?,
+                ends_at: row.try_get("ends_at")?,
+            }))
+            .collect()
+    }
+
+    async fn candidates(&self, request: &BookingRequest<'_>) -> Result<Vec<Staff>, AppError> {
+        let staff: Vec<Staff> = self.staff()
+            .await?
+            .into_iter()
+            .filter(|staff| request.staff_id.is_none_or(|staff_id| staff.staff_id == staff_id))
+            .filter(|staff| staff.does(request.service))
+            .collect();
+
+        if staff.is_empty() {
+            return Err(AppError::Booking(format!("nobody is available to do {}", request.service)));
+        }
+
+        Ok(staff)
+    }
+
+    // Every start time in `days` days from `from` (business time) that someone who does the service is free for
+    pub async fn available_slots(&self, request: &BookingRequest<'_>, from: NaiveDate, days: Option<u32>) -> Result<Vec<Slot>, AppError> {
+        let duration = self.duration(request)?;
+        let staff = self.candidates(request).await?;
+        let days = days.unwrap_or(self.config.search_days) as i64;
+
+        let window_start = self.local(from.and_hms_opt(0, 0, 0).unwrap_or_default()).unwrap_or_else(Utc::now);
+        let window_end = window_start + Duration::days(days + 1);
+        let buffer = Duration::minutes(self.config.buffer_minutes as i64);
+        let mut busy: Vec<BusyPeriod> = self.confirmed_between(window_start - buffer, window_end + buffer).await?.iter().map(BusyPeriod::from).collect();
+        busy.extend(self.imported_between(&self.pool, None, window_start - buffer, window_end + buffer).await?);
+
+        let earliest = self.earliest_start();
+        let mut slots = Vec::new();
+
+        for date in (0..days).map(|offset| from + Duration::days(offset)) {
+            for member in &staff {
+                slots.extend(self.slots_on(member, date, duration, earliest, &busy)?);
+            }
+        }
+
+        slots.sort_by_key(|slot| slot.starts_at);
+        Ok(slots)
+    }
+
+    // The start times one staff member is free for in the hours that open on this date, a slot_minutes apart from
+    // each opening time
+    fn slots_on(&self, member: &Staff, date: NaiveDate, duration: Duration, earliest: DateTime<Utc>, busy: &[BusyPeriod]) -> Result<Vec<Slot>, AppError> {
+        let step = Duration::minutes(self.config.slot_minutes.max(1) as i64);
+        let mut slots = Vec::new();
+
+        for range in self.location(member.location.as_deref())?.hours_on(date) {
+            let (mut start, close) = range.on(date);
+            while start + duration <= close {
+                if let Some(starts_at) = self.local(start).filter(|starts_at| *starts_at >= earliest) {
+                    if self.is_free(member, starts_at, duration, busy)? {
+                        slots.push(Slot {
+                            staff_name: member.name.clone(),
+                            starts_at: starts_at.with_timezone(&self.timezone),
+                            ends_at: (starts_at + duration).with_timezone(&self.timezone),
+                        });
+                    }
+                }
+                start += step;
+            }
+        }
+
+        Ok(slots)
+    }
+
+    // Picks a staff member free at that time, holding a lock on them until the transaction ends so two
+    // customers can't take the same slot
+    async fn claim(&self, transaction: &mut Transaction<'_, Postgres>, request: &BookingRequest<'_>, starts_at: DateTime<Utc>, excluding: Option<Uuid>) -> Result<(Staff, Duration), AppError> {
+        let duration = self.duration(request)?;
+        if starts_at < self.earliest_start() {
+            return Err(AppError::Booking(format!("bookings need at least {} minutes' notice", self.config.min_notice_minutes)));
+        }
+
+        let buffer = Duration::minutes(self.config.buffer_minutes as i64);
+        for member in self.candidates(request).await? {
+            sqlx::query("SELECT staff_id FROM staff WHERE staff_id = $1 FOR UPDATE")
+                .bind(member.staff_id)
+                .execute(&mut **transaction)
+                .await?;
+
+            let mut busy: Vec<BusyPeriod> = sqlx::query(&format!(
+                "SELECT {} FROM booking WHERE staff = $1 AND status = 'confirmed' AND starts_at < $3 AND ends_at > $2", BOOKING_COLUMNS
+            ))
+                .bind(member.staff_id)
+                .bind(starts_at - buffer)
+                .bind(starts_at + duration + buffer)
+                .fetch_all(&mut **transaction)
+                .await?
+                .iter()
+                .map(Booking::from_row)
+                .collect::<Result<Vec<Booking>, AppError>>()?
+                .iter()
+                .filter(|booking| Some(booking.booking_id) != excluding)
+                .map(BusyPeriod::from)
+                .collect();
+            busy.extend(self.imported_between(&mut **transaction, Some(member.staff_id), starts_at - buffer, starts_at + duration + buffer).await?);
+
+            if self.is_free(&member, starts_at, duration, &busy)? {
+                return Ok((member, duration));
+            }
+        }
+
+        Err(AppError::Booking(format!(
+            "{} isn't available at {}", request.service, starts_at.with_timezone(&self.timezone).format("%A %-d %B %H:%M")
+        )))
+    }
+
+    pub async fn create(&self, customer: &str, customer_name: Option<&str>, request: &BookingRequest<'_>, starts_at: DateTime<Utc>) -> Result<Booking, AppError> {
+        let mut transaction = self.pool.begin().await?;
+        let (staff, duration) = self.claim(&mut transaction, request, starts_at, None).await?;
+
+        let row = sqlx::query(&format!(
+            "INSERT INTO booking (business, staff, service, variant, customer, customer_name, starts_at, ends_at, status) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9) RETURNING {}",
+            BOOKING_COLUMNS
+        ))
+            .bind(self.config.business_id)
+            .bind(staff.staff_id)
+            .bind(request.service)
+            .bind(request.variant)
+            .bind(customer)
+            .bind(customer_name)
+            .bind(starts_at)
+            .bind(starts_at + duration)
+            .bind(BookingStatus::Confirmed.as_str())
+            .fetch_one(&mut *transaction)
+            .await?;
+
+        transaction.commit().await?;
+        Booking::from_row(&row)
+    }
+
+    async fn confirmed(&self, booking_id: Uuid) -> Result<Booking, AppError> {
+        let row = sqlx::query(&format!(
+            "SELECT {} FROM booking WHERE business = $1 AND booking_id = $2", BOOKING_COLUMNS
+        ))
+            .bind(self.config.business_id)
+            .bind(booking_id)
+            .fetch_optional(&self.pool)
+            .await?
+            .ok_or_else(|| AppError::Booking(format!("there's no booking {}", booking_id)))?;
+
+        let booking = Booking::from_row(&row)?;
+        if booking.status != BookingStatus::Confirmed {
+            return Err(AppError::Booking(format!("booking {} was already cancelled", booking_id)));
+        }
+
+        Ok(booking)
+    }
+
+    // Moves a booking to a new time, with the same staff member unless one is given or they're busy then
+    pub async fn reschedule(&self, booking_id: Uuid, starts_at: DateTime<Utc>, staff_id: Option<Uuid>) -> Result<Booking, AppError> {
+        let booking = self.confirmed(booking_id).await?;
+        let request = BookingRequest {
+            service: &booking.service,
+            variant: booking.variant.as_deref(),
+            staff_id,
+        };
+
+        let mut transaction = self.pool.begin().await?;
+        let (staff, duration) = match staff_id {
+            Some(_) => self.claim(&mut transaction, &request, starts_at, Some(booking_id)).await?,
+            None => {
+                let same_staff = BookingRequest { staff_id: Some(booking.staff_id), ..request };
+                match self.claim(&mut transaction, &same_staff, starts_at, Some(booking_id)).await {
+                    Ok(claimed) => claimed,
+                    Err(AppError::Booking(_)) => self.claim(&mut transaction, &request, starts_at, Some(booking_id)).await?,
+                    Err(e) => return Err(e),
+                }
+            }
+        };
+
+        let row = sqlx::query(&format!(
+            "UPDATE booking SET staff = $3, starts_at = $4, ends_at = $5 WHERE business = $1 AND booking_id = $2 AND status = 'confirmed' RETURNING {}",
+            BOOKING_COLUMNS
+        ))
+            .bind(self.config.business_id)
+            .bind(booking_id)
+            .bind(staff.staff_id)
+            .bind(starts_at)
+            .bind(starts_at + duration)
+            .fetch_optional(&mut *transaction)
+            .await?
+            .ok_or_else(|| AppError::Booking(format!("booking {} was cancelled in the meantime", booking_id)))?;
+
+        // Reminders sent for the old time don't count for the new one
+        sqlx::query("DELETE FROM notification WHERE booking = $1")
+            .bind(booking_id)
+            .execute(&mut *transaction)
+            .await?;
+
+        transaction.commit().await?;
+        Booking::from_row(&row)
+    }
+
+    // Only a confirmed booking can be cancelled, checked in the UPDATE itself so a cancel racing another cancel or a
+    // reschedule can't both go through
+    pub async fn cancel(&self, booking_id: Uuid) -> Result<Booking, AppError> {
+        let row = sqlx::query(&format!(
+            "UPDATE booking SET status = $3 WHERE business = $1 AND booking_id = $2 AND status = 'confirmed' RETURNING {}", BOOKING_COLUMNS
+        ))
+            .bind(self.config.business_id)
+            .bind(booking_id)
+            .bind(BookingStatus::Cancelled.as_str())
+            .fetch_optional(&self.pool)
+            .await?
+            .ok_or_else(|| AppError::Booking(format!("there's no confirmed booking {}, it may have been cancelled already", booking_id)))?;
+
+        Booking::from_row(&row)
+    }
+
+    pub async fn upcoming_for(&self, customer: &str) -> Result<Vec<Booking>, AppError> {
+        sqlx::query(&format!(
+            "SELECT {} FROM booking WHERE business = $1 AND customer = $2 AND status = 'confirmed' AND ends_at > now() ORDER BY starts_at",
+            BOOKING_COLUMNS
+        ))
+            .bind(self.config.business_id)
+            .bind(customer)
+            .fetch_all(&self.pool)
+            .await?
+            .iter()
+            .map(Booking::from_row)
+            .collect()
+    }
+
+    // Confirmed bookings from a month ago onwards, for everyone or one staff member, as calendar events
+    pub async fn calendar_events(&self, staff_id: Option<Uuid>) -> Result<Vec<CalendarEvent>, AppError> {
+        let now = Utc::now();
+        let staff = self.staff().await?;
+        let bookings = self.confirmed_between(now - Duration::days(EXPORT_PAST_DAYS), now + Duration::days(EXPORT_FUTURE_DAYS)).await?;
+
+        Ok(bookings
+            .iter()
+            .filter(|booking| staff_id.is_none_or(|staff_id| booking.staff_id == staff_id))
+            .map(|booking| {
+                let service = match &booking.variant {
+                    Some(variant) => format!("{} ({})", booking.service, variant),
+                    None => booking.service.clone(),
+                };
+                let staff_name = staff.iter().find(|staff| staff.staff_id == booking.staff_id).map_or("", |staff| staff.name.as_str());
+
+                CalendarEvent {
+                    uid: booking_uid(booking.booking_id),
+                    summary: match &booking.customer_name {
+                        Some(name) => format!("{} - {}", service, name),
+                        None => service,
+                    },
+                    description: Some(format!("With {}\nCustomer: {}\nBooking: {}", staff_name, booking.customer, booking.booking_id)),
+                    starts_at: booking.starts_at,
+                    ends_at: booking.ends_at,
+                }
+            })
+            .collect())
+    }
+
+    // Replaces whatever an earlier import of the same calendar brought in, so importing again picks up changes
+    pub async fn import_busy(&self, staff_id: Option<Uuid>, source: &str, events: &[CalendarEvent]) -> Result<usize, AppError> {
+        let mut transaction = self.pool.begin().await?;
+
+        sqlx::query("DELETE FROM busy_time WHERE business = $1 AND source = $2 AND staff IS NOT DISTINCT FROM $3")
+            .bind(self.config.business_id)
+            .bind(source)
+            .bind(staff_id)
+            .execute(&mut *transaction)
+            .await?;
+
+        for event in events {
+            sqlx::query(
+                "INSERT INTO busy_time (business, staff, source, uid, summary, starts_at, ends_at) VALUES ($1, $2, $3, $4, $5, $6, $7)"
+            )
+                .bind(self.config.business_id)
+                .bind(staff_id)
+                .bind(source)
+                .bind(&event.uid)
+                .bind(&event.summary)
+                .bind(event.starts_at)
+                .bind(event.ends_at)
+                .execute(&mut *transaction)
+                .await?;
+        }
+
+        transaction.commit().await?;
+        Ok(events.len())
+    }
+
+    // Marks a reminder or follow-up as dealt with, false if it already was, so each goes out once per booking
+    pub async fn record_notification(&self, booking_id: Uuid, kind: &str, status: &str) -> Result<bool, AppError> {
+        let result = sqlx::query("INSERT INTO notification (booking, kind, status) VALUES ($1, $2, $3) ON CONFLICT DO NOTHING")
+            .bind(booking_id)
+            .bind(kind)
+            .bind(status)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected() == 1)
+    }
+
+    // For one that couldn't be sent, so the next run tries again
+    pub async fn forget_notification(&self, booking_id: Uuid, kind: &str) -> Result<(), AppError> {
+        sqlx::query("DELETE FROM notification WHERE booking = $1 AND kind = $2")
+            .bind(booking_id)
+            .bind(kind)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hours::parse_ranges;
+    use chrono::Weekday;
+    use std::collections::HashMap;
+
+    // Slot finding doesn't touch the database, so the pool never connects
+    fn service(buffer_minutes: u32, slot_minutes: u32) -> BookingService {
+        let location = Location {
+            name: "High Street".to_string(),
+            address: None,
+            weekly: HashMap::from([
+                (Weekday::Mon, parse_ranges("09:00-12:00").unwrap()),
+                (Weekday::Fri, parse_ranges("18:00-02:00").unwrap()),
+            ]),
+            exceptions: Vec::new(),
+        };
+
+        BookingService {
+            pool: PgPoolOptions::new().connect_lazy("postgres://localhost/unused").unwrap(),
+            config: BookingConfig {
+                business_id: Uuid::nil(),
+                timezone: None,
+                buffer_minutes,
+                slot_minutes,
+                min_notice_minutes: 0,
+                search_days: 7,
+            },
+            timezone: chrono_tz::Europe::London,
+            opening_hours: OpeningHours { locations: vec![location] },
+            catalog: Catalog::default(),
+        }
+    }
+
+    fn alice() -> Staff {
+        Staff { staff_id: Uuid::from_u128(1), name: "Alice".to_string(), location: None, services: Vec::new() }
+    }
+
+    fn at(service: &BookingService, text: &str) -> DateTime<Utc> {
+        service.local(NaiveDateTime::parse_from_str(text, "%Y-%m-%d %H:%M").unwrap()).unwrap()
+    }
+
+    fn busy(service: &BookingService, staff_id: Option<Uuid>, from: &str, to: &str) -> BusyPeriod {
+        BusyPeriod { staff_id, starts_at: at(service, from), ends_at: at(service, to) }
+    }
+
+    fn start_times(slots: &[Slot]) -> Vec<String> {
+        slots.iter().map(|slot| slot.starts_at.format("%a %H:%M").to_string()).collect()
+    }
+
+    // 2026-10-12 is a Monday
+    fn monday() -> NaiveDate {
+        NaiveDate::from_ymd_opt(2026, 10, 12).unwrap()
+    }
+
+    fn long_ago() -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap()
+    }
+
+    #[tokio::test]
+    async fn slots_step_from_opening_and_have_to_end_by_closing() {
+        let service = service(0, 30);
+
+        let slots = service.slots_on(&alice(), monday(), Duration::minutes(60), long_ago(), &[]).unwrap();
+        assert_eq!(start_times(&slots), vec!["Mon 09:00", "Mon 09:30", "Mon 10:00", "Mon 10:30", "Mon 11:00"]);
+        assert_eq!(slots[4].ends_at.format("%H:%M").to_string(), "12:00");
+
+        // Crossing closing time isn't free even with nothing booked
+        assert!(service.is_free(&alice(), at(&service, "2026-10-12 11:00"), Duration::minutes(60), &[]).unwrap());
+        assert!(!service.is_free(&alice(), at(&service, "2026-10-12 11:30"), Duration::minutes(60), &[]).unwrap());
+        assert!(!service.is_free(&alice(), at(&service, "2026-10-12 08:45"), Duration::minutes(30), &[]).unwrap());
+
+        // Nothing on a closed day, and nothing before the earliest start
+        assert!(service.slots_on(&alice(), monday() + Duration::days(1), Duration::minutes(60), long_ago(), &[]).unwrap().is_empty());
+        let later = service.slots_on(&alice(), monday(), Duration::minutes(60), at(&service, "2026-10-12 10:15"), &[]).unwrap();
+        assert_eq!(start_times(&later), vec!["Mon 10:30", "Mon 11:00"]);
+    }
+
+    #[tokio::test]
+    async fn buffers_keep_time_free_either_side_of_a_booking() {
+        let service = service(15, 15);
+        let booked = [busy(&service, Some(alice().staff_id), "2026-10-12 10:00", "2026-10-12 10:30")];
+
+        let slots = service.slots_on(&alice(), monday(), Duration::minutes(30), long_ago(), &booked).unwrap();
+        assert_eq!(start_times(&slots), vec![
+            "Mon 09:00", "Mon 09:15", "Mon 10:45", "Mon 11:00", "Mon 11:15", "Mon 11:30",
+        ]);
+
+        // Without the buffer the times either side are free
+        let unbuffered = self::service(0, 15);
+        assert!(unbuffered.is_free(&alice(), at(&unbuffered, "2026-10-12 09:30"), Duration::minutes(30), &booked).unwrap());
+        assert!(unbuffered.is_free(&alice(), at(&unbuffered, "2026-10-12 10:30"), Duration::minutes(30), &booked).unwrap());
+        assert!(!unbuffered.is_free(&alice(), at(&unbuffered, "2026-10-12 10:15"), Duration::minutes(30), &booked).unwrap());
+    }
+
+    #[tokio::test]
+    async fn overnight_hours_offer_slots_past_midnight_until_closing() {
+        let service = service(0, 30);
+        let friday = monday() + Duration::days(4);
+
+        let slots = service.slots_on(&alice(), friday, Duration::minutes(60), long_ago(), &[]).unwrap();
+        assert_eq!(slots.first().map(|slot| slot.starts_at.format("%a %H:%M").to_string()).as_deref(), Some("Fri 18:00"));
+        assert_eq!(slots.last().map(|slot| slot.starts_at.format("%a %H:%M").to_string()).as_deref(), Some("Sat 01:00"));
+        assert_eq!(slots.len(), 15);
+
+        assert!(service.is_free(&alice(), at(&service, "2026-10-17 00:30"), Duration::minutes(90), &[]).unwrap());
+        assert!(!service.is_free(&alice(), at(&service, "2026-10-17 01:30"), Duration::minutes(60), &[]).unwrap());
+        // Saturday's own hours don't start until the next Friday evening
+        assert!(service.slots_on(&alice(), friday + Duration::days(1), Duration::minutes(60), long_ago(), &[]).unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn busy_times_for_the_whole_business_block_everyone() {
+        let service = service(0, 30);
+        let starts_at = at(&service, "2026-10-12 10:00");
+        let duration = Duration::minutes(30);
+
+        let everyone = [busy(&service, None, "2026-10-12 09:45", "2026-10-12 10:15")];
+        assert!(!service.is_free(&alice(), starts_at, duration, &everyone).unwrap());
+
+        let someone_else = [busy(&service, Some(Uuid::from_u128(2)), "2026-10-12 09:45", "2026-10-12 10:15")];
+        assert!(service.is_free(&alice(), starts_at, duration, &someone_else).unwrap());
+
+        let slots = service.slots_on(&alice(), monday(), Duration::minutes(60), long_ago(), &everyone).unwrap();
+        assert_eq!(start_times(&slots), vec!["Mon 10:30", "Mon 11:00"]);
+    }
+}
@@ -0,0 +1,519 @@
+use chrono::{DateTime, Datelike, Duration, NaiveDate, NaiveDateTime, TimeZone, Utc, Weekday};
+use chrono_tz::Tz;
+
+use crate::error::AppError;
+
+// Ends the UID of every event we export, so a calendar we wrote can be imported back without our own bookings
+// blocking themselves
+const UID_SUFFIX: &str = "@prompt-generator";
+
+// How far ahead repeating events are expanded when importing
+const IMPORT_HORIZON_DAYS: i64 = 365;
+
+// RFC 5545 says lines should be no longer than this, in bytes
+const MAX_LINE_BYTES: usize = 75;
+
+// One appointment or busy period, in either direction
+#[derive(Debug, Clone)]
+pub struct CalendarEvent {
+    pub uid: String,
+    pub summary: String,
+    pub description: Option<String>,
+    pub starts_at: DateTime<Utc>,
+    pub ends_at: DateTime<Utc>,
+}
+
+pub struct ImportedCalendar {
+    pub events: Vec<CalendarEvent>,
+    // What we couldn't use and why, for the person importing
+    pub skipped: Vec<String>,
+}
+
+pub fn booking_uid(booking_id: uuid::Uuid) -> String {
+    format!("{}{}", booking_id, UID_SUFFIX)
+}
+
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace(';', "\\;").replace(',', "\\,").replace("\r\n", "\\n").replace('\n', "\\n")
+}
+
+fn unescape(text: &str) -> String {
+    let mut unescaped = String::new();
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n' | 'N') => unescaped.push('\n'),
+            Some(other) => unescaped.push(other),
+            None => {}
+        }
+    }
+    unescaped
+}
+
+// Long lines carry on after a line break and a space
+fn fold(line: &str) -> String {
+    let mut folded = String::new();
+    let mut length = 0;
+    for c in line.chars() {
+        if length + c.len_utf8() > MAX_LINE_BYTES {
+            folded.push_str("\r\n ");
+            length = 1;
+        }
+        folded.push(c);
+        length += c.len_utf8();
+    }
+    folded.push_str("\r\n");
+    folded
+}
+
+fn utc_stamp(at: DateTime<Utc>) -> String {
+    at.format("%Y%m%dT%H%M%SZ").to_string()
+}
+
+// An iCalendar feed calendar apps can import or subscribe to, times in UTC so no timezone definitions are needed
+pub fn export(name: &str, timezone: Tz, events: &[CalendarEvent]) -> String {
+    let mut lines = vec![
+        "BEGIN:VCALENDAR".to_string(),
+        "VERSION:2.0".to_string(),
+        "PRODID:-//prompt-generator//bookings//EN".to_string(),
+        "CALSCALE:GREGORIAN".to_string(),
+        "METHOD:PUBLISH".to_string(),
+        format!("X-WR-CALNAME:{}", escape(name)),
+        format!("X-WR-TIMEZONE:{}", timezone.name()),
+    ];
+
+    let stamp = utc_stamp(Utc::now());
+    for event in events {
+        lines.push("BEGIN:VEVENT".to_string());
+        lines.push(format!("UID:{}", event.uid));
+        lines.push(format!("DTSTAMP:{}", stamp));
+        lines.push(format!("DTSTART:{}", utc_stamp(event.starts_at)));
+        lines.push(format!("DTEND:{}", utc_stamp(event.ends_at)));
+        lines.push(format!("SUMMARY:{}", escape(&event.summary)));
+        if let Some(description) = &event.description {
+            lines.push(format!("DESCRIPTION:{}", escape(description)));
+        }
+        lines.push("STATUS:CONFIRMED".to_string());
+        lines.push("END:VEVENT".to_string());
+    }
+    lines.push("END:VCALENDAR".to_string());
+
+    lines.iter().map(|line| fold(line)).collect()
+}
+
+// A content line: name, parameters and value, e.g. DTSTART;TZID=Europe/London:20261103T093000
+struct Property {
+    name: String,
+    params: Vec<(String, String)>,
+    value: String,
+}
+
+impl Property {
+    fn parse(line: &str) -> Option<Self> {
+        // The value starts at the first colon that isn't inside a quoted parameter
+        let mut quoted = false;
+        let split = line.char_indices().find(|(_, c)| {
+            if *c == '"' {
+                quoted = !quoted;
+            }
+            *c == ':' && !quoted
+        })?.0;
+        let (head, value) = (&line[..split], &line[split + 1..]);
+
+        let mut parts = head.split(';');
+        let name = parts.next()?.to_uppercase();
+        let params = parts
+            .filter_map(|param| param.split_once('='))
+            .map(|(key, value)| (key.to_uppercase(), value.trim_matches('"').to_string()))
+            .collect();
+
+        Some(Property { name, params, value: value.to_string() })
+    }
+
+    fn param(&self, key: &str) -> Option<&str> {
+        self.params.iter().find(|(name, _)| name == key).map(|(_, value)| value.as_str())
+    }
+}
+
+// When an event starts or ends, and whether it was a whole day
+fn parse_time(property: &Property, timezone: Tz) -> Option<(DateTime<Utc>, bool)> {
+    let value = property.value.trim();
+
+    if property.param("VALUE") == Some("DATE") || value.len() == 8 {
+        let date = NaiveDate::parse_from_str(value, "%Y%m%d").ok()?;
+        let midnight = timezone.from_local_datetime(&date.and_hms_opt(0, 0, 0)?).earliest()?;
+        return Some((midnight.with_timezone(&Utc), true));
+    }
+
+    if let Some(utc) = value.strip_suffix('Z') {
+        let at = NaiveDateTime::parse_from_str(utc, "%Y%m%dT%H%M%S").ok()?;
+        return Some((Utc.from_utc_datetime(&at), false));
+    }
+
+    // Windows names like "GMT Standard Time" aren't IANA ones, the business's own timezone is the best guess
+    let zone = property.param("TZID").and_then(|name| name.parse::<Tz>().ok()).unwrap_or(timezone);
+    let at = NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%S").ok()?;
+    Some((zone.from_local_datetime(&at).earliest()?.with_timezone(&Utc), false))
+}
+
+// "PT1H30M", "P1D", "P2W"
+fn parse_duration(text: &str) -> Option<Duration> {
+    let text = text.trim().trim_start_matches('+').strip_prefix('P')?;
+    let mut total = Duration::zero();
+    let mut number = String::new();
+    let mut in_time = false;
+
+    for c in text.chars() {
+        match c {
+            'T' => in_time = true,
+            '0'..='9' => number.push(c),
+            unit => {
+                let amount: i64 = number.parse().ok()?;
+                number.clear();
+                total += match (unit, in_time) {
+                    ('W', false) => Duration::weeks(amount),
+                    ('D', false) => Duration::days(amount),
+                    ('H', true) => Duration::hours(amount),
+                    ('M', true) => Duration::minutes(amount),
+                    ('S', true) => Duration::seconds(amount),
+                    _ => return None,
+                };
+            }
+        }
+    }
+
+    Some(total)
+}
+
+fn weekday(code: &str) -> Option<Weekday> {
+    match code.trim_start_matches(|c: char| c.is_ascii_digit() || c == '-' || c == '+') {
+        "MO" => Some(Weekday::Mon),
+        "TU" => Some(Weekday::Tue),
+        "WE" => Some(Weekday::Wed),
+        "TH" => Some(Weekday::Thu),
+        "FR" => Some(Weekday::Fri),
+        "SA" => Some(Weekday::Sat),
+        "SU" => Some(Weekday::Sun),
+        _ => None,
+    }
+}
+
+// Start times of a repeating event, only daily and weekly repeats, which covers the usual standing meetings and
+// days off. None for rules we can't follow.
+fn repeats(rule: &str, first: DateTime<Utc>, timezone: Tz, horizon: DateTime<Utc>) -> Option<Vec<DateTime<Utc>>> {
+    let parts: Vec<(&str, &str)> = rule.split(';').filter_map(|part| part.split_once('=')).collect();
+    let get = |key: &str| parts.iter().find(|(name, _)| name.eq_ignore_ascii_case(key)).map(|(_, value)| *value);
+
+    let weekly = match get("FREQ")? {
+        "DAILY" => false,
+        "WEEKLY" => true,
+        _ => return None,
+    };
+    if parts.iter().any(|(name, _)| !["FREQ", "INTERVAL", "COUNT", "UNTIL", "BYDAY", "WKST"].contains(name)) {
+        return None;
+    }
+
+    let interval: i64 = get("INTERVAL").map_or(Some(1), |interval| interval.parse().ok())?.max(1);
+    let count: Option<usize> = get("COUNT").map(|count| count.parse().ok()).unwrap_or(None);
+    // UNTIL is inclusive, so a date on its own runs to the end of that day
+    let until = match get("UNTIL") {
+        Some(until) => match parse_time(&Property { name: "UNTIL".to_string(), params: Vec::new(), value: until.to_string() }, timezone)? {
+            (midnight, true) => {
+                let next_day = midnight.with_timezone(&timezone).date_naive() + Duration::days(1);
+                Some(timezone.from_local_datetime(&next_day.and_hms_opt(0, 0, 0)?).earliest()?.with_timezone(&Utc) - Duration::seconds(1))
+            }
+            (until, false) => Some(until),
+        },
+        None => None,
+    };
+    let days: Vec<Weekday> = match get("BYDAY") {
+        Some(days) => days.split(',').map(weekday).collect::<Option<Vec<Weekday>>>()?,
+        None => Vec::new(),
+    };
+
+    // Worked out in local time, so a 9am meeting stays at 9am across clock changes
+    let local_first = first.with_timezone(&timezone).naive_local();
+    let first_monday = local_first.date() - Duration::days(local_first.weekday().num_days_from_monday() as i64);
+    let mut starts = Vec::new();
+
+    let last_date = horizon.with_timezone(&timezone).date_naive();
+    for day in 0.. {
+        let date = local_first.date() + Duration::days(day);
+        if date > last_date {
+            break;
+        }
+        let occurs = if weekly {
+            let week = (date - first_monday).num_days() / 7;
+            week % interval == 0 && (if days.is_empty() { date.weekday() == local_first.weekday() } else { days.contains(&date.weekday()) })
+        } else {
+            day % interval == 0 && (days.is_empty() || days.contains(&date.weekday()))
+        };
+        if !occurs {
+            continue;
+        }
+
+        let Some(start) = timezone.from_local_datetime(&date.and_time(local_first.time())).earliest().map(|start| start.with_timezone(&Utc)) else {
+            continue;
+        };
+        if start > horizon || until.is_some_and(|until| start > until) || count.is_some_and(|count| starts.len() >= count) {
+            break;
+        }
+        starts.push(start);
+    }
+
+    Some(starts)
+}
+
+// Busy times from another calendar. Free, cancelled and past events are left out, as are our own bookings.
+pub fn import(text: &str, timezone: Tz, now: DateTime<Utc>) -> Result<ImportedCalendar, AppError> {
+    // Unfold continuation lines first
+    let mut lines: Vec<String> = Vec::new();
+    for line in text.lines() {
+        match (line.strip_prefix([' ', '\t']), lines.last_mut()) {
+            (Some(rest), Some(last)) => last.push_str(rest),
+            _ => lines.push(line.trim_end_matches('\r').to_string()),
+        }
+    }
+    if !lines.iter().any(|line| line.eq_ignore_ascii_case("BEGIN:VCALENDAR")) {
+        return Err(AppError::UserInput("that isn't an iCalendar (.ics) file".to_string()));
+    }
+
+    let horizon = now + Duration::days(IMPORT_HORIZON_DAYS);
+    let mut calendar = ImportedCalendar { events: Vec::new(), skipped: Vec::new() };
+    let mut event: Option<Vec<Property>> = None;
+    // Nested components like alarms have their own DTSTART we mustn't read
+    let mut depth = 0;
+
+    for line in &lines {
+        let Some(property) = Property::parse(line) else {
+            continue;
+        };
+
+        match (property.name.as_str(), property.value.to_uppercase().as_str()) {
+            ("BEGIN", "VEVENT") => {
+                event = Some(Vec::new());
+                depth = 0;
+            }
+            ("BEGIN", _) if event.is_some() => depth += 1,
+            ("END", "VEVENT") => {
+                if let Some(properties) = event.take() {
+                    read_event(&properties, timezone, now, horizon, &mut calendar);
+                }
+            }
+            ("END", _) if event.is_some() => depth -= 1,
+            _ => {
+                if let Some(properties) = event.as_mut().filter(|_| depth == 0) {
+                    properties.push(property);
+                }
+            }
+        }
+    }
+
+    Ok(calendar)
+}
+
+fn read_event(properties: &[Property], timezone: Tz, now: DateTime<Utc>, horizon: DateTime<Utc>, calendar: &mut ImportedCalendar) {
+    let get = |name: &str| properties.iter().find(|property| property.name == name);
+    let summary = get("SUMMARY").map(|property| unescape(&property.value)).unwrap_or_else(|| "Busy".to_string());
+    let uid = get("UID").map(|property| property.value.clone()).unwrap_or_default();
+    let mut skip = |reason: &str| calendar.skipped.push(format!("{}: {}", summary, reason));
+
+    if uid.ends_with(UID_SUFFIX) {
+        return;
+    }
+    if get("STATUS").is_some_and(|status| status.value.eq_ignore_ascii_case("CANCELLED"))
+        || get("TRANSP").is_some_and(|transp| transp.value.eq_ignore_ascii_case("TRANSPARENT"))
+    {
+        return;
+    }
+
+    let Some((starts_at, all_day)) = get("DTSTART").and_then(|start| parse_time(start, timezone)) else {
+        return skip("no start time we could read");
+    };
+    let length = match (get("DTEND").and_then(|end| parse_time(end, timezone)), get("DURATION").and_then(|duration| parse_duration(&duration.value))) {
+        (Some((ends_at, _)), _) => ends_at - starts_at,
+        (None, Some(duration)) => duration,
+        (None, None) if all_day => Duration::days(1),
+        (None, None) => Duration::zero(),
+    };
+    if length <= Duration::zero() {
+        return skip("it doesn't take up any time");
+    }
+
+    let starts = match get("RRULE") {
+        Some(rule) => match repeats(&rule.value, starts_at, timezone, horizon) {
+            Some(starts) => starts,
+            None => return skip(&format!("we can only follow daily and weekly repeats, not {}", rule.value)),
+        },
+        None => vec![starts_at],
+    };
+    // Single days taken out of a repeating event
+    let excluded: Vec<DateTime<Utc>> = properties
+        .iter()
+        .filter(|property| property.name == "EXDATE")
+        .flat_map(|property| property.value.split(',').filter_map(|value| {
+            let single = Property { name: property.name.clone(), params: property.params.clone(), value: value.to_string() };
+            parse_time(&single, timezone).map(|(at, _)| at)
+        }).collect::<Vec<_>>())
+        .collect();
+
+    for (index, start) in starts.into_iter().enumerate() {
+        if start + length <= now || excluded.contains(&start) {
+            continue;
+        }
+        calendar.events.push(CalendarEvent {
+            uid: if index == 0 { uid.clone() } else { format!("{}#{}", uid, index) },
+            summary: summary.clone(),
+            description: get("DESCRIPTION").map(|property| unescape(&property.value)),
+            starts_at: start,
+            ends_at: start + length,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono_tz::Europe::London;
+
+    fn utc(text: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(text).unwrap().with_timezone(&Utc)
+    }
+
+    fn local_dates(starts: &[DateTime<Utc>]) -> Vec<String> {
+        starts.iter().map(|start| start.with_timezone(&London).format("%a %d %H:%M").to_string()).collect()
+    }
+
+    // Monday 12 October 2026, 09:00 in London
+    const FIRST: &str = "2026-10-12T08:00:00Z";
+
+    #[test]
+    fn daily_repeats_keep_to_their_days() {
+        let starts = repeats("FREQ=DAILY;BYDAY=MO,TU,WE,TH,FR", utc(FIRST), London, utc("2026-10-25T12:00:00Z")).unwrap();
+        assert_eq!(starts.len(), 10);
+        assert!(local_dates(&starts).iter().all(|start| !start.starts_with("Sat") && !start.starts_with("Sun")));
+
+        let every_other = repeats("FREQ=DAILY;INTERVAL=2;COUNT=3", utc(FIRST), London, utc("2026-10-25T12:00:00Z")).unwrap();
+        assert_eq!(local_dates(&every_other), ["Mon 12 09:00", "Wed 14 09:00", "Fri 16 09:00"]);
+    }
+
+    #[test]
+    fn weekly_repeats() {
+        let starts = repeats("FREQ=WEEKLY;INTERVAL=2;BYDAY=TU,TH;COUNT=4", utc("2026-10-13T08:00:00Z"), London, utc("2026-12-01T00:00:00Z")).unwrap();
+        assert_eq!(local_dates(&starts), ["Tue 13 09:00", "Thu 15 09:00", "Tue 27 09:00", "Thu 29 09:00"]);
+
+        let same_day = repeats("FREQ=WEEKLY", utc(FIRST), London, utc("2026-10-27T00:00:00Z")).unwrap();
+        assert_eq!(local_dates(&same_day), ["Mon 12 09:00", "Mon 19 09:00", "Mon 26 09:00"]);
+    }
+
+    #[test]
+    fn until_is_inclusive() {
+        let date_only = repeats("FREQ=DAILY;UNTIL=20261015", utc(FIRST), London, utc("2026-10-25T12:00:00Z")).unwrap();
+        assert_eq!(local_dates(&date_only).last().unwrap(), "Thu 15 09:00");
+        assert_eq!(date_only.len(), 4);
+
+        let exact = repeats("FREQ=DAILY;UNTIL=20261014T080000Z", utc(FIRST), London, utc("2026-10-25T12:00:00Z")).unwrap();
+        assert_eq!(exact.len(), 3);
+    }
+
+    #[test]
+    fn repeats_stay_at_the_same_local_time_across_clock_changes() {
+        // The clocks go back on Sunday 25 October 2026
+        let starts = repeats("FREQ=WEEKLY;COUNT=2", utc("2026-10-19T08:00:00Z"), London, utc("2026-12-01T00:00:00Z")).unwrap();
+        assert_eq!(starts, [utc("2026-10-19T08:00:00Z"), utc("2026-10-26T09:00:00Z")]);
+    }
+
+    #[test]
+    fn rules_we_cant_follow() {
+        assert!(repeats("FREQ=MONTHLY", utc(FIRST), London, utc("2027-01-01T00:00:00Z")).is_none());
+        assert!(repeats("FREQ=WEEKLY;BYMONTHDAY=1", utc(FIRST), London, utc("2027-01-01T00:00:00Z")).is_none());
+        assert!(repeats("FREQ=WEEKLY;BYDAY=XX", utc(FIRST), London, utc("2027-01-01T00:00:00Z")).is_none());
+    }
+
+    const CALENDAR: &str = "BEGIN:VCALENDAR\r
+VERSION:2.0\r
+BEGIN:VEVENT\r
+UID:dentist\r
+SUMMARY:Dentist\\, then lunch\r
+DESCRIPTION:A long description that carries on\r
+  onto the next line\r
+DTSTART;TZID=Europe/London:20261014T100000\r
+DTEND;TZID=Europe/London:20261014T113000\r
+BEGIN:VALARM\r
+DTSTART:20261001T000000Z\r
+END:VALARM\r
+END:VEVENT\r
+BEGIN:VEVENT\r
+UID:holiday\r
+SUMMARY:Holiday\r
+DTSTART;VALUE=DATE:20261020\r
+END:VEVENT\r
+BEGIN:VEVENT\r
+UID:standup\r
+SUMMARY:Standup\r
+DTSTART:20261012T080000Z\r
+DURATION:PT15M\r
+RRULE:FREQ=DAILY;COUNT=3\r
+EXDATE:20261013T080000Z\r
+END:VEVENT\r
+BEGIN:VEVENT\r
+UID:past\r
+SUMMARY:Last week\r
+DTSTART:20261005T080000Z\r
+DTEND:20261005T090000Z\r
+END:VEVENT\r
+BEGIN:VEVENT\r
+UID:cancelled\r
+STATUS:CANCELLED\r
+DTSTART:20261015T080000Z\r
+DTEND:20261015T090000Z\r
+END:VEVENT\r
+BEGIN:VEVENT\r
+UID:free\r
+TRANSP:TRANSPARENT\r
+DTSTART:20261015T080000Z\r
+DTEND:20261015T090000Z\r
+END:VEVENT\r
+BEGIN:VEVENT\r
+UID:1234@prompt-generator\r
+DTSTART:20261015T080000Z\r
+DTEND:20261015T090000Z\r
+END:VEVENT\r
+BEGIN:VEVENT\r
+UID:monthly\r
+SUMMARY:Board meeting\r
+DTSTART:20261015T080000Z\r
+DTEND:20261015T090000Z\r
+RRULE:FREQ=MONTHLY\r
+END:VEVENT\r
+END:VCALENDAR\r
+";
+
+    #[test]
+    fn imports_busy_times() {
+        let calendar = import(CALENDAR, London, utc(FIRST)).unwrap();
+        let events: Vec<(&str, DateTime<Utc>, DateTime<Utc>)> = calendar.events
+            .iter()
+            .map(|event| (event.uid.as_str(), event.starts_at, event.ends_at))
+            .collect();
+
+        assert_eq!(events, [
+            ("dentist", utc("2026-10-14T09:00:00Z"), utc("2026-10-14T10:30:00Z")),
+            ("holiday", utc("2026-10-19T23:00:00Z"), utc("2026-10-20T23:00:00Z")),
+            ("standup", utc("2026-10-12T08:00:00Z"), utc("2026-10-12T08:15:00Z")),
+            ("standup#2", utc("2026-10-14T08:00:00Z"), utc("2026-10-14T08:15:00Z")),
+        ]);
+        assert_eq!(calendar.events[0].summary, "Dentist, then lunch");
+        assert_eq!(calendar.events[0].description.as_deref(), Some("A long description that carries on onto the next line"));
+        assert_eq!(calendar.skipped, ["Board meeting: we can only follow daily and weekly repeats, not FREQ=MONTHLY"]);
+    }
+
+    #[test]
+    fn rejects_other_files() {
+        assert!(import("Subject,Start Date\nDentist,2026-10-14\n", London, utc(FIRST)).is_err());
+    }
+}
@@ -0,0 +1,305 @@
+use serde_derive::{Serialize, Deserialize};
+use std::path::Path;
+
+use crate::error::AppError;
+use crate::read_input;
+
+pub const DEFAULT_CURRENCY: &str = "GBP";
+
+pub fn currency_symbol(currency: &str) -> Option<&'static str> {
+    match currency {
+        "GBP" => Some("£"),
+        "USD" | "AUD" | "CAD" | "NZD" => Some("$"),
+        "EUR" => Some("€"),
+        _ => None,
+    }
+}
+
+pub fn format_price(amount: f64, currency: &str) -> String {
+    match currency_symbol(currency) {
+        Some(symbol) => format!("{}{:.2}", symbol, amount),
+        None => format!("{:.2} {}", amount, currency),
+    }
+}
+
+fn parse_price(text: &str) -> Result<Option<f64>, AppError> {
+    let text = text.trim().trim_start_matches(['£', '$', '€']);
+    if text.is_empty() {
+        return Ok(None);
+    }
+
+    match text.replace(',', "").parse::<f64>() {
+        Ok(price) if price >= 0.0 => Ok(Some(price)),
+        _ => Err(AppError::UserInput(format!("'{}' should be a price like 25 or 25.50", text))),
+    }
+}
+
+fn parse_minutes(text: &str) -> Result<Option<u32>, AppError> {
+    let text = text.trim().trim_end_matches("min").trim();
+    if text.is_empty() {
+        return Ok(None);
+    }
+
+    text.parse::<u32>()
+        .map(Some)
+        .map_err(|_| AppError::UserInput(format!("'{}' should be a number of minutes", text)))
+}
+
+// Asks until the answer parses
+fn ask<T>(question: &str, parse: impl Fn(&str) -> Result<T, AppError>) -> Result<T, AppError> {
+    loop {
+        println!("{}", question);
+        match parse(&read_input()?) {
+            Ok(value) => return Ok(value),
+            Err(e) => println!("{}", e),
+        }
+    }
+}
+
+// A size, length or tier of a service, anything it leaves out is the same as the service itself
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Variant {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub price: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub duration_minutes: Option<u32>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CatalogItem {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    // No price means it varies or is on request
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub price: Option<f64>,
+    pub currency: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub duration_minutes: Option<u32>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub variants: Vec<Variant>,
+}
+
+impl CatalogItem {
+    // Every price it can be sold at, its own and its variants'
+    pub fn prices(&self) -> Vec<f64> {
+        self.price
+            .into_iter()
+            .chain(self.variants.iter().filter_map(|variant| variant.price.or(self.price)))
+            .collect()
+    }
+
+    // How long the service, or the given variant of it, takes
+    pub fn duration_minutes(&self, variant: Option<&str>) -> Result<Option<u32>, AppError> {
+        match variant {
+            Some(name) => self.variants
+                .iter()
+                .find(|variant| variant.name.eq_ignore_ascii_case(name))
+                .map(|variant| variant.duration_minutes.or(self.duration_minutes))
+                .ok_or_else(|| AppError::UserInput(format!("{} has no '{}' option", self.name, name))),
+            None => Ok(self.duration_minutes),
+        }
+    }
+}
+
+// One line of an imported CSV, a line with a variant adds it to the service of the same name
+#[derive(Debug, Deserialize)]
+struct CsvRow {
+    name: String,
+    #[serde(default)]
+    description: Option<String>,
+    #[serde(default)]
+    price: Option<String>,
+    #[serde(default)]
+    currency: Option<String>,
+    #[serde(default)]
+    duration_minutes: Option<String>,
+    #[serde(default)]
+    variant: Option<String>,
+}
+
+fn non_empty(text: Option<String>) -> Option<String> {
+    text.map(|text| text.trim().to_string()).filter(|text| !text.is_empty())
+}
+
+// The services and products a business sells, kept in catalog.json in its data directory
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Catalog {
+    pub items: Vec<CatalogItem>,
+}
+
+impl Catalog {
+    // Columns: name, description, price, currency, duration_minutes, variant. Only name is required.
+    pub fn import_csv(path: &Path) -> Result<Self, AppError> {
+        let invalid = |e: &dyn std::fmt::Display| AppError::UserInput(format!("{}: {}", path.display(), e));
+
+        let mut reader = csv::ReaderBuilder::new()
+            .trim(csv::Trim::All)
+            .from_path(path)
+            .map_err(|e| invalid(&e))?;
+
+        let mut catalog = Catalog::default();
+        for (line, row) in reader.deserialize::<CsvRow>().enumerate() {
+            let row = row.map_err(|e| invalid(&e))?;
+            // The header is line 1
+            let at_line = |e: AppError| invalid(&format!("line {}: {}", line + 2, e));
+
+            let price = parse_price(row.price.as_deref().unwrap_or_default()).map_err(at_line)?;
+            let duration_minutes = parse_minutes(row.duration_minutes.as_deref().unwrap_or_default()).map_err(at_line)?;
+            let currency = non_empty(row.currency).map(|currency| currency.to_uppercase());
+
+            let index = match catalog.items.iter().position(|item| item.name.eq_ignore_ascii_case(&row.name)) {
+                Some(index) => index,
+                None => {
+                    catalog.items.push(CatalogItem {
+                        name: row.name.clone(),
+                        description: None,
+                        price: None,
+                        currency: currency.clone().unwrap_or_else(|| DEFAULT_CURRENCY.to_string()),
+                        duration_minutes: None,
+                        variants: Vec::new(),
+                    });
+                    catalog.items.len() - 1
+                }
+            };
+            let item = &mut catalog.items[index];
+
+            if let Some(description) = non_empty(row.description) {
+                item.description = Some(description);
+            }
+
+            match non_empty(row.variant) {
+                Some(variant) => item.variants.push(Variant { name: variant, price, duration_minutes }),
+                None => {
+                    item.price = price;
+                    item.duration_minutes = duration_minutes;
+                    if let Some(currency) = currency {
+                        item.currency = currency;
+                    }
+                }
+            }
+        }
+
+        Ok(catalog)
+    }
+
+    // Services found on the business's website are offered first, the manager can take them or start over
+    pub fn collect(imported: Option<Catalog>) -> Result<Self, AppError> {
+        if let Some(imported) = imported.filter(|imported| !imported.items.is_empty()) {
+            println!("We found these {} services on your website:\n{}", imported.items.len(), imported.prompt_section());
+            println!("Use them? (Y/n)");
+            if !read_input()?.eq_ignore_ascii_case("n") {
+                return Ok(imported);
+            }
+        }
+
+        println!("Import your services and prices from a CSV file? Enter its path, or leave blank to enter them here:");
+        loop {
+            let csv_path = read_input()?;
+            if csv_path.is_empty() {
+                break;
+            }
+
+            match Catalog::import_csv(Path::new(&csv_path)) {
+                Ok(catalog) => {
+                    println!("Imported {} services.", catalog.items.len());
+                    return Ok(catalog);
+                }
+                Err(e) => println!("{}\nFix the file and enter its path again, or leave blank to enter them here:", e),
+            }
+        }
+
+        println!("Which currency are your prices in? (e.g. GBP, USD, EUR, leave blank for {})", DEFAULT_CURRENCY);
+        let currency = match read_input()?.to_uppercase() {
+            currency if currency.is_empty() => DEFAULT_CURRENCY.to_string(),
+            currency => currency,
+        };
+
+        let mut catalog = Catalog::default();
+        loop {
+            println!("Service or product name (leave blank to finish):");
+            let name = read_input()?;
+            if name.is_empty() {
+                return Ok(catalog);
+            }
+
+            println!("Short description (optional):");
+            let description = read_input()?;
+            let price = ask("Price (e.g. 25 or 25.50, leave blank if it varies or is on request):", parse_price)?;
+            let duration_minutes = ask("How long does it take, in minutes? (optional)", parse_minutes)?;
+
+            let mut variants = Vec::new();
+            println!("Variants of {}, one per line as \"name, price, minutes\" (e.g. \"Long hair, 35, 45\"), leave a blank line to finish:", name);
+            loop {
+                let answer = read_input()?;
+                if answer.is_empty() {
+                    break;
+                }
+
+                let mut parts = answer.split(',');
+                let variant = (|| {
+                    Ok::<Variant, AppError>(Variant {
+                        name: parts.next().unwrap_or_default().trim().to_string(),
+                        price: parse_price(parts.next().unwrap_or_default())?,
+                        duration_minutes: parse_minutes(parts.next().unwrap_or_default())?,
+                    })
+                })();
+
+                match variant {
+                    Ok(variant) if !variant.name.is_empty() => variants.push(variant),
+                    Ok(_) => println!("A variant needs a name."),
+                    Err(e) => println!("{}", e),
+                }
+            }
+
+            catalog.items.push(CatalogItem {
+                name,
+                description: (!description.is_empty()).then_some(description),
+                price,
+                currency: currency.clone(),
+                duration_minutes,
+                variants,
+            });
+        }
+    }
+
+    pub fn find(&self, name: &str) -> Option<&CatalogItem> {
+        self.items.iter().find(|item| item.name.eq_ignore_ascii_case(name.trim()))
+    }
+
+    // A compact table for the system prompt, empty when the business has no catalog
+    pub fn prompt_section(&self) -> String {
+        if self.items.is_empty() {
+            return String::new();
+        }
+
+        let price = |price: Option<f64>, currency: &str| match price {
+            Some(price) => format_price(price, currency),
+            None => "on request".to_string(),
+        };
+        let duration = |minutes: Option<u32>| minutes.map(|minutes| format!("{} min", minutes)).unwrap_or_default();
+
+        let mut table = vec![
+            "| Service | Price | Duration | Details |".to_string(),
+            "|---|---|---|---|".to_string(),
+        ];
+        for item in &self.items {
+            table.push(format!(
+                "| {} | {} | {} | {} |",
+                item.name, price(item.price, &item.currency), duration(item.duration_minutes), item.description.as_deref().unwrap_or_default()
+            ));
+
+            for variant in &item.variants {
+                table.push(format!(
+                    "| {} ({}) | {} | {} | |",
+                    item.name, variant.name,
+                    price(variant.price.or(item.price), &item.currency),
+                    duration(variant.duration_minutes.or(item.duration_minutes))
+                ));
+            }
+        }
+
+        table.join("\n")
+    }
+}
@@ -0,0 +1,106 @@
+use async_openai::types::{ChatCompletionRequestMessage, ChatCompletionRequestMessageArgs, Role};
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
+use serde_derive::{Serialize, Deserialize};
+
+use crate::error::AppError;
+use crate::read_input;
+
+// Starts the system message carrying the current time, so each turn can find and replace it
+const CLOCK_MARKER: &str = "[CURRENT TIME]";
+
+// How the timezone is kept in clock.json, by its IANA name
+#[derive(Serialize, Deserialize)]
+struct SavedClock {
+    timezone: String,
+}
+
+impl TryFrom<SavedClock> for BusinessClock {
+    type Error = AppError;
+
+    fn try_from(saved: SavedClock) -> Result<Self, AppError> {
+        BusinessClock::parse(&saved.timezone)
+    }
+}
+
+impl From<BusinessClock> for SavedClock {
+    fn from(clock: BusinessClock) -> Self {
+        SavedClock { timezone: clock.timezone.name().to_string() }
+    }
+}
+
+// The business's local time, in the IANA timezone it was set up with. Kept in clock.json in its data directory,
+// the one place the chat, bookings and reminders all take it from.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(try_from = "SavedClock", into = "SavedClock")]
+pub struct BusinessClock {
+    pub timezone: Tz,
+}
+
+impl BusinessClock {
+    pub fn collect() -> Result<Self, AppError> {
+        loop {
+            println!("Which timezone is your business in? (e.g. \"Europe/London\", leave blank for the default):");
+            let timezone = read_input()?;
+            if timezone.is_empty() {
+                return BusinessClock::default_from_env();
+            }
+
+            match BusinessClock::parse(&timezone) {
+                Ok(clock) => return Ok(clock),
+                Err(e) => println!("{}", e),
+            }
+        }
+    }
+
+    pub fn parse(timezone: &str) -> Result<Self, AppError> {
+        timezone
+            .parse::<Tz>()
+            .map(|timezone| BusinessClock { timezone })
+            .map_err(|_| AppError::UserInput(format!("'{}' is not an IANA timezone such as Europe/London", timezone)))
+    }
+
+    // DEFAULT_TIMEZONE for businesses that didn't give one, UTC if that isn't set either
+    pub fn default_from_env() -> Result<Self, AppError> {
+        let timezone = std::env::var("DEFAULT_TIMEZONE").unwrap_or_else(|_| "UTC".to_string());
+        BusinessClock::parse(&timezone).map_err(|e| AppError::Config(format!("DEFAULT_TIMEZONE: {}", e)))
+    }
+
+    pub fn now(&self) -> DateTime<Tz> {
+        Utc::now().with_timezone(&self.timezone)
+    }
+
+    // Spelled out in full, models get the weekday of an ISO timestamp wrong surprisingly often
+    pub fn describe(&self, at: DateTime<Tz>) -> String {
+        format!(
+            "{} ({} time, UTC{})",
+            at.format("%A %-d %B %Y, %H:%M"), self.timezone.name(), at.format("%:z")
+        )
+    }
+
+    // Puts the time of this turn right after the system prompt, replacing the previous turn's. Anything else
+    // that depends on the time, like whether the business is open, goes in the same message.
+    pub fn apply(&self, conversation: &mut Vec<ChatCompletionRequestMessage>, at: DateTime<Tz>, status: &str) -> Result<(), AppError> {
+        let mut content = format!("{} It is currently {} where the business is.", CLOCK_MARKER, self.describe(at));
+        if !status.is_empty() {
+            content.push_str(&format!(" {}", status));
+        }
+
+        let message = ChatCompletionRequestMessageArgs::default()
+            .role(Role::System)
+            .content(content)
+            .build()?;
+
+        let existing = conversation.iter().position(|message| {
+            message.role == Role::System
+                && message.content.as_deref().is_some_and(|content| content.starts_with(CLOCK_MARKER))
+        });
+
+        match existing {
+            Some(index) => conversation[index] = message,
+            None => conversation.insert(1.min(conversation.len()), message),
+        }
+
+        Ok(())
+    }
+}
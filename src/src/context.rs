@@ -0,0 +1,281 @@
+use async_openai::types::{ChatCompletionRequestMessage, Role};
+use std::collections::HashMap;
+use tiktoken_rs::CoreBPE;
+
+use crate::error::AppError;
+
+// Every chat message carries a few tokens of framing (role, separators) on top of its content,
+// and the reply itself is primed with a few more.
+const TOKENS_PER_MESSAGE: usize = 4;
+const TOKENS_PER_REPLY: usize = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TruncationStrategy {
+    // Drop the oldest unpinned message, one at a time, until the conversation fits. A function call and its result
+    // count as one.
+    DropOldest,
+    // Drop the oldest user message together with every reply that followed it, so we never send half a turn
+    DropOldestTurns,
+}
+
+impl TruncationStrategy {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.trim().to_lowercase().as_str() {
+            "drop_oldest" => Some(TruncationStrategy::DropOldest),
+            "drop_oldest_turns" => Some(TruncationStrategy::DropOldestTurns),
+            _ => None,
+        }
+    }
+}
+
+pub struct ContextWindow {
+    bpe: CoreBPE,
+    budgets: HashMap<String, usize>,
+    reply_tokens: usize,
+    strategy: TruncationStrategy,
+}
+
+impl ContextWindow {
+    pub fn new(reply_tokens: u16, strategy: TruncationStrategy) -> Result<Self, AppError> {
+        Ok(ContextWindow {
+            bpe: tiktoken_rs::cl100k_base().map_err(|e| AppError::Config(e.to_string()))?,
+            budgets: HashMap::new(),
+            reply_tokens: reply_tokens as usize,
+            strategy,
+        })
+    }
+
+    // Reads overrides from the environment, e.g.
+    // CONTEXT_BUDGETS="gpt-3.5-turbo=4096,gpt-4=8192" and CONTEXT_TRUNCATION="drop_oldest"
+    pub fn from_env(reply_tokens: u16) -> Result<Self, AppError> {
+        let strategy = std::env::var("CONTEXT_TRUNCATION")
+            .ok()
+            .and_then(|name| TruncationStrategy::from_name(&name))
+            .unwrap_or(TruncationStrategy::DropOldestTurns);
+
+        let mut window = ContextWindow::new(reply_tokens, strategy)?;
+
+        if let Ok(budgets) = std::env::var("CONTEXT_BUDGETS") {
+            for entry in budgets.split(',') {
+                if let Some((model, tokens)) = entry.split_once('=') {
+                    let tokens = tokens.trim().parse().map_err(|_| {
+                        AppError::Config(format!("CONTEXT_BUDGETS has an invalid token count for {}", model.trim()))
+                    })?;
+                    window = window.with_budget(model.trim(), tokens);
+                }
+            }
+        }
+
+        Ok(window)
+    }
+
+    pub fn with_budget(mut self, model: &str, tokens: usize) -> Self {
+        self.budgets.insert(model.to_string(), tokens);
+        self
+    }
+
+    // Total context size for a model, either configured or the model's published limit
+    pub fn budget_for(&self, model: &str) -> usize {
+        self.budgets
+            .get(model)
+            .copied()
+            .unwrap_or_else(|| tiktoken_rs::model::get_context_size(model))
+    }
+
+    pub fn count_text(&self, text: &str) -> usize {
+        self.bpe.encode_with_special_tokens(text).len()
+    }
+
+    pub fn count_message(&self, message: &ChatCompletionRequestMessage) -> usize {
+        let mut tokens = TOKENS_PER_MESSAGE;
+
+        if let Some(content) = &message.content {
+            tokens += self.count_text(content);
+        }
+        if let Some(name) = &message.name {
+            tokens += self.count_text(name);
+        }
+        if let Some(function_call) = &message.function_call {
+            tokens += self.count_text(&function_call.name) + self.count_text(&function_call.arguments);
+        }
+
+        tokens
+    }
+
+    pub fn count_messages(&self, messages: &[ChatCompletionRequestMessage]) -> usize {
+        messages.iter().map(|message| self.count_message(message)).sum::<usize>() + TOKENS_PER_REPLY
+    }
+
+    // System messages hold the generated prompt and [TRAINDATA] directives, those are never dropped
+    pub fn is_pinned(message: &ChatCompletionRequestMessage) -> bool {
+        message.role == Role::System
+    }
+
+    // Tokens available for the request itself once the reply has been reserved
+    pub fn limit_for(&self, model: &str) -> usize {
+        self.budget_for(model).saturating_sub(self.reply_tokens)
+    }
+
+    // Trims the conversation in place so that it plus the reply fits the model's budget.
+    // Returns the dropped messages, oldest first.
+    pub fn fit(&self, model: &str, conversation: &mut Vec<ChatCompletionRequestMessage>) -> Vec<ChatCompletionRequestMessage> {
+        self.fit_within(self.limit_for(model), conversation)
+    }
+
+    pub fn fit_within(&self, limit: usize, conversation: &mut Vec<ChatCompletionRequestMessage>) -> Vec<ChatCompletionRequestMessage> {
+        let mut dropped = Vec::new();
+
+        while self.count_messages(conversation) > limit {
+            let Some(oldest) = conversation.iter().position(|message| !Self::is_pinned(message)) else {
+                break;
+            };
+
+            let end = match self.strategy {
+                // A function call goes with the results after it, the API rejects a result whose call is missing
+                TruncationStrategy::DropOldest => oldest + 1 + conversation[oldest + 1..]
+                    .iter()
+                    .take_while(|message| message.role == Role::Function)
+                    .count(),
+                TruncationStrategy::DropOldestTurns => conversation[oldest + 1..]
+                    .iter()
+                    .position(|message| message.role == Role::User)
+                    .map(|next_user| oldest + 1 + next_user)
+                    .unwrap_or(conversation.len()),
+            };
+
+            // Never drop the message we are about to reply to, or a call while keeping its result
+            let end = end.min(conversation.len() - 1);
+            if end <= oldest || conversation[end].role == Role::Function {
+                break;
+            }
+
+            // Pinned messages inside the dropped range are kept where they are
+            let mut index = oldest;
+            for _ in oldest..end {
+                if Self::is_pinned(&conversation[index]) {
+                    index += 1;
+                } else {
+                    dropped.push(conversation.remove(index));
+                }
+            }
+        }
+
+        dropped
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_openai::types::{ChatCompletionRequestMessageArgs, FunctionCall};
+
+    fn message(role: Role, content: &str) -> ChatCompletionRequestMessage {
+        ChatCompletionRequestMessageArgs::default().role(role).content(content).build().unwrap()
+    }
+
+    fn call(name: &str) -> ChatCompletionRequestMessage {
+        ChatCompletionRequestMessageArgs::default()
+            .role(Role::Assistant)
+            .function_call(FunctionCall { name: name.to_string(), arguments: "{}".to_string() })
+            .build()
+            .unwrap()
+    }
+
+    fn result(name: &str, content: &str) -> ChatCompletionRequestMessage {
+        ChatCompletionRequestMessageArgs::default().role(Role::Function).name(name).content(content).build().unwrap()
+    }
+
+    fn window(strategy: TruncationStrategy) -> ContextWindow {
+        ContextWindow::new(512, strategy).unwrap()
+    }
+
+    // What's left, as the content (or called function) of each message
+    fn contents(conversation: &[ChatCompletionRequestMessage]) -> Vec<String> {
+        conversation
+            .iter()
+            .map(|message| message.content.clone().or_else(|| message.function_call.as_ref().map(|call| call.name.clone())).unwrap_or_default())
+            .collect()
+    }
+
+    fn chat() -> Vec<ChatCompletionRequestMessage> {
+        vec![
+            message(Role::System, "You are the salon's assistant."),
+            message(Role::User, "Do you cut children's hair?"),
+            message(Role::Assistant, "Yes, for under 12s it's a shorter appointment."),
+            message(Role::User, "Are you open on Sunday?"),
+            message(Role::Assistant, "No, we're closed on Sundays."),
+            message(Role::User, "What about Saturday?"),
+        ]
+    }
+
+    #[test]
+    fn drop_oldest_drops_one_message_at_a_time_until_it_fits() {
+        let window = window(TruncationStrategy::DropOldest);
+        let mut conversation = chat();
+        let limit = window.count_messages(&conversation) - window.count_message(&conversation[1]);
+
+        let dropped = window.fit_within(limit, &mut conversation);
+        assert_eq!(contents(&dropped), vec!["Do you cut children's hair?"]);
+        assert_eq!(conversation.len(), 5);
+        assert!(window.count_messages(&conversation) <= limit);
+
+        // Already fitting changes nothing
+        assert!(window.fit_within(limit, &mut conversation).is_empty());
+    }
+
+    #[test]
+    fn drop_oldest_turns_drops_a_question_with_its_answers() {
+        let window = window(TruncationStrategy::DropOldestTurns);
+        let mut conversation = chat();
+        let limit = window.count_messages(&conversation) - window.count_message(&conversation[1]);
+
+        let dropped = window.fit_within(limit, &mut conversation);
+        assert_eq!(contents(&dropped), vec!["Do you cut children's hair?", "Yes, for under 12s it's a shorter appointment."]);
+        assert_eq!(contents(&conversation), vec![
+            "You are the salon's assistant.", "Are you open on Sunday?", "No, we're closed on Sundays.", "What about Saturday?",
+        ]);
+    }
+
+    #[test]
+    fn pinned_messages_and_the_latest_message_are_never_dropped() {
+        for strategy in [TruncationStrategy::DropOldest, TruncationStrategy::DropOldestTurns] {
+            let window = window(strategy);
+            let mut conversation = chat();
+            conversation.insert(3, message(Role::System, "[TRAINDATA] Always mention the loyalty card."));
+
+            window.fit_within(0, &mut conversation);
+            assert_eq!(contents(&conversation), vec![
+                "You are the salon's assistant.", "[TRAINDATA] Always mention the loyalty card.", "What about Saturday?",
+            ], "{:?}", strategy);
+        }
+    }
+
+    #[test]
+    fn function_calls_are_dropped_together_with_their_results() {
+        let window = window(TruncationStrategy::DropOldest);
+        let mut conversation = vec![
+            message(Role::System, "You are the salon's assistant."),
+            message(Role::User, "How much is a haircut?"),
+            call("look_up_price"),
+            result("look_up_price", r#"{"price": "£25.00"}"#),
+            message(Role::Assistant, "A haircut is £25."),
+            message(Role::User, "Can I book one for Saturday?"),
+        ];
+        // Room for everything but the question and the call, which would leave the result on its own
+        let limit = window.count_messages(&conversation) - window.count_message(&conversation[1]) - window.count_message(&conversation[2]);
+
+        let dropped = window.fit_within(limit, &mut conversation);
+        assert_eq!(contents(&dropped), vec!["How much is a haircut?", "look_up_price", r#"{"price": "£25.00"}"#]);
+        assert!(conversation.iter().all(|message| message.role != Role::Function));
+        assert_eq!(contents(&conversation), vec!["You are the salon's assistant.", "A haircut is £25.", "Can I book one for Saturday?"]);
+
+        // A call whose result is the latest message can't go without it, so both stay
+        let mut conversation = vec![
+            message(Role::System, "You are the salon's assistant."),
+            call("look_up_price"),
+            result("look_up_price", r#"{"price": "£25.00"}"#),
+        ];
+        assert!(window.fit_within(0, &mut conversation).is_empty());
+        assert_eq!(conversation.len(), 3);
+    }
+}
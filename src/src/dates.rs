@@ -0,0 +1,604 @@
+use chrono::{DateTime, Datelike, Duration, Months, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Timelike};
+use chrono_tz::Tz;
+use regex::Regex;
+use serde_derive::{Serialize, Deserialize};
+
+use crate::error::AppError;
+
+const MONTHS: [&str; 12] = ["jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec"];
+
+// Number words customers use for times and offsets, "half two" and "in three days"
+const NUMBER_WORDS: [(&str, &str); 16] = [
+    ("one", "1"), ("two", "2"), ("three", "3"), ("four", "4"), ("five", "5"), ("six", "6"), ("seven", "7"), ("eight", "8"),
+    ("nine", "9"), ("ten", "10"), ("eleven", "11"), ("twelve", "12"), ("fifteen", "15"), ("twenty", "20"), ("thirty", "30"),
+    ("fifty", "50"),
+];
+
+// Lower means someone should check with the customer before acting on it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Confidence {
+    // Parts of the text couldn't be placed
+    Low,
+    // Resolved with a guess, like 3 meaning 3pm or "next Tuesday" meaning the one after this week's
+    Medium,
+    High,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TimeOfDay {
+    Any,
+    At(NaiveTime),
+    // Start inclusive, end exclusive, on every day of the range
+    Between(NaiveTime, NaiveTime),
+}
+
+// What a customer's "next Tuesday afternoon" means: the days it covers and the time of day on each of them
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DateRange {
+    pub from: NaiveDate,
+    // Inclusive
+    pub to: NaiveDate,
+    pub time: TimeOfDay,
+    // Whether a day was named, a bare "3pm" is just taken to be the next 3pm
+    pub dated: bool,
+    pub confidence: Confidence,
+}
+
+// Clocks going forward skip an hour, times inside it are moved on by that hour, so 02:30 becomes 03:30
+fn local(timezone: Tz, at: NaiveDateTime) -> DateTime<Tz> {
+    timezone
+        .from_local_datetime(&at)
+        .earliest()
+        .or_else(|| timezone.from_local_datetime(&(at + Duration::hours(1))).earliest())
+        .unwrap_or_else(|| timezone.from_utc_datetime(&at))
+}
+
+fn time(hour: u32, minute: u32) -> NaiveTime {
+    NaiveTime::from_hms_opt(hour, minute, 0).unwrap_or(NaiveTime::MIN)
+}
+
+impl DateRange {
+    pub fn start(&self, timezone: Tz) -> DateTime<Tz> {
+        let at = match self.time {
+            TimeOfDay::Any => NaiveTime::MIN,
+            TimeOfDay::At(at) | TimeOfDay::Between(at, _) => at,
+        };
+        local(timezone, self.from.and_time(at))
+    }
+
+    // Exclusive, except for an exact time where it's the same as the start
+    pub fn end(&self, timezone: Tz) -> DateTime<Tz> {
+        match self.time {
+            TimeOfDay::Any => local(timezone, (self.to + Duration::days(1)).and_time(NaiveTime::MIN)),
+            TimeOfDay::At(at) | TimeOfDay::Between(_, at) => local(timezone, self.to.and_time(at)),
+        }
+    }
+
+    pub fn days(&self) -> u32 {
+        (self.to - self.from).num_days().max(0) as u32 + 1
+    }
+
+    pub fn contains(&self, at: NaiveDateTime) -> bool {
+        let in_days = self.from <= at.date() && at.date() <= self.to;
+        in_days && match self.time {
+            TimeOfDay::Any => true,
+            TimeOfDay::At(time) => at.time() == time,
+            TimeOfDay::Between(start, end) => start <= at.time() && at.time() < end,
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        let days = if self.from == self.to {
+            self.from.format("%A %-d %B %Y").to_string()
+        } else {
+            format!("{} to {}", self.from.format("%A %-d %B"), self.to.format("%A %-d %B %Y"))
+        };
+
+        match self.time {
+            TimeOfDay::Any => format!("{}, any time", days),
+            TimeOfDay::At(at) => format!("{} at {}", days, at.format("%H:%M")),
+            TimeOfDay::Between(start, end) => format!("{}, between {} and {}", days, start.format("%H:%M"), end.format("%H:%M")),
+        }
+    }
+}
+
+// Blanks out the first match so later patterns can't read it again, handing back its groups ("" for the ones
+// that didn't take part)
+fn take(pattern: &Regex, rest: &mut String) -> Option<Vec<String>> {
+    let captures = pattern.captures(rest)?;
+    let groups = captures.iter().map(|group| group.map(|group| group.as_str().to_string()).unwrap_or_default()).collect();
+    let range = captures.get(0)?.range();
+    rest.replace_range(range, " ");
+    Some(groups)
+}
+
+fn number(text: &str) -> u32 {
+    text.parse().unwrap_or(0)
+}
+
+fn month_number(name: &str) -> u32 {
+    MONTHS.iter().position(|month| name.starts_with(month)).map_or(0, |index| index as u32 + 1)
+}
+
+fn weekday_index(name: &str) -> i64 {
+    ["mon", "tu", "we", "th", "fr", "sa", "su"].iter().position(|day| name.starts_with(day)).unwrap_or(0) as i64
+}
+
+// The first date on or after today with this month and day, when the customer didn't say which year
+fn next_date(today: NaiveDate, month: u32, day: u32) -> Option<NaiveDate> {
+    NaiveDate::from_ymd_opt(today.year(), month, day)
+        .filter(|date| *date >= today)
+        .or_else(|| NaiveDate::from_ymd_opt(today.year() + 1, month, day))
+}
+
+// "the 31st" in a month that doesn't have one means the next month that does
+fn next_day_of_month(today: NaiveDate, day: u32) -> Option<NaiveDate> {
+    (0..3).find_map(|ahead| {
+        let month = today.with_day(1)?.checked_add_months(Months::new(ahead))?;
+        month.with_day(day).filter(|date| *date >= today)
+    })
+}
+
+fn invalid_date(text: &str) -> AppError {
+    AppError::UserInput(format!("'{}' isn't a real date", text.trim()))
+}
+
+// Parts of the day, as the business's customers would mean them
+fn period(name: &str, modifier: &str) -> (NaiveTime, NaiveTime) {
+    let (start, end) = match name {
+        "morning" => (8 * 60, 12 * 60),
+        "lunchtime" | "lunch" => (12 * 60, 14 * 60),
+        "afternoon" => (12 * 60, 17 * 60),
+        "first thing" => (8 * 60, 10 * 60),
+        _ => (17 * 60, 22 * 60),
+    };
+    let middle = (start + end) / 2;
+    let (start, end) = match modifier {
+        "early" => (start, middle),
+        "late" => (middle, end),
+        _ => (start, end),
+    };
+    (time(start / 60, start % 60), time(end / 60, end % 60))
+}
+
+// Works out dates and times like "next Tuesday afternoon", "the 3rd at half two" or "in 2 weeks" against the
+// current time in the business's timezone, so booking doesn't depend on the model's date arithmetic
+pub struct DateParser {
+    datetime_separator: Regex,
+    iso_date: Regex,
+    numeric_date: Regex,
+    day_month: Regex,
+    month_day: Regex,
+    ordinal_day: Regex,
+    relative_day: Regex,
+    weekday: Regex,
+    weekend: Regex,
+    week_or_month: Regex,
+    offset: Regex,
+    period: Regex,
+    named_time: Regex,
+    clock_time: Regex,
+    am_pm: Regex,
+    past_to: Regex,
+    half_hour: Regex,
+    hour_minutes: Regex,
+    bare_hour: Regex,
+    digit: Regex,
+}
+
+impl DateParser {
+    pub fn new() -> Self {
+        let month = r"(jan|feb|mar|apr|may|jun|jul|aug|sep|oct|nov|dec)[a-z]*\.?";
+        let pattern = |pattern: &str| Regex::new(pattern).unwrap();
+
+        DateParser {
+            datetime_separator: pattern(r"(\d)t(\d)"),
+            iso_date: pattern(r"\b(\d{4})-(\d{1,2})-(\d{1,2})\b"),
+            numeric_date: pattern(r"\b(\d{1,2})/(\d{1,2})(?:/(\d{2}|\d{4}))?\b"),
+            day_month: pattern(&format!(r"\b(\d{{1,2}})(?:st|nd|rd|th)?(?: of)? {}(?:,? (\d{{4}}))?", month)),
+            month_day: pattern(&format!(r"\b{} (?:the )?(\d{{1,2}})(?:st|nd|rd|th)?\b(?:,? (\d{{4}}))?", month)),
+            ordinal_day: pattern(r"\b(\d{1,2})(?:st|nd|rd|th)\b"),
+            relative_day: pattern(r"\b(day after tomorrow|tomorrow|today|tonight|yesterday)\b"),
+            weekday: pattern(r"\b(?:(this|next|coming) )?(monday|tuesday|wednesday|thursday|friday|saturday|sunday|mon|tues?|wed|thurs?|thu|fri|sat|sun)\b"),
+            weekend: pattern(r"\b(?:(this|next) )?weekend\b"),
+            week_or_month: pattern(r"\b(this|next) (week|month)\b"),
+            offset: pattern(r"\bin (a|an|\d+) (minute|hour|day|week|fortnight|month)s?(?:'? time)?\b"),
+            period: pattern(r"\b(?:(early|late) )?(?:in the )?(morning|afternoon|evening|lunchtime|lunch|first thing)\b"),
+            named_time: pattern(r"\b(noon|midday)\b"),
+            clock_time: pattern(r"\b(\d{1,2})[:.](\d{2}) ?(am|pm)?\b"),
+            am_pm: pattern(r"\b(\d{1,2}) ?(am|pm)\b"),
+            past_to: pattern(r"\b(half|quarter|\d{1,2}) (past|to) (\d{1,2})\b"),
+            half_hour: pattern(r"\bhalf (\d{1,2})\b"),
+            hour_minutes: pattern(r"\b(\d{1,2}) (\d{2})\b|\b(\d{1,2}) o'?clock\b"),
+            bare_hour: pattern(r"\b(at|around|about|from|after|before|by|until|till) (\d{1,2})\b"),
+            digit: pattern(r"\d"),
+        }
+    }
+
+    fn normalise(&self, text: &str) -> String {
+        let text = text
+            .to_lowercase()
+            .replace(['’', '‘'], "'")
+            .replace("a.m.", "am")
+            .replace("p.m.", "pm")
+            .replace("forty-five", "45")
+            .replace("forty five", "45")
+            .replace(['?', '!', ',', ';', '(', ')', '"'], " ");
+        let text = self.datetime_separator.replace_all(&text, "$1 $2");
+
+        text.split_whitespace()
+            .map(|word| {
+                let word = word.trim_end_matches('.');
+                // "threeish", "3ish"
+                let stem = word.strip_suffix("ish").filter(|stem| !stem.is_empty()).unwrap_or(word);
+                match NUMBER_WORDS.iter().find(|(name, _)| *name == stem) {
+                    Some((_, digits)) => digits.to_string(),
+                    None if stem.chars().all(|c| c.is_ascii_digit()) => stem.to_string(),
+                    None => word.to_string(),
+                }
+            })
+            .collect::<Vec<String>>()
+            .join(" ")
+    }
+
+    pub fn parse(&self, text: &str, now: DateTime<Tz>) -> Result<DateRange, AppError> {
+        let mut rest = self.normalise(text);
+        let today = now.date_naive();
+        let weekday = today.weekday().num_days_from_monday() as i64;
+        let mut confidence = Confidence::High;
+
+        let mut days: Option<(NaiveDate, NaiveDate)> = None;
+        let mut exact: Option<NaiveTime> = None;
+        let mut tonight = false;
+        // "Saturday at 10" said on a Saturday afternoon means next Saturday
+        let mut rolls_over = false;
+
+        if let Some(groups) = take(&self.offset, &mut rest) {
+            let amount = if groups[1].starts_with('a') { 1 } else { number(&groups[1]) };
+            let at = match groups[2].as_str() {
+                "minute" => now + Duration::minutes(amount.into()),
+                "hour" => now + Duration::hours(amount.into()),
+                "day" => now + Duration::days(amount.into()),
+                "week" => now + Duration::weeks(amount.into()),
+                "fortnight" => now + Duration::weeks(2 * i64::from(amount)),
+                _ => now.checked_add_months(Months::new(amount)).ok_or_else(|| invalid_date(text))?,
+            };
+            days = Some((at.date_naive(), at.date_naive()));
+            if matches!(groups[2].as_str(), "minute" | "hour") {
+                exact = Some(time(at.hour(), at.minute()));
+            } else {
+                // "In two weeks" is rarely that exact day
+                confidence = Confidence::Medium;
+            }
+        } else if let Some(groups) = take(&self.iso_date, &mut rest) {
+            let date = NaiveDate::from_ymd_opt(groups[1].parse().unwrap_or(0), number(&groups[2]), number(&groups[3]));
+            let date = date.ok_or_else(|| invalid_date(&groups[0]))?;
+            days = Some((date, date));
+        } else if let Some(groups) = take(&self.numeric_date, &mut rest) {
+            // Day first, unless that can't be right
+            let (mut day, mut month) = (number(&groups[1]), number(&groups[2]));
+            if month > 12 && day <= 12 {
+                (day, month) = (month, day);
+            }
+            if day <= 12 && month <= 12 && day != month {
+                confidence = Confidence::Medium;
+            }
+            let date = match groups[3].len() {
+                0 => next_date(today, month, day),
+                2 => NaiveDate::from_ymd_opt(2000 + number(&groups[3]) as i32, month, day),
+                _ => NaiveDate::from_ymd_opt(number(&groups[3]) as i32, month, day),
+            };
+            let date = date.ok_or_else(|| invalid_date(&groups[0]))?;
+            days = Some((date, date));
+        } else if let Some(groups) = take(&self.day_month, &mut rest).or_else(|| {
+            // Same groups as day_month, in that order
+            take(&self.month_day, &mut rest).map(|groups| vec![groups[0].clone(), groups[2].clone(), groups[1].clone(), groups[3].clone()])
+        }) {
+            let (day, month) = (number(&groups[1]), month_number(&groups[2]));
+            let date = match groups[3].as_str() {
+                "" => next_date(today, month, day),
+                year => NaiveDate::from_ymd_opt(number(year) as i32, month, day),
+            };
+            let date = date.ok_or_else(|| invalid_date(&groups[0]))?;
+            days = Some((date, date));
+        } else if let Some(groups) = take(&self.relative_day, &mut rest) {
+            let date = match groups[1].as_str() {
+                "day after tomorrow" => today + Duration::days(2),
+                "tomorrow" => today + Duration::days(1),
+                "yesterday" => today - Duration::days(1),
+                "tonight" => {
+                    tonight = true;
+                    today
+                }
+                _ => today,
+            };
+            days = Some((date, date));
+        } else if let Some(groups) = take(&self.weekday, &mut rest) {
+            let mut ahead = (weekday_index(&groups[2]) - weekday + 7) % 7;
+            match groups[1].as_str() {
+                // Said on a Monday, "next Tuesday" usually means the one eight days away, said on a Saturday it
+                // means three days away
+                "next" => {
+                    if ahead == 0 || weekday + ahead <= 6 {
+                        ahead += 7;
+                    }
+                    confidence = Confidence::Medium;
+                }
+                "" if ahead == 0 => {
+                    confidence = Confidence::Medium;
+                    rolls_over = true;
+                }
+                _ => {}
+            }
+            let date = today + Duration::days(ahead);
+            days = Some((date, date));
+        } else if let Some(groups) = take(&self.weekend, &mut rest) {
+            let saturday = today + Duration::days((5 - weekday + 7) % 7);
+            days = Some(match (groups[1].as_str(), weekday) {
+                ("next", _) => {
+                    confidence = Confidence::Medium;
+                    let saturday = today + Duration::days(7 - weekday + 5);
+                    (saturday, saturday + Duration::days(1))
+                }
+                // Already Sunday, what's left of this weekend is today
+                (_, 6) => (today, today),
+                (_, 5) => (today, today + Duration::days(1)),
+                _ => (saturday, saturday + Duration::days(1)),
+            });
+        } else if let Some(groups) = take(&self.week_or_month, &mut rest) {
+            let first_of_month = today.with_day(1).unwrap_or(today);
+            let last_of = |first: NaiveDate| first.checked_add_months(Months::new(1)).map_or(first, |next| next - Duration::days(1));
+
+            days = Some(match (groups[1].as_str(), groups[2].as_str()) {
+                ("this", "week") => (today, today + Duration::days(6 - weekday)),
+                ("next", "week") => {
+                    let monday = today + Duration::days(7 - weekday);
+                    (monday, monday + Duration::days(6))
+                }
+                ("this", _) => (today, last_of(first_of_month)),
+                _ => {
+                    let first = first_of_month.checked_add_months(Months::new(1)).unwrap_or(first_of_month);
+                    (first, last_of(first))
+                }
+            });
+        } else if let Some(groups) = take(&self.ordinal_day, &mut rest) {
+            let date = next_day_of_month(today, number(&groups[1])).ok_or_else(|| invalid_date(&groups[0]))?;
+            days = Some((date, date));
+        }
+
+        // The part of the day also settles whether a bare 3 is morning or afternoon
+        let mut window = take(&self.period, &mut rest).map(|groups| period(&groups[2], &groups[1]));
+        if tonight && window.is_none() {
+            window = Some(period("evening", ""));
+        }
+        let afternoon = window.is_some_and(|(start, _)| start >= time(12, 0));
+
+        // Hour, minute and am/pm as said, before working out which 3 o'clock it is
+        let mut said: Option<(u32, u32, String)> = None;
+        let mut bound: Option<String> = None;
+        if take(&self.named_time, &mut rest).is_some() {
+            said = Some((12, 0, "pm".to_string()));
+        } else if let Some(groups) = take(&self.clock_time, &mut rest) {
+            said = Some((number(&groups[1]), number(&groups[2]), groups[3].clone()));
+        } else if let Some(groups) = take(&self.am_pm, &mut rest) {
+            said = Some((number(&groups[1]), 0, groups[2].clone()));
+        } else if let Some(groups) = take(&self.past_to, &mut rest) {
+            let minutes = match groups[1].as_str() {
+                "half" => 30,
+                "quarter" => 15,
+                minutes => number(minutes),
+            };
+            if minutes == 0 || minutes >= 60 {
+                return Err(AppError::UserInput(format!("'{}' isn't a time of day", text.trim())));
+            }
+            let hour = number(&groups[3]);
+            said = Some(match groups[2].as_str() {
+                "past" => (hour, minutes, String::new()),
+                // "Quarter to one" is 12:45
+                _ => (if hour <= 1 { hour + 11 } else { hour - 1 }, 60 - minutes, String::new()),
+            });
+        } else if let Some(groups) = take(&self.half_hour, &mut rest) {
+            // "Half two" is half past two
+            said = Some((number(&groups[1]), 30, String::new()));
+        } else if let Some(groups) = take(&self.hour_minutes, &mut rest) {
+            said = Some(match groups[3].as_str() {
+                "" => (number(&groups[1]), number(&groups[2]), String::new()),
+                hour => (number(hour), 0, String::new()),
+            });
+        } else if let Some(groups) = take(&self.bare_hour, &mut rest) {
+            said = Some((number(&groups[2]), 0, String::new()));
+            bound = Some(groups[1].clone());
+        }
+
+        if let Some((hour, minute, meridiem)) = said {
+            if hour > 23 || minute > 59 || (!meridiem.is_empty() && !(1..=12).contains(&hour)) {
+                return Err(AppError::UserInput(format!("'{}' isn't a time of day", text.trim())));
+            }
+            let hour = match meridiem.as_str() {
+                "am" => hour % 12,
+                "pm" => hour % 12 + 12,
+                // Nobody books a 3am appointment, but an 8 or 9 could be either
+                _ if hour >= 12 || hour == 0 => hour,
+                _ if window.is_some() => if afternoon { hour + 12 } else { hour },
+                _ => {
+                    confidence = confidence.min(Confidence::Medium);
+                    if hour <= 7 { hour + 12 } else { hour }
+                }
+            };
+            let at = time(hour, minute);
+
+            match bound.as_deref() {
+                Some("after" | "from") => window = Some((at, time(23, 59))),
+                Some("before" | "by" | "until" | "till") => window = Some((time(0, 0), at)),
+                _ => exact = exact.or(Some(at)),
+            }
+        }
+
+        let time_of_day = match (exact, window) {
+            (Some(at), _) => TimeOfDay::At(at),
+            (None, Some((start, end))) => TimeOfDay::Between(start, end),
+            (None, None) => TimeOfDay::Any,
+        };
+
+        let last = match time_of_day {
+            TimeOfDay::Between(_, end) => end,
+            TimeOfDay::At(at) => at,
+            TimeOfDay::Any => time(23, 59),
+        };
+
+        let dated = days.is_some();
+        let (from, to) = match days {
+            Some((from, to)) if rolls_over && last <= now.time() => (from + Duration::days(7), to + Duration::days(7)),
+            Some(days) => days,
+            None if time_of_day == TimeOfDay::Any => {
+                return Err(AppError::UserInput(format!("couldn't work out a date or time from '{}'", text.trim())));
+            }
+            // Just a time means today, unless that time has already gone
+            None => {
+                if last <= now.time() {
+                    confidence = confidence.min(Confidence::Medium);
+                    (today + Duration::days(1), today + Duration::days(1))
+                } else {
+                    (today, today)
+                }
+            }
+        };
+
+        // Numbers we couldn't place could have changed the meaning
+        if self.digit.is_match(&rest) {
+            confidence = Confidence::Low;
+        }
+
+        Ok(DateRange { from, to, time: time_of_day, dated, confidence })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono_tz::America::New_York;
+    use chrono_tz::Europe::London;
+
+    fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    // 2026-10-12 is a Monday
+    fn at(day: u32, hour: u32, minute: u32) -> DateTime<Tz> {
+        London.with_ymd_and_hms(2026, 10, day, hour, minute, 0).unwrap()
+    }
+
+    fn parse(text: &str, now: DateTime<Tz>) -> DateRange {
+        DateParser::new().parse(text, now).unwrap()
+    }
+
+    #[test]
+    fn next_weekday_depends_on_the_day_it_is_said() {
+        for said_on in [12, 13, 17] {
+            let range = parse("next Tuesday", at(said_on, 10, 0));
+            assert_eq!((range.from, range.to), (date(2026, 10, 20), date(2026, 10, 20)), "said on the {}th", said_on);
+            assert_eq!(range.confidence, Confidence::Medium);
+        }
+
+        assert_eq!(parse("next Tuesday", at(16, 10, 0)).from, date(2026, 10, 20));
+        assert_eq!(parse("this Thursday", at(12, 10, 0)).from, date(2026, 10, 15));
+        assert_eq!(parse("friday", at(14, 10, 0)).from, date(2026, 10, 16));
+    }
+
+    #[test]
+    fn same_weekday_rolls_over_once_the_time_has_gone() {
+        let later = parse("Saturday at 10", at(17, 15, 0));
+        assert_eq!(later.from, date(2026, 10, 24));
+        assert_eq!(later.time, TimeOfDay::At(time(10, 0)));
+
+        assert_eq!(parse("Saturday at 10", at(17, 9, 0)).from, date(2026, 10, 17));
+    }
+
+    #[test]
+    fn weekends() {
+        let wednesday = parse("this weekend", at(14, 10, 0));
+        assert_eq!((wednesday.from, wednesday.to), (date(2026, 10, 17), date(2026, 10, 18)));
+
+        let saturday = parse("this weekend", at(17, 10, 0));
+        assert_eq!((saturday.from, saturday.to), (date(2026, 10, 17), date(2026, 10, 18)));
+
+        let sunday = parse("weekend", at(18, 10, 0));
+        assert_eq!((sunday.from, sunday.to), (date(2026, 10, 18), date(2026, 10, 18)));
+
+        let next = parse("next weekend", at(14, 10, 0));
+        assert_eq!((next.from, next.to), (date(2026, 10, 24), date(2026, 10, 25)));
+    }
+
+    #[test]
+    fn spoken_times() {
+        let half_two = parse("tomorrow at half two", at(14, 10, 0));
+        assert_eq!(half_two.from, date(2026, 10, 15));
+        assert_eq!(half_two.time, TimeOfDay::At(time(14, 30)));
+        assert_eq!(half_two.confidence, Confidence::Medium);
+
+        assert_eq!(parse("quarter to one", at(14, 10, 0)).time, TimeOfDay::At(time(12, 45)));
+        assert_eq!(parse("quarter past 9 in the morning", at(14, 8, 0)).time, TimeOfDay::At(time(9, 15)));
+        assert_eq!(parse("at 3 in the afternoon", at(14, 10, 0)).time, TimeOfDay::At(time(15, 0)));
+        assert_eq!(parse("noon", at(14, 10, 0)).time, TimeOfDay::At(time(12, 0)));
+        assert_eq!(parse("Friday after 4", at(14, 10, 0)).time, TimeOfDay::Between(time(16, 0), time(23, 59)));
+        assert_eq!(parse("next Tuesday afternoon", at(12, 10, 0)).time, TimeOfDay::Between(time(12, 0), time(17, 0)));
+    }
+
+    #[test]
+    fn a_time_that_has_gone_means_tomorrow() {
+        let gone = parse("9am", at(14, 10, 0));
+        assert_eq!(gone.from, date(2026, 10, 15));
+        assert!(!gone.dated);
+        assert_eq!(gone.confidence, Confidence::Medium);
+
+        let later = parse("4pm", at(14, 10, 0));
+        assert_eq!(later.from, date(2026, 10, 14));
+        assert_eq!(later.confidence, Confidence::High);
+    }
+
+    #[test]
+    fn day_comes_before_month() {
+        let ambiguous = parse("3/4", at(14, 10, 0));
+        assert_eq!(ambiguous.from, date(2027, 4, 3));
+        assert_eq!(ambiguous.confidence, Confidence::Medium);
+
+        let clear = parse("13/4", at(14, 10, 0));
+        assert_eq!(clear.from, date(2027, 4, 13));
+        assert_eq!(clear.confidence, Confidence::High);
+
+        // Only one way round makes a date
+        assert_eq!(parse("4/13/2027", at(14, 10, 0)).from, date(2027, 4, 13));
+
+        assert_eq!(parse("the 3rd of November", at(14, 10, 0)).from, date(2026, 11, 3));
+        assert_eq!(parse("march 5th", at(14, 10, 0)).from, date(2027, 3, 5));
+        assert!(DateParser::new().parse("31/02/2027", at(14, 10, 0)).is_err());
+    }
+
+    #[test]
+    fn offsets_and_ranges() {
+        let soon = parse("in 2 hours", at(14, 10, 0));
+        assert_eq!((soon.from, soon.time), (date(2026, 10, 14), TimeOfDay::At(time(12, 0))));
+
+        let next_week = parse("next week", at(14, 10, 0));
+        assert_eq!((next_week.from, next_week.to), (date(2026, 10, 19), date(2026, 10, 25)));
+        assert_eq!(next_week.days(), 7);
+
+        assert_eq!(parse("the 31st", at(14, 10, 0)).from, date(2026, 10, 31));
+        assert_eq!(parse("the 31st", date(2026, 11, 2).and_hms_opt(10, 0, 0).unwrap().and_local_timezone(London).unwrap()).from, date(2026, 12, 31));
+    }
+
+    #[test]
+    fn unplaced_numbers_lower_the_confidence() {
+        assert_eq!(parse("tomorrow at 10am for 2", at(14, 10, 0)).confidence, Confidence::Low);
+    }
+
+    #[test]
+    fn times_skipped_by_the_clocks_going_forward_move_on_an_hour() {
+        let london = DateRange { from: date(2027, 3, 28), to: date(2027, 3, 28), time: TimeOfDay::At(time(1, 30)), dated: true, confidence: Confidence::High };
+        assert_eq!(london.start(London).naive_local(), date(2027, 3, 28).and_hms_opt(2, 30, 0).unwrap());
+
+        let new_york = DateRange { from: date(2027, 3, 14), to: date(2027, 3, 14), time: TimeOfDay::At(time(2, 30)), dated: true, confidence: Confidence::High };
+        assert_eq!(new_york.start(New_York).naive_local(), date(2027, 3, 14).and_hms_opt(3, 30, 0).unwrap());
+    }
+}
@@ -0,0 +1,192 @@
+use serde_derive::{Serialize, Deserialize};
+use std::path::Path;
+
+use crate::error::AppError;
+use crate::read_input;
+
+// The directives every business starts with, in their default order
+const BUILTIN_DIRECTIVES: &[(&str, &str, &str)] = &[
+    ("human", "Human", "Act natural and human, don't be too robotic or mechanical in your responses - you can reveal your identity if inquired about, but other than that act as natural as possible. Instead of using robotic words like \"assist\" use words like \"help\", etc."),
+    ("stay_ontopic", "Stay Ontopic", "Don't leave the topic of the business, try to stay on topic and answer questions related to the business, if they try to move off topic, bring them back on topic."),
+    ("professionalism", "Professionalism", "Always maintain a courteous and professional demeanor. Your responses should reflect the business's values and standards."),
+    ("transparency", "Transparency", "If a customer inquires about your nature, be honest. Inform them that you are an AI designed to assist and make their experience smoother."),
+    ("constraints", "Constraints", "Ensure never to give out links to non-business provided material, if there is no context to a question (for example 'how can i buy x?') assume that they are trying to use or buy something from the business"),
+    ("training", "Training", "If the message [TRAINDATA] is given to you, this is for your training by a developer. Ensure that you listen to what is told and reply with nothing. This is to ensure that you are trained to respond to certain prompts in a certain way."),
+];
+
+fn enabled_by_default() -> bool {
+    true
+}
+
+fn builtin(id: &str) -> Option<&'static (&'static str, &'static str, &'static str)> {
+    BUILTIN_DIRECTIVES.iter().find(|(builtin_id, _, _)| *builtin_id == id)
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Directive {
+    pub id: String,
+    // Built-in directives leave these empty in directives.json unless the business customised them
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub title: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub text: String,
+    #[serde(default = "enabled_by_default")]
+    pub enabled: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DirectiveSet {
+    directives: Vec<Directive>,
+}
+
+impl Default for DirectiveSet {
+    fn default() -> Self {
+        DirectiveSet {
+            directives: BUILTIN_DIRECTIVES
+                .iter()
+                .map(|(id, title, text)| Directive {
+                    id: id.to_string(),
+                    title: title.to_string(),
+                    text: text.to_string(),
+                    enabled: true,
+                })
+                .collect(),
+        }
+    }
+}
+
+impl DirectiveSet {
+    // Reads directives.json from the business's data directory. Directives it lists come first in the order
+    // given, built-ins it doesn't mention follow in their default order.
+    pub fn load(path: &Path) -> Result<Self, AppError> {
+        if !path.exists() {
+            return Ok(DirectiveSet::default());
+        }
+
+        let stored: DirectiveSet = serde_json::from_str(&std::fs::read_to_string(path)?)
+            .map_err(|e| AppError::Config(format!("{}: {}", path.display(), e)))?;
+
+        let mut directives = Vec::new();
+        for mut directive in stored.directives {
+            match builtin(&directive.id) {
+                Some((_, title, text)) => {
+                    if directive.title.is_empty() {
+                        directive.title = title.to_string();
+                    }
+                    if directive.text.is_empty() {
+                        directive.text = text.to_string();
+                    }
+                }
+                None if directive.title.is_empty() || directive.text.is_empty() => {
+                    return Err(AppError::Config(format!(
+                        "{}: directive '{}' needs a title and text", path.display(), directive.id
+                    )));
+                }
+                None => {}
+            }
+            directives.push(directive);
+        }
+
+        for default in DirectiveSet::default().directives {
+            if !directives.iter().any(|directive| directive.id == default.id) {
+                directives.push(default);
+            }
+        }
+
+        Ok(DirectiveSet { directives })
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), AppError> {
+        // Only keep what differs from the built-in wording, so improvements to it still reach this business
+        let directives = self.directives
+            .iter()
+            .map(|directive| {
+                let mut stored = directive.clone();
+                if let Some((_, title, text)) = builtin(&directive.id) {
+                    if stored.title == *title {
+                        stored.title.clear();
+                    }
+                    if stored.text == *text {
+                        stored.text.clear();
+                    }
+                }
+                stored
+            })
+            .collect();
+
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(path, serde_json::to_string_pretty(&DirectiveSet { directives })?)?;
+
+        Ok(())
+    }
+
+    pub fn enabled(&self) -> Vec<&Directive> {
+        self.directives.iter().filter(|directive| directive.enabled).collect()
+    }
+
+    fn print(&self) {
+        for (index, directive) in self.directives.iter().enumerate() {
+            println!(
+                "{}. [{}] **{}**: {}",
+                index + 1, if directive.enabled { "on" } else { "off" }, directive.title, directive.text
+            );
+        }
+    }
+
+    // Picks a directive by its number in the printed list
+    fn index(&self, number: Option<&str>) -> Option<usize> {
+        let number: usize = number?.parse().ok()?;
+        (1..=self.directives.len()).contains(&number).then(|| number - 1)
+    }
+
+    // Lets the manager enable, disable, reorder, reword and add directives during setup
+    pub fn edit_interactively(&mut self) -> Result<(), AppError> {
+        loop {
+            self.print();
+            println!("Type 'enable N', 'disable N', 'move N M', 'edit N', 'add' or 'done':");
+
+            let command = read_input()?;
+            let mut parts = command.split_whitespace();
+
+            match (parts.next(), self.index(parts.next()), parts.next()) {
+                (Some("done"), _, _) | (None, _, _) => return Ok(()),
+                (Some("enable"), Some(index), _) => self.directives[index].enabled = true,
+                (Some("disable"), Some(index), _) => self.directives[index].enabled = false,
+                (Some("move"), Some(from), Some(to)) => match self.index(Some(to)) {
+                    Some(to) => {
+                        let directive = self.directives.remove(from);
+                        self.directives.insert(to, directive);
+                    }
+                    None => println!("Invalid position."),
+                },
+                (Some("edit"), Some(index), _) => {
+                    println!("New wording for **{}**:", self.directives[index].title);
+                    let text = read_input()?;
+                    if !text.is_empty() {
+                        self.directives[index].text = text;
+                    }
+                }
+                (Some("add"), _, _) => {
+                    println!("Title of the new directive:");
+                    let title = read_input()?;
+                    println!("What should the assistant do?");
+                    let text = read_input()?;
+
+                    if title.is_empty() || text.is_empty() {
+                        println!("A directive needs both a title and a description.");
+                    } else {
+                        self.directives.push(Directive {
+                            id: format!("custom-{}", crate::slug(&title)),
+                            title,
+                            text,
+                            enabled: true,
+                        });
+                    }
+                }
+                _ => println!("Invalid command."),
+            }
+        }
+    }
+}
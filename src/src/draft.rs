@@ -0,0 +1,273 @@
+use async_openai::types::{ChatCompletionRequestMessage, ChatCompletionRequestMessageArgs, Role};
+use chrono::{NaiveDate, NaiveTime};
+use serde_derive::{Serialize, Deserialize};
+
+use crate::error::AppError;
+use crate::profile::CustomerIdentity;
+
+// Starts the system message describing a booking in progress, so each turn can find and replace it
+const DRAFT_MARKER: &str = "[BOOKING IN PROGRESS]";
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DraftField {
+    Service,
+    Date,
+    Time,
+    Name,
+    Contact,
+}
+
+impl DraftField {
+    fn question(&self) -> &'static str {
+        match self {
+            DraftField::Service => "Ask which service they'd like.",
+            DraftField::Date => "Ask which day suits them.",
+            DraftField::Time => "Ask what time they'd like, offering some of the free times.",
+            DraftField::Name => "Ask what name the booking should be under.",
+            DraftField::Contact => "Ask for an email address or phone number we can reach them on.",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DraftStage {
+    // No booking under way
+    #[default]
+    Idle,
+    Collecting,
+    // Everything's filled in, waiting for the customer to say yes to the summary
+    Confirming,
+}
+
+// A booking being put together over several turns, one per chat session. The customer can wander off to
+// other questions and the assistant picks up where it left off.
+#[derive(Debug, Clone, Default)]
+pub struct BookingDraft {
+    pub stage: DraftStage,
+    pub service: Option<String>,
+    pub variant: Option<String>,
+    pub staff: Option<String>,
+    pub date: Option<NaiveDate>,
+    pub time: Option<NaiveTime>,
+    pub name: Option<String>,
+    pub contact: Option<CustomerIdentity>,
+}
+
+impl BookingDraft {
+    // Who the customer is carries over from their profile, so returning customers aren't asked again
+    pub fn new(name: Option<String>, contact: Option<CustomerIdentity>) -> Self {
+        BookingDraft { name, contact, ..BookingDraft::default() }
+    }
+
+    pub fn missing(&self) -> Vec<DraftField> {
+        [
+            (DraftField::Service, self.service.is_none()),
+            (DraftField::Date, self.date.is_none()),
+            (DraftField::Time, self.time.is_none()),
+            (DraftField::Name, self.name.is_none()),
+            (DraftField::Contact, self.contact.is_none()),
+        ]
+        .into_iter()
+        .filter(|(_, missing)| *missing)
+        .map(|(field, _)| field)
+        .collect()
+    }
+
+    // Called after every change, anything changed after the summary was read out needs confirming again
+    pub fn advance(&mut self) {
+        self.stage = if self.missing().is_empty() { DraftStage::Confirming } else { DraftStage::Collecting };
+    }
+
+    // Starts the next booking, still knowing who the customer is
+    pub fn reset(&mut self) {
+        *self = BookingDraft::new(self.name.take(), self.contact.take());
+    }
+
+    pub fn summary(&self) -> String {
+        let mut parts = Vec::new();
+
+        if let Some(service) = &self.service {
+            parts.push(match &self.variant {
+                Some(variant) => format!("{} ({})", service, variant),
+                None => service.clone(),
+            });
+        }
+        if let Some(staff) = &self.staff {
+            parts.push(format!("with {}", staff));
+        }
+        if let Some(date) = self.date {
+            parts.push(format!("on {}", date.format("%A %-d %B %Y")));
+        }
+        if let Some(time) = self.time {
+            parts.push(format!("at {}", time.format("%H:%M")));
+        }
+        if let Some(name) = &self.name {
+            parts.push(format!("for {}", name));
+        }
+        match &self.contact {
+            Some(CustomerIdentity::Email(contact) | CustomerIdentity::Phone(contact) | CustomerIdentity::ChatId(contact)) => {
+                parts.push(format!("(contact: {})", contact));
+            }
+            None => {}
+        }
+
+        parts.join(" ")
+    }
+
+    // What the assistant should do next, asking for one missing detail at a time
+    pub fn next_step(&self) -> String {
+        match (self.stage, self.missing().first()) {
+            (DraftStage::Idle, _) => "No booking is in progress.".to_string(),
+            (DraftStage::Confirming, _) | (_, None) => format!(
+                "Read this back to the customer and ask them to confirm it before booking: {}.", self.summary()
+            ),
+            (DraftStage::Collecting, Some(field)) => field.question().to_string(),
+        }
+    }
+
+    // Keeps the booking in front of the model for as long as it's under way, right after the system prompt
+    pub fn apply(&self, conversation: &mut Vec<ChatCompletionRequestMessage>) -> Result<(), AppError> {
+        let existing = conversation.iter().position(|message| {
+            message.role == Role::System
+                && message.content.as_deref().is_some_and(|content| content.starts_with(DRAFT_MARKER))
+        });
+
+        if self.stage == DraftStage::Idle {
+            if let Some(index) = existing {
+                conversation.remove(index);
+            }
+            return Ok(());
+        }
+
+        let so_far = match self.summary() {
+            summary if summary.is_empty() => "nothing yet".to_string(),
+            summary => summary,
+        };
+        let message = ChatCompletionRequestMessageArgs::default()
+            .role(Role::System)
+            .content(format!(
+                "{} The customer is part way through booking. So far: {}. Next: {} If they've asked about something else, answer that first, then come back to the booking. Record each detail they give with update_booking.",
+                DRAFT_MARKER, so_far, self.next_step()
+            ))
+            .build()?;
+
+        match existing {
+            Some(index) => conversation[index] = message,
+            None => conversation.insert(1.min(conversation.len()), message),
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(role: Role, content: &str) -> ChatCompletionRequestMessage {
+        ChatCompletionRequestMessageArgs::default().role(role).content(content).build().unwrap()
+    }
+
+    // Everything but the contact filled in
+    fn nearly_done() -> BookingDraft {
+        let mut draft = BookingDraft::new(Some("Sam".to_string()), None);
+        draft.service = Some("Haircut".to_string());
+        draft.date = NaiveDate::from_ymd_opt(2026, 10, 17);
+        draft.time = NaiveTime::from_hms_opt(10, 30, 0);
+        draft.advance();
+        draft
+    }
+
+    fn drafts(conversation: &[ChatCompletionRequestMessage]) -> Vec<&str> {
+        conversation
+            .iter()
+            .filter_map(|message| message.content.as_deref())
+            .filter(|content| content.starts_with(DRAFT_MARKER))
+            .collect()
+    }
+
+    #[test]
+    fn missing_lists_the_details_still_needed_in_order() {
+        assert_eq!(BookingDraft::default().missing(), vec![
+            DraftField::Service, DraftField::Date, DraftField::Time, DraftField::Name, DraftField::Contact,
+        ]);
+        assert_eq!(BookingDraft::new(Some("Sam".to_string()), Some(CustomerIdentity::Email("sam@example.com".to_string()))).missing(), vec![
+            DraftField::Service, DraftField::Date, DraftField::Time,
+        ]);
+        assert_eq!(nearly_done().missing(), vec![DraftField::Contact]);
+    }
+
+    #[test]
+    fn the_draft_is_confirmed_once_complete_and_collected_again_after_a_change() {
+        let mut draft = nearly_done();
+        assert_eq!(draft.stage, DraftStage::Collecting);
+        assert_eq!(draft.next_step(), DraftField::Contact.question());
+
+        draft.contact = Some(CustomerIdentity::Phone("07700 900123".to_string()));
+        draft.advance();
+        assert_eq!(draft.stage, DraftStage::Confirming);
+        assert_eq!(
+            draft.next_step(),
+            "Read this back to the customer and ask them to confirm it before booking: Haircut on Saturday 17 October 2026 at 10:30 for Sam (contact: 07700 900123)."
+        );
+
+        // Changing their mind about the day after the summary goes back to asking for it
+        draft.date = None;
+        draft.advance();
+        assert_eq!(draft.stage, DraftStage::Collecting);
+        assert_eq!(draft.next_step(), DraftField::Date.question());
+
+        assert_eq!(BookingDraft::default().next_step(), "No booking is in progress.");
+    }
+
+    #[test]
+    fn reset_starts_over_but_remembers_the_customer() {
+        let mut draft = nearly_done();
+        draft.contact = Some(CustomerIdentity::Email("sam@example.com".to_string()));
+        draft.variant = Some("Long hair".to_string());
+        draft.staff = Some("Alice".to_string());
+        draft.advance();
+
+        draft.reset();
+        assert_eq!(draft.stage, DraftStage::Idle);
+        assert_eq!((draft.service, draft.variant, draft.staff, draft.date, draft.time), (None, None, None, None, None));
+        assert_eq!(draft.name.as_deref(), Some("Sam"));
+        assert!(matches!(draft.contact, Some(CustomerIdentity::Email(contact)) if contact == "sam@example.com"));
+    }
+
+    #[test]
+    fn apply_pins_one_draft_message_after_the_system_prompt_and_removes_it_when_idle() {
+        let mut conversation = vec![
+            message(Role::System, "You are the salon's assistant."),
+            message(Role::User, "I'd like a haircut on Saturday"),
+        ];
+
+        let mut draft = BookingDraft::new(None, None);
+        draft.service = Some("Haircut".to_string());
+        draft.advance();
+        draft.apply(&mut conversation).unwrap();
+        assert_eq!(conversation.len(), 3);
+        assert_eq!(conversation[1].role, Role::System);
+        assert!(drafts(&conversation)[0].contains("So far: Haircut. Next: Ask which day suits them."));
+
+        // Replaced in place rather than added again
+        conversation.push(message(Role::User, "Saturday please"));
+        draft.date = NaiveDate::from_ymd_opt(2026, 10, 17);
+        draft.advance();
+        draft.apply(&mut conversation).unwrap();
+        assert_eq!(conversation.len(), 4);
+        assert_eq!(drafts(&conversation).len(), 1);
+        assert!(conversation[1].content.as_deref().unwrap().contains("So far: Haircut on Saturday 17 October 2026."));
+
+        draft.reset();
+        draft.apply(&mut conversation).unwrap();
+        assert_eq!(conversation.len(), 3);
+        assert!(drafts(&conversation).is_empty());
+
+        // Nothing to remove is fine too
+        draft.apply(&mut conversation).unwrap();
+        assert_eq!(conversation.len(), 3);
+    }
+}
@@ -0,0 +1,135 @@
+use async_openai::types::{CreateEmbeddingRequestArgs, EmbeddingInput};
+use serde_derive::{Serialize, Deserialize};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use crate::error::AppError;
+use crate::llm::LlmClient;
+
+const DEFAULT_MODEL: &str = "text-embedding-ada-002";
+// Inputs sent in one request
+const BATCH_SIZE: usize = 64;
+
+// FNV-1a, unlike std's hasher it gives the same answer in every build, so it can be kept on disk
+pub fn fingerprint(bytes: &[u8]) -> String {
+    let hash = bytes.iter().fold(0xcbf29ce484222325u64, |hash, byte| (hash ^ *byte as u64).wrapping_mul(0x100000001b3));
+    format!("{:016x}", hash)
+}
+
+// Scaled to length 1, so the cosine similarity of two of them is just their dot product
+fn normalise(mut vector: Vec<f32>) -> Vec<f32> {
+    let length = vector.iter().map(|value| value * value).sum::<f32>().sqrt();
+    if length > 0.0 {
+        vector.iter_mut().for_each(|value| *value /= length);
+    }
+    vector
+}
+
+pub fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b).map(|(a, b)| a * b).sum()
+}
+
+// Turns text into vectors through one provider's embeddings endpoint. Unlike chat there's no falling through to
+// the next provider, vectors from different models can't be compared with each other.
+pub struct Embedder {
+    pub provider: String,
+    pub model: String,
+    client: LlmClient,
+}
+
+impl Embedder {
+    // EMBEDDING_PROVIDER names a provider set up as for LLM_PROVIDERS, a local one works as long as it has an
+    // OpenAI-compatible embeddings endpoint. It defaults to the first of LLM_PROVIDERS, "off" leaves the knowledge
+    // base on keyword search alone. EMBEDDING_MODEL defaults to text-embedding-ada-002.
+    pub fn from_env() -> Result<Option<Self>, AppError> {
+        let provider = match std::env::var("EMBEDDING_PROVIDER") {
+            Ok(provider) if provider == "off" => return Ok(None),
+            Ok(provider) => provider,
+            Err(_) => std::env::var("LLM_PROVIDERS")
+                .unwrap_or_else(|_| "openai".to_string())
+                .split(',')
+                .map(str::trim)
+                .find(|name| !name.is_empty())
+                .unwrap_or("openai")
+                .to_string(),
+        };
+        let model = std::env::var("EMBEDDING_MODEL").unwrap_or_else(|_| DEFAULT_MODEL.to_string());
+
+        Ok(Some(Embedder { client: LlmClient::from_env(&provider)?, provider, model }))
+    }
+
+    // One vector per input, in the same order, along with the tokens used
+    pub async fn embed(&self, inputs: &[String]) -> Result<(Vec<Vec<f32>>, u32), AppError> {
+        let mut vectors = Vec::with_capacity(inputs.len());
+        let mut tokens = 0;
+
+        for batch in inputs.chunks(BATCH_SIZE) {
+            let request = CreateEmbeddingRequestArgs::default()
+                .model(&self.model)
+                .input(EmbeddingInput::StringArray(batch.to_vec()))
+                .build()?;
+            let mut response = self.client.create_embedding(&request).await?;
+
+            if response.data.len() != batch.len() {
+                return Err(AppError::LlmContent(format!("asked for {} embeddings but got {}", batch.len(), response.data.len())));
+            }
+            response.data.sort_by_key(|embedding| embedding.index);
+            vectors.extend(response.data.into_iter().map(|embedding| normalise(embedding.embedding)));
+            tokens += response.usage.prompt_tokens;
+        }
+
+        Ok((vectors, tokens))
+    }
+}
+
+// Chunk vectors kept in knowledge_vectors.json next to the knowledge base. They're keyed by a fingerprint of the
+// chunk's text, so re-reading a document only embeds the parts of it that changed.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct VectorIndex {
+    #[serde(skip)]
+    path: PathBuf,
+    model: String,
+    vectors: HashMap<String, Vec<f32>>,
+}
+
+impl VectorIndex {
+    // Vectors made by a different model are no use, so changing EMBEDDING_MODEL starts the index again
+    pub fn open(path: &Path, model: &str) -> Result<Self, AppError> {
+        let mut index: VectorIndex = if path.exists() {
+            serde_json::from_str(&std::fs::read_to_string(path)?)
+                .map_err(|e| AppError::Config(format!("{}: {}", path.display(), e)))?
+        } else {
+            VectorIndex::default()
+        };
+
+        if index.model != model {
+            index.model = model.to_string();
+            index.vectors.clear();
+        }
+        index.path = path.to_path_buf();
+
+        Ok(index)
+    }
+
+    pub fn save(&self) -> Result<(), AppError> {
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(&self.path, serde_json::to_string(self)?)?;
+
+        Ok(())
+    }
+
+    pub fn get(&self, key: &str) -> Option<&[f32]> {
+        self.vectors.get(key).map(Vec::as_slice)
+    }
+
+    pub fn insert(&mut self, key: String, vector: Vec<f32>) {
+        self.vectors.insert(key, vector);
+    }
+
+    // Drops the vectors of chunks that no longer exist
+    pub fn retain(&mut self, keys: &HashSet<String>) {
+        self.vectors.retain(|key, _| keys.contains(key));
+    }
+}
@@ -0,0 +1,69 @@
+use async_openai::error::OpenAIError;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum AppError {
+    // Missing or malformed settings, nothing sensible can be done without them
+    #[error("configuration error: {0}")]
+    Config(String),
+
+    // The LLM could not be reached or refused the request
+    #[error("LLM request failed: {0}")]
+    LlmTransport(String),
+
+    // The LLM turned the request itself down (too long, bad parameters, bad key), sending it again won't help
+    #[error("LLM rejected the request: {0}")]
+    LlmRequest(String),
+
+    // The LLM answered, but with nothing we can use
+    #[error("LLM returned unusable content: {0}")]
+    LlmContent(String),
+
+    #[error("sentiment service error: {0}")]
+    Sentiment(String),
+
+    #[error("storage error: {0}")]
+    Storage(String),
+
+    #[error("invalid input: {0}")]
+    UserInput(String),
+
+    // A booking that can't be made, moved or cancelled as asked, e.g. the slot was taken
+    #[error("booking error: {0}")]
+    Booking(String),
+
+    // The business has reached its monthly LLM spend cap and chose to block further calls
+    #[error("spend cap reached: {0}")]
+    SpendCap(String),
+
+    // A reminder or follow-up couldn't be handed to the notification channel
+    #[error("notification error: {0}")]
+    Notification(String),
+}
+
+impl From<OpenAIError> for AppError {
+    fn from(error: OpenAIError) -> Self {
+        match error {
+            OpenAIError::JSONDeserialize(error) => AppError::LlmContent(error.to_string()),
+            error => AppError::LlmTransport(error.to_string()),
+        }
+    }
+}
+
+impl From<std::io::Error> for AppError {
+    fn from(error: std::io::Error) -> Self {
+        AppError::Storage(error.to_string())
+    }
+}
+
+impl From<serde_json::Error> for AppError {
+    fn from(error: serde_json::Error) -> Self {
+        AppError::Storage(error.to_string())
+    }
+}
+
+impl From<sqlx::Error> for AppError {
+    fn from(error: sqlx::Error) -> Self {
+        AppError::Storage(error.to_string())
+    }
+}
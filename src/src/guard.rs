@@ -0,0 +1,244 @@
+use chrono::{DateTime, Utc};
+use regex::Regex;
+use serde_derive::{Serialize, Deserialize};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use crate::catalog::{currency_symbol, format_price, Catalog, CatalogItem};
+use crate::error::AppError;
+
+// Said instead of a reply that still quotes prices we can't back up after regenerating, once the question has been
+// passed on to the team as an escalation
+pub const PRICE_FALLBACK: &str = "I'd rather not give you the wrong price, so I've passed your question on to the team and they'll confirm it with you shortly.";
+// The same when the escalation couldn't be recorded, so nobody is going to get back to them
+pub const PRICE_FALLBACK_UNESCALATED: &str = "I'd rather not give you the wrong price, so please check it with a member of the team.";
+
+// Amounts closer than this are the same price
+const PRICE_TOLERANCE: f64 = 0.005;
+
+// Whether an amount written with this symbol or word can be in the given currency. "$" and "dollars" could be any
+// of the dollars.
+fn in_currency(written: &str, currency: &str) -> bool {
+    let currency = currency.to_uppercase();
+    match written.to_lowercase().as_str() {
+        "£" | "gbp" | "pound" | "pounds" => currency == "GBP",
+        "€" | "eur" | "euro" | "euros" => currency == "EUR",
+        "usd" => currency == "USD",
+        _ => currency_symbol(&currency) == Some("$"),
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Violation {
+    // The amount as the reply wrote it
+    pub quoted: String,
+    // The catalog service it was quoted for, if the sentence named one or referred back to one
+    pub service: Option<String>,
+    pub expected: Vec<String>,
+}
+
+impl Violation {
+    pub fn describe(&self) -> String {
+        match (&self.service, self.expected.is_empty()) {
+            (Some(service), false) => format!("{} was quoted for {}, but the catalog price is {}", self.quoted, service, self.expected.join(" or ")),
+            (Some(service), true) => format!("{} was quoted for {}, which has no listed price", self.quoted, service),
+            (None, _) => format!("{} was quoted for something that isn't a service in the catalog", self.quoted),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GuardAction {
+    Regenerated,
+    FellBack,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ViolationRecord {
+    at: DateTime<Utc>,
+    session: String,
+    reply: String,
+    violations: Vec<Violation>,
+    action: GuardAction,
+}
+
+// Checks the prices in assistant replies against the catalog, logging every mismatch to violations.jsonl in
+// the business's data directory
+pub struct PriceGuard {
+    catalog: Catalog,
+    log_path: PathBuf,
+    pub max_regenerations: u32,
+    amount_pattern: Regex,
+    sentence_pattern: Regex,
+    // A sentence that starts like this is still about the service the one before it named, "It's £25."
+    reference_pattern: Regex,
+}
+
+impl PriceGuard {
+    // PRICE_GUARD_REGENERATIONS is how many corrected replies we ask for before falling back, default 1
+    pub fn new(catalog: Catalog, data_dir: &Path) -> Result<Self, AppError> {
+        let max_regenerations = match std::env::var("PRICE_GUARD_REGENERATIONS") {
+            Ok(value) => value.parse().map_err(|_| AppError::Config(format!("PRICE_GUARD_REGENERATIONS '{}' is not a number", value)))?,
+            Err(_) => 1,
+        };
+
+        Ok(PriceGuard {
+            catalog,
+            log_path: data_dir.join("violations.jsonl"),
+            max_regenerations,
+            amount_pattern: Regex::new(
+                r"(?i)([£$€])\s?(\d{1,3}(?:,\d{3})+|\d+)(\.\d{1,2})?|\b(\d{1,3}(?:,\d{3})+|\d+)(\.\d{1,2})?\s?(gbp|usd|eur|pounds?|dollars?|euros?)\b"
+            ).unwrap(),
+            sentence_pattern: Regex::new(r"[.!?](?:\s|$)|\n").unwrap(),
+            reference_pattern: Regex::new(r"(?i)^\W*(?:it|it's|its|that|that's|this|these|those|they|they're|which|the (?:price|cost|total))\b").unwrap(),
+        })
+    }
+
+    // Every quoted price has to be for a service in the catalog, named in its sentence or the one before, and be
+    // one of that service's prices in its currency. Services mentioned without a price aren't checked.
+    pub fn check(&self, reply: &str) -> Vec<Violation> {
+        // Without a catalog there is nothing to check against
+        if self.catalog.items.is_empty() {
+            return Vec::new();
+        }
+
+        let mut violations = Vec::new();
+        let mut previous: Option<&CatalogItem> = None;
+
+        for sentence in self.sentence_pattern.split(reply) {
+            let lowercase = sentence.to_lowercase();
+
+            // Longest names first, so "Long Haircut" isn't mistaken for "Haircut"
+            let mut named: Vec<_> = self.catalog.items
+                .iter()
+                .filter(|item| lowercase.contains(&item.name.to_lowercase()))
+                .collect();
+            named.sort_by_key(|item| std::cmp::Reverse(item.name.len()));
+
+            let service = match named.first() {
+                Some(item) => Some(*item),
+                None if self.reference_pattern.is_match(sentence) => previous,
+                None => None,
+            };
+            previous = service;
+
+            for captures in self.amount_pattern.captures_iter(sentence) {
+                let written = captures.get(1).or(captures.get(6)).map(|m| m.as_str()).unwrap_or_default();
+                let whole = captures.get(2).or(captures.get(4)).map(|m| m.as_str().replace(',', "")).unwrap_or_default();
+                let fraction = captures.get(3).or(captures.get(5)).map(|m| m.as_str()).unwrap_or_default();
+                let Ok(amount) = format!("{}{}", whole, fraction).parse::<f64>() else {
+                    continue;
+                };
+                // £25 isn't $25, the currency has to match as well as the amount
+                let matches = |item: &CatalogItem| {
+                    in_currency(written, &item.currency) && item.prices().iter().any(|price| (price - amount).abs() < PRICE_TOLERANCE)
+                };
+
+                let violation = match service {
+                    // The sentence is about a particular service, so the amount has to be one of its prices
+                    Some(item) if !matches(item) => Some(Violation {
+                        quoted: captures[0].to_string(),
+                        service: Some(item.name.clone()),
+                        expected: item.prices().iter().map(|price| format_price(*price, &item.currency)).collect(),
+                    }),
+                    Some(_) => None,
+                    // A price for something we can't tie to the catalog, even one that happens to match a listed
+                    // price, could be for a service the business doesn't offer
+                    None => Some(Violation {
+                        quoted: captures[0].to_string(),
+                        service: None,
+                        expected: Vec::new(),
+                    }),
+                };
+
+                violations.extend(violation);
+            }
+        }
+
+        violations
+    }
+
+    // Goes into the conversation as a system message when asking the model to try again
+    pub fn correction_hint(&self, violations: &[Violation]) -> String {
+        format!(
+            "Your last reply quoted prices that aren't in the business's catalog: {}. Rewrite the reply using only the services and prices in the catalog table, naming the service each price is for, and if the customer is asking about something that isn't listed, say you'll check with the team instead of giving a price.",
+            violations.iter().map(Violation::describe).collect::<Vec<String>>().join("; ")
+        )
+    }
+
+    pub fn log(&self, session: &str, reply: &str, violations: &[Violation], action: GuardAction) -> Result<(), AppError> {
+        let record = ViolationRecord {
+            at: Utc::now(),
+            session: session.to_string(),
+            reply: reply.to_string(),
+            violations: violations.to_vec(),
+            action,
+        };
+
+        if let Some(parent) = self.log_path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let mut log = std::fs::OpenOptions::new().create(true).append(true).open(&self.log_path)?;
+        writeln!(log, "{}", serde_json::to_string(&record)?)?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn guard(currency: &str) -> PriceGuard {
+        let catalog = Catalog {
+            items: vec![CatalogItem {
+                name: "Haircut".to_string(),
+                description: None,
+                price: Some(25.0),
+                currency: currency.to_string(),
+                duration_minutes: None,
+                variants: Vec::new(),
+            }],
+        };
+        PriceGuard::new(catalog, Path::new("/tmp")).unwrap()
+    }
+
+    #[test]
+    fn quoted_prices_must_be_in_the_catalog_currency() {
+        let pounds = guard("GBP");
+        assert!(pounds.check("A haircut is £25.").is_empty());
+        assert!(pounds.check("A haircut is 25 pounds.").is_empty());
+        assert!(pounds.check("A haircut is £25. It's 25 GBP.").is_empty());
+
+        let violations = pounds.check("A haircut is $25.");
+        assert_eq!(violations.len(), 1);
+        assert_eq!(violations[0].service.as_deref(), Some("Haircut"));
+        assert_eq!(violations[0].expected, vec![format_price(25.0, "GBP")]);
+        assert_eq!(pounds.check("A haircut is 25 euros.").len(), 1);
+    }
+
+    #[test]
+    fn prices_must_be_for_a_service_in_the_catalog() {
+        let pounds = guard("GBP");
+
+        // £25 is a catalog price, but not for anything the business offers
+        let violations = pounds.check("We also do hot stone massages for £25.");
+        assert_eq!(violations.len(), 1);
+        assert_eq!(violations[0].service, None);
+
+        // A sentence referring back to the service before it is still about that service
+        assert!(pounds.check("Our haircut takes half an hour. It costs £25!").is_empty());
+        assert_eq!(pounds.check("Our haircut takes half an hour. It costs £30.")[0].service.as_deref(), Some("Haircut"));
+        assert_eq!(pounds.check("Our haircut takes half an hour. Massages are £25.").len(), 1);
+    }
+
+    #[test]
+    fn dollar_signs_cover_every_dollar_currency() {
+        let dollars = guard("CAD");
+        assert!(dollars.check("A haircut is $25.").is_empty());
+        assert!(dollars.check("A haircut is 25 dollars.").is_empty());
+        assert_eq!(dollars.check("A haircut is 25 USD.").len(), 1);
+        assert_eq!(dollars.check("A haircut is £25.").len(), 1);
+    }
+}
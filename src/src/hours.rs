@@ -0,0 +1,421 @@
+use chrono::{DateTime, Datelike, Duration, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Weekday};
+use serde_derive::{Serialize, Deserialize};
+use std::collections::HashMap;
+
+use crate::error::AppError;
+use crate::read_input;
+
+const WEEK: [Weekday; 7] = [
+    Weekday::Mon, Weekday::Tue, Weekday::Wed, Weekday::Thu, Weekday::Fri, Weekday::Sat, Weekday::Sun,
+];
+
+// How far ahead to look for the next opening before calling a location closed for good
+const NEXT_OPENING_SEARCH_DAYS: i64 = 60;
+
+fn day_name(day: Weekday) -> &'static str {
+    match day {
+        Weekday::Mon => "Monday",
+        Weekday::Tue => "Tuesday",
+        Weekday::Wed => "Wednesday",
+        Weekday::Thu => "Thursday",
+        Weekday::Fri => "Friday",
+        Weekday::Sat => "Saturday",
+        Weekday::Sun => "Sunday",
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct TimeRange {
+    pub open: NaiveTime,
+    pub close: NaiveTime,
+}
+
+impl TimeRange {
+    // Closing at or before the opening time means closing the next day, so "18:00-02:00" and "17:00-00:00" both
+    // belong to the day they open on
+    pub fn runs_overnight(&self) -> bool {
+        self.close <= self.open
+    }
+
+    // When the range opens and closes if it opens on this date
+    pub fn on(&self, date: NaiveDate) -> (NaiveDateTime, NaiveDateTime) {
+        let close_date = if self.runs_overnight() { date + Duration::days(1) } else { date };
+        (date.and_time(self.open), close_date.and_time(self.close))
+    }
+}
+
+// "09:00-17:00", several separated by spaces or commas, or "closed". A range can run past midnight, e.g.
+// "18:00-02:00", and "00:00-00:00" is open all day.
+pub fn parse_ranges(text: &str) -> Result<Vec<TimeRange>, AppError> {
+    if text.trim().eq_ignore_ascii_case("closed") {
+        return Ok(Vec::new());
+    }
+
+    let mut ranges = Vec::new();
+    for part in text.split(|c: char| c == ',' || c.is_whitespace()).filter(|part| !part.is_empty()) {
+        let invalid = || AppError::UserInput(format!("'{}' should look like 09:00-17:00", part));
+
+        let (open, close) = part.split_once('-').ok_or_else(invalid)?;
+        let open = NaiveTime::parse_from_str(open, "%H:%M").map_err(|_| invalid())?;
+        let close = NaiveTime::parse_from_str(close, "%H:%M").map_err(|_| invalid())?;
+        if close == open && open != NaiveTime::MIN {
+            return Err(AppError::UserInput(format!("'{}' opens and closes at the same time", part)));
+        }
+
+        ranges.push(TimeRange { open, close });
+    }
+
+    if ranges.is_empty() {
+        return Err(AppError::UserInput("Give opening times like 09:00-17:00, or 'closed'".to_string()));
+    }
+
+    ranges.sort_by_key(|range| range.open);
+    Ok(ranges)
+}
+
+fn describe_ranges(ranges: &[TimeRange]) -> String {
+    if ranges.is_empty() {
+        return "closed".to_string();
+    }
+
+    ranges
+        .iter()
+        .map(|range| format!("{}-{}", range.open.format("%H:%M"), range.close.format("%H:%M")))
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+// A holiday closure or special hours for one day or a run of days, no hours means closed
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Exception {
+    pub from: NaiveDate,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub to: Option<NaiveDate>,
+    pub hours: Vec<TimeRange>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+}
+
+impl Exception {
+    fn last_day(&self) -> NaiveDate {
+        self.to.unwrap_or(self.from)
+    }
+
+    fn covers(&self, date: NaiveDate) -> bool {
+        self.from <= date && date <= self.last_day()
+    }
+
+    // "2026-12-25 closed Christmas Day" or "2026-12-24..2026-12-31 10:00-14:00 Holiday hours"
+    pub fn parse(text: &str) -> Result<Self, AppError> {
+        let mut parts = text.split_whitespace();
+        let dates = parts.next().unwrap_or_default();
+        let hours = parts.next().unwrap_or_default();
+        let note = parts.collect::<Vec<&str>>().join(" ");
+
+        let parse_date = |date: &str| {
+            NaiveDate::parse_from_str(date, "%Y-%m-%d")
+                .map_err(|_| AppError::UserInput(format!("'{}' should be a date like 2026-12-25", date)))
+        };
+        let (from, to) = match dates.split_once("..") {
+            Some((from, to)) => (parse_date(from)?, Some(parse_date(to)?)),
+            None => (parse_date(dates)?, None),
+        };
+        if to.is_some_and(|to| to < from) {
+            return Err(AppError::UserInput(format!("'{}' ends before it starts", dates)));
+        }
+
+        Ok(Exception {
+            from,
+            to,
+            hours: parse_ranges(hours)?,
+            note: (!note.is_empty()).then_some(note),
+        })
+    }
+
+    fn describe(&self) -> String {
+        let mut dates = self.from.format("%A %-d %B %Y").to_string();
+        if let Some(to) = self.to {
+            dates.push_str(&format!(" to {}", to.format("%A %-d %B %Y")));
+        }
+
+        match &self.note {
+            Some(note) => format!("{} {} ({})", dates, describe_ranges(&self.hours), note),
+            None => format!("{} {}", dates, describe_ranges(&self.hours)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Location {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub address: Option<String>,
+    // Days missing from the schedule are closed
+    pub weekly: HashMap<Weekday, Vec<TimeRange>>,
+    #[serde(default)]
+    pub exceptions: Vec<Exception>,
+}
+
+impl Location {
+    // Special hours win over the weekly schedule, the most recently added exception wins over older ones
+    pub fn hours_on(&self, date: NaiveDate) -> &[TimeRange] {
+        match self.exceptions.iter().rev().find(|exception| exception.covers(date)) {
+            Some(exception) => &exception.hours,
+            None => self.weekly.get(&date.weekday()).map(Vec::as_slice).unwrap_or_default(),
+        }
+    }
+
+    // Every range that opened yesterday or opens today, with when it opens and closes. Yesterday's matter when
+    // they run past midnight.
+    fn ranges_around(&self, date: NaiveDate) -> impl Iterator<Item = (TimeRange, NaiveDateTime, NaiveDateTime)> + '_ {
+        [date - Duration::days(1), date]
+            .into_iter()
+            .flat_map(move |date| self.hours_on(date).iter().map(move |range| {
+                let (open, close) = range.on(date);
+                (*range, open, close)
+            }))
+    }
+
+    // The range the location is open in at this moment, if it is open
+    pub fn current_range(&self, at: NaiveDateTime) -> Option<TimeRange> {
+        self.ranges_around(at.date())
+            .find(|(_, open, close)| *open <= at && at < *close)
+            .map(|(range, _, _)| range)
+    }
+
+    // Whether the location stays open for the whole of this time
+    pub fn is_open_throughout(&self, from: NaiveDateTime, to: NaiveDateTime) -> bool {
+        self.ranges_around(from.date()).any(|(_, open, close)| open <= from && to <= close)
+    }
+
+    pub fn next_opening(&self, after: NaiveDateTime) -> Option<NaiveDateTime> {
+        (0..NEXT_OPENING_SEARCH_DAYS)
+            .map(|offset| after.date() + Duration::days(offset))
+            .flat_map(|date| self.hours_on(date).iter().map(move |range| date.and_time(range.open)))
+            .find(|opening| *opening > after)
+    }
+
+    fn collect() -> Result<Option<Self>, AppError> {
+        println!("Location name (leave blank to finish):");
+        let name = read_input()?;
+        if name.is_empty() {
+            return Ok(None);
+        }
+
+        println!("Address of {} (optional):", name);
+        let address = read_input()?;
+
+        println!("Opening hours for {}, e.g. \"09:00-17:00\", \"09:00-12:00 13:00-17:00\", \"18:00-02:00\" for past midnight or \"closed\". Leave blank to copy the day before.", name);
+        let mut weekly = HashMap::new();
+        let mut previous: Vec<TimeRange> = Vec::new();
+        for day in WEEK {
+            let hours = loop {
+                println!("{}:", day_name(day));
+                let answer = read_input()?;
+                if answer.is_empty() {
+                    break previous.clone();
+                }
+
+                match parse_ranges(&answer) {
+                    Ok(hours) => break hours,
+                    Err(e) => println!("{}", e),
+                }
+            };
+
+            if !hours.is_empty() {
+                weekly.insert(day, hours.clone());
+            }
+            previous = hours;
+        }
+
+        println!("Holiday closures or special hours for {}, one per line, e.g. \"2026-12-25 closed Christmas Day\" or \"2026-12-24..2026-12-31 10:00-14:00 Holiday hours\" (leave a blank line to finish):", name);
+        let mut exceptions = Vec::new();
+        loop {
+            let answer = read_input()?;
+            if answer.is_empty() {
+                break;
+            }
+
+            match Exception::parse(&answer) {
+                Ok(exception) => exceptions.push(exception),
+                Err(e) => println!("{}", e),
+            }
+        }
+
+        Ok(Some(Location {
+            name,
+            address: (!address.is_empty()).then_some(address),
+            weekly,
+            exceptions,
+        }))
+    }
+
+    fn describe(&self, today: NaiveDate) -> String {
+        let title = match &self.address {
+            Some(address) => format!("{} ({})", self.name, address),
+            None => self.name.clone(),
+        };
+
+        let weekly = WEEK
+            .iter()
+            .map(|day| format!("{} {}", day_name(*day), describe_ranges(self.weekly.get(day).map(Vec::as_slice).unwrap_or_default())))
+            .collect::<Vec<String>>()
+            .join("; ");
+
+        // Past exceptions no longer matter to customers
+        let upcoming: Vec<String> = self.exceptions
+            .iter()
+            .filter(|exception| exception.last_day() >= today)
+            .map(Exception::describe)
+            .collect();
+
+        if upcoming.is_empty() {
+            format!("{}: {}.", title, weekly)
+        } else {
+            format!("{}: {}. Exceptions: {}.", title, weekly, upcoming.join("; "))
+        }
+    }
+}
+
+// Opening hours for each of a business's locations, kept in opening_hours.json in its data directory
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct OpeningHours {
+    pub locations: Vec<Location>,
+}
+
+impl OpeningHours {
+    pub fn collect() -> Result<Self, AppError> {
+        let mut locations = Vec::new();
+        while let Some(location) = Location::collect()? {
+            locations.push(location);
+        }
+
+        Ok(OpeningHours { locations })
+    }
+
+    // The schedule as it goes into the system prompt, empty when the business gave no hours
+    pub fn prompt_section(&self, today: NaiveDate) -> String {
+        self.locations
+            .iter()
+            .map(|location| location.describe(today))
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+
+    // Worked out here rather than left to the model, which is unreliable at reading schedules
+    pub fn status_at<T: TimeZone>(&self, at: DateTime<T>) -> String {
+        let at = at.naive_local();
+
+        self.locations
+            .iter()
+            .map(|location| match (location.current_range(at), location.next_opening(at)) {
+                (Some(range), _) => format!("{} is open now, until {}.", location.name, range.close.format("%H:%M")),
+                (None, Some(opening)) => format!(
+                    "{} is closed now, it next opens {}.", location.name, opening.format("%A %-d %B at %H:%M")
+                ),
+                (None, None) => format!("{} is closed now, with no opening in the next {} days.", location.name, NEXT_OPENING_SEARCH_DAYS),
+            })
+            .collect::<Vec<String>>()
+            .join(" ")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono_tz::Europe::London;
+
+    // Weekdays 09:00-17:00 with a lunch break on Wednesday, late nights Friday into Saturday, 24 hours on Sunday
+    fn location(exceptions: &[&str]) -> Location {
+        Location {
+            name: "High Street".to_string(),
+            address: None,
+            weekly: HashMap::from([
+                (Weekday::Mon, parse_ranges("09:00-17:00").unwrap()),
+                (Weekday::Tue, parse_ranges("09:00-17:00").unwrap()),
+                (Weekday::Wed, parse_ranges("09:00-12:00, 13:00-17:00").unwrap()),
+                (Weekday::Thu, parse_ranges("09:00-17:00").unwrap()),
+                (Weekday::Fri, parse_ranges("18:00-02:00").unwrap()),
+                (Weekday::Sun, parse_ranges("00:00-00:00").unwrap()),
+            ]),
+            exceptions: exceptions.iter().map(|exception| Exception::parse(exception).unwrap()).collect(),
+        }
+    }
+
+    fn status(location: Location, at: &str) -> String {
+        let at = NaiveDateTime::parse_from_str(at, "%Y-%m-%d %H:%M").unwrap();
+        OpeningHours { locations: vec![location] }.status_at(London.from_local_datetime(&at).unwrap())
+    }
+
+    fn time(text: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(text, "%Y-%m-%d %H:%M").unwrap()
+    }
+
+    #[test]
+    fn ranges_parse_closed_overnight_and_all_day() {
+        assert!(parse_ranges("closed").unwrap().is_empty());
+        assert!(parse_ranges(" Closed ").unwrap().is_empty());
+
+        let split = parse_ranges("13:00-17:00 09:00-12:00").unwrap();
+        assert_eq!(split[0].open, NaiveTime::from_hms_opt(9, 0, 0).unwrap());
+        assert!(!split[0].runs_overnight());
+
+        let late = parse_ranges("18:00-02:00").unwrap();
+        assert!(late[0].runs_overnight());
+        assert_eq!(late[0].on(NaiveDate::from_ymd_opt(2026, 10, 16).unwrap()), (time("2026-10-16 18:00"), time("2026-10-17 02:00")));
+
+        let all_day = parse_ranges("00:00-00:00").unwrap();
+        assert_eq!(all_day[0].on(NaiveDate::from_ymd_opt(2026, 10, 18).unwrap()), (time("2026-10-18 00:00"), time("2026-10-19 00:00")));
+
+        assert!(parse_ranges("09:00-09:00").is_err());
+        assert!(parse_ranges("9am-5pm").is_err());
+        assert!(parse_ranges("").is_err());
+    }
+
+    // 2026-10-12 is a Monday
+    #[test]
+    fn status_says_whether_it_is_open_and_until_or_from_when() {
+        assert_eq!(status(location(&[]), "2026-10-12 10:00"), "High Street is open now, until 17:00.");
+        assert_eq!(status(location(&[]), "2026-10-12 17:00"), "High Street is closed now, it next opens Tuesday 13 October at 09:00.");
+        assert_eq!(status(location(&[]), "2026-10-14 12:30"), "High Street is closed now, it next opens Wednesday 14 October at 13:00.");
+        assert_eq!(status(location(&[]), "2026-10-15 18:00"), "High Street is closed now, it next opens Friday 16 October at 18:00.");
+    }
+
+    #[test]
+    fn overnight_hours_stay_open_past_midnight() {
+        assert_eq!(status(location(&[]), "2026-10-16 23:30"), "High Street is open now, until 02:00.");
+        assert_eq!(status(location(&[]), "2026-10-17 01:59"), "High Street is open now, until 02:00.");
+        assert_eq!(status(location(&[]), "2026-10-17 02:00"), "High Street is closed now, it next opens Sunday 18 October at 00:00.");
+        assert_eq!(status(location(&[]), "2026-10-18 23:59"), "High Street is open now, until 00:00.");
+
+        let location = location(&[]);
+        assert!(location.is_open_throughout(time("2026-10-16 23:00"), time("2026-10-17 01:00")));
+        assert!(location.is_open_throughout(time("2026-10-17 01:00"), time("2026-10-17 02:00")));
+        assert!(!location.is_open_throughout(time("2026-10-17 01:30"), time("2026-10-17 02:30")));
+        assert!(!location.is_open_throughout(time("2026-10-16 17:30"), time("2026-10-16 18:30")));
+        // Each opening is its own range, the lunch break isn't bridged
+        assert!(!location.is_open_throughout(time("2026-10-14 11:30"), time("2026-10-14 13:30")));
+    }
+
+    #[test]
+    fn holiday_closures_and_special_hours_replace_the_weekly_hours() {
+        let closed = location(&["2026-10-12..2026-10-13 closed Staff training"]);
+        assert_eq!(status(closed.clone(), "2026-10-12 10:00"), "High Street is closed now, it next opens Wednesday 14 October at 09:00.");
+        assert!(!closed.is_open_throughout(time("2026-10-13 10:00"), time("2026-10-13 11:00")));
+        assert!(closed.is_open_throughout(time("2026-10-14 10:00"), time("2026-10-14 11:00")));
+
+        let special = location(&["2026-10-12 10:00-14:00"]);
+        assert_eq!(status(special.clone(), "2026-10-12 09:30"), "High Street is closed now, it next opens Monday 12 October at 10:00.");
+        assert_eq!(status(special.clone(), "2026-10-12 13:00"), "High Street is open now, until 14:00.");
+        assert_eq!(status(special, "2026-10-12 15:00"), "High Street is closed now, it next opens Tuesday 13 October at 09:00.");
+
+        // The most recently added exception wins, and a late night on a closed Saturday still ends at its own close
+        let overridden = location(&["2026-10-12 closed", "2026-10-12 12:00-13:00 Open for collections"]);
+        assert_eq!(status(overridden, "2026-10-12 12:30"), "High Street is open now, until 13:00.");
+        let saturday_off = location(&["2026-10-17 closed"]);
+        assert_eq!(status(saturday_off, "2026-10-17 01:00"), "High Street is open now, until 02:00.");
+
+        assert!(Exception::parse("2026-12-31..2026-12-24 closed").is_err());
+        assert!(Exception::parse("25/12/2026 closed").is_err());
+    }
+}
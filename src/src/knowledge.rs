@@ -0,0 +1,813 @@
+use async_openai::types::{ChatCompletionRequestMessage, ChatCompletionRequestMessageArgs, Role};
+use regex::Regex;
+use serde_derive::{Serialize, Deserialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::LazyLock;
+
+use crate::embeddings::{cosine_similarity, fingerprint, VectorIndex};
+use crate::error::AppError;
+use crate::OpenAIHelper;
+
+// Starts the system message holding the excerpts for the current turn, so each turn can find and replace it
+const REFERENCE_MARKER: &str = "[REFERENCE]";
+// A paragraph or two, small enough that a few fit next to the conversation
+const CHUNK_WORDS: usize = 150;
+const EXTENSIONS: &[&str] = &["md", "markdown", "txt", "html", "htm", "csv"];
+
+// The usual BM25 parameters, how quickly repeats of a word stop counting and how much long chunks are penalised
+const K1: f64 = 1.2;
+const B: f64 = 0.75;
+
+const STOP_WORDS: &[&str] = &[
+    "a", "about", "an", "and", "any", "are", "as", "at", "be", "but", "by", "can", "could", "do", "does", "for",
+    "from", "get", "have", "how", "i", "if", "in", "is", "it", "its", "me", "my", "of", "on", "or", "our", "so",
+    "that", "the", "their", "there", "this", "to", "us", "was", "we", "what", "when", "where", "which", "who",
+    "will", "with", "would", "you", "your",
+];
+
+// Lowercase words without stop words, with a plural "s" or an "ing" taken off so "prices" finds "price" and
+// "parking" finds "park"
+fn terms(text: &str) -> Vec<String> {
+    text.to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| word.chars().count() > 1 && !STOP_WORDS.contains(word))
+        .map(|word| match (word.strip_suffix("ing"), word.strip_suffix('s')) {
+            (Some(stem), _) if stem.chars().count() > 2 => stem.to_string(),
+            (_, Some(stem)) if stem.chars().count() > 2 && !stem.ends_with('s') => stem.to_string(),
+            _ => word.to_string(),
+        })
+        .collect()
+}
+
+fn collapse_whitespace(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<&str>>().join(" ")
+}
+
+// Compiled on first use rather than on every page or line
+static ENTITY_PATTERN: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"&(#[0-9]+|#[xX][0-9a-fA-F]+|[a-zA-Z]+);").unwrap());
+static HIDDEN_PATTERN: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?is)<!--.*?-->|<head\b.*?</head>|<script\b.*?</script>|<style\b.*?</style>|<noscript\b.*?</noscript>|<svg\b.*?</svg>|<nav\b.*?</nav>|<footer\b.*?</footer>|<aside\b.*?</aside>|<form\b.*?</form>").unwrap()
+});
+static HEADING_PATTERN: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?is)<h[1-6]\b[^>]*>(.*?)</h[1-6]>").unwrap());
+static BLOCK_PATTERN: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?i)</?(p|div|section|article|main|header|li|ul|ol|tr|table|blockquote|dl|dt|dd|br|hr)\b[^>]*>").unwrap()
+});
+static CELL_PATTERN: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?i)</t[dh]>").unwrap());
+static TAG_PATTERN: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"<[^>]*>").unwrap());
+static TITLE_PATTERN: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?is)<title\b[^>]*>(.*?)</title>").unwrap());
+static LINK_PATTERN: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"!?\[([^\]]*)\]\(([^)\s]*)[^)]*\)").unwrap());
+static EMPHASIS_PATTERN: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\*\*|__|`").unwrap());
+
+fn decode_entities(text: &str) -> String {
+    ENTITY_PATTERN.replace_all(text, |captures: &regex::Captures| {
+        let name = &captures[1];
+        let decoded = match name {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            "nbsp" => Some(' '),
+            "pound" => Some('£'),
+            "euro" => Some('€'),
+            "hellip" => Some('…'),
+            "ndash" => Some('–'),
+            "mdash" => Some('—'),
+            "rsquo" | "lsquo" => Some('\''),
+            "rdquo" | "ldquo" => Some('"'),
+            _ => match name.strip_prefix("#x").or_else(|| name.strip_prefix("#X")) {
+                Some(hex) => u32::from_str_radix(hex, 16).ok().and_then(char::from_u32),
+                None => name.strip_prefix('#').and_then(|decimal| decimal.parse().ok()).and_then(char::from_u32),
+            },
+        };
+        decoded.map_or(captures[0].to_string(), String::from)
+    }).to_string()
+}
+
+// The readable text of a page. Scripts, styles and navigation go, headings come out as Markdown "# " lines and
+// other blocks as paragraphs, so the result can be read like a Markdown document.
+pub fn html_text(html: &str) -> String {
+    let text = HIDDEN_PATTERN.replace_all(html, " ");
+    let text = HEADING_PATTERN.replace_all(&text, |captures: &regex::Captures| {
+        format!("\n\n# {}\n\n", collapse_whitespace(&TAG_PATTERN.replace_all(&captures[1], " ")))
+    });
+    let text = BLOCK_PATTERN.replace_all(&text, "\n\n");
+    let text = CELL_PATTERN.replace_all(&text, " | ");
+    let text = decode_entities(&TAG_PATTERN.replace_all(&text, ""));
+
+    text.split("\n\n")
+        .map(|paragraph| collapse_whitespace(paragraph).trim_end_matches(" |").trim_end_matches('|').to_string())
+        .filter(|paragraph| !paragraph.is_empty())
+        .collect::<Vec<String>>()
+        .join("\n\n")
+}
+
+// The <title> of a page, if it has one
+pub fn html_title(html: &str) -> Option<String> {
+    TITLE_PATTERN.captures(html)
+        .map(|captures| collapse_whitespace(&decode_entities(&captures[1])))
+        .filter(|title| !title.is_empty())
+}
+
+// Text under one heading, split into paragraphs
+struct Section {
+    heading: Option<String>,
+    paragraphs: Vec<String>,
+}
+
+fn markdown_sections(markdown: &str) -> Vec<Section> {
+    let mut sections = vec![Section { heading: None, paragraphs: Vec::new() }];
+    let mut paragraph: Vec<String> = Vec::new();
+
+    let end_paragraph = |paragraph: &mut Vec<String>, sections: &mut Vec<Section>| {
+        if !paragraph.is_empty() {
+            let text = collapse_whitespace(&paragraph.join(" "));
+            sections.last_mut().unwrap().paragraphs.push(text);
+            paragraph.clear();
+        }
+    };
+
+    for line in markdown.lines() {
+        let line = LINK_PATTERN.replace_all(line, |captures: &regex::Captures| match (&captures[1], &captures[2]) {
+            (text, "") => text.to_string(),
+            ("", url) => url.to_string(),
+            (text, url) => format!("{} ({})", text, url),
+        });
+        let line = EMPHASIS_PATTERN.replace_all(&line, "");
+        let line = line.trim();
+
+        if let Some(heading) = line.strip_prefix('#') {
+            end_paragraph(&mut paragraph, &mut sections);
+            sections.push(Section { heading: Some(heading.trim_start_matches('#').trim().to_string()), paragraphs: Vec::new() });
+        } else if line.is_empty() || line.chars().all(|c| matches!(c, '-' | '=' | '*' | '_')) {
+            end_paragraph(&mut paragraph, &mut sections);
+        } else if line.starts_with(['-', '*', '+']) || line.split_once(". ").is_some_and(|(number, _)| number.parse::<u32>().is_ok()) {
+            // Each list item stands on its own line
+            end_paragraph(&mut paragraph, &mut sections);
+            paragraph.push(line.to_string());
+            end_paragraph(&mut paragraph, &mut sections);
+        } else {
+            paragraph.push(line.to_string());
+        }
+    }
+    end_paragraph(&mut paragraph, &mut sections);
+
+    sections.into_iter().filter(|section| !section.paragraphs.is_empty()).collect()
+}
+
+// Each row becomes a line of "column: value" pairs, so a chunk of rows still makes sense on its own
+fn csv_sections(path: &Path) -> Result<Vec<Section>, AppError> {
+    let invalid = |e: csv::Error| AppError::UserInput(format!("{}: {}", path.display(), e));
+
+    let mut reader = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .flexible(true)
+        .from_path(path)
+        .map_err(invalid)?;
+    let headers = reader.headers().map_err(invalid)?.clone();
+
+    let mut paragraphs = Vec::new();
+    for row in reader.records() {
+        let row = row.map_err(invalid)?;
+        let fields: Vec<String> = headers
+            .iter()
+            .zip(row.iter())
+            .filter(|(_, value)| !value.is_empty())
+            .map(|(header, value)| format!("{}: {}", header, value))
+            .collect();
+        if !fields.is_empty() {
+            paragraphs.push(fields.join(", "));
+        }
+    }
+
+    Ok(vec![Section { heading: None, paragraphs }])
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Chunk {
+    // "<document>-<n>", e.g. "price-list-2"
+    pub id: String,
+    pub heading: Option<String>,
+    pub text: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Document {
+    // Starts the IDs of its chunks
+    pub id: String,
+    // Where it was read from, adding the same file again replaces it
+    pub source: String,
+    // Of the file's contents when it was read, so a refresh only re-reads files that changed
+    #[serde(default)]
+    pub fingerprint: String,
+    pub title: String,
+    pub chunks: Vec<Chunk>,
+}
+
+fn chunk(document_id: &str, sections: Vec<Section>) -> Vec<Chunk> {
+    let mut chunks = Vec::new();
+
+    for section in sections {
+        let mut lines: Vec<String> = Vec::new();
+        let mut words = 0;
+        let mut finish = |lines: &mut Vec<String>, words: &mut usize| {
+            if !lines.is_empty() {
+                chunks.push(Chunk {
+                    id: format!("{}-{}", document_id, chunks.len() + 1),
+                    heading: section.heading.clone(),
+                    text: lines.join("\n"),
+                });
+                lines.clear();
+                *words = 0;
+            }
+        };
+
+        // Paragraphs stay whole where they fit, ones longer than a chunk are split between words
+        for paragraph in &section.paragraphs {
+            let paragraph_words: Vec<&str> = paragraph.split_whitespace().collect();
+            for piece in paragraph_words.chunks(CHUNK_WORDS) {
+                if words + piece.len() > CHUNK_WORDS {
+                    finish(&mut lines, &mut words);
+                }
+                lines.push(piece.join(" "));
+                words += piece.len();
+            }
+        }
+        finish(&mut lines, &mut words);
+    }
+
+    chunks
+}
+
+// Reads a Markdown, text, HTML or CSV file into chunks
+fn read_document(path: &Path, document_id: &str) -> Result<Document, AppError> {
+    let extension = path.extension().map(|extension| extension.to_string_lossy().to_lowercase()).unwrap_or_default();
+    let stem = path.file_stem().map_or(document_id.to_string(), |stem| stem.to_string_lossy().to_string());
+
+    let (title, sections) = match extension.as_str() {
+        "csv" => (stem, csv_sections(path)?),
+        "html" | "htm" => {
+            let html = std::fs::read_to_string(path)?;
+            (html_title(&html).unwrap_or(stem), markdown_sections(&html_text(&html)))
+        }
+        "md" | "markdown" => {
+            let markdown = std::fs::read_to_string(path)?;
+            let title = markdown.lines().find_map(|line| line.trim().strip_prefix('#')).map(|title| title.trim_start_matches('#').trim().to_string());
+            (title.filter(|title| !title.is_empty()).unwrap_or(stem), markdown_sections(&markdown))
+        }
+        // Paragraphs split at blank lines, same as Markdown
+        "txt" => (stem, markdown_sections(&std::fs::read_to_string(path)?)),
+        _ => return Err(AppError::UserInput(format!(
+            "{} isn't a document we can read, use one of: {}", path.display(), EXTENSIONS.join(", ")
+        ))),
+    };
+
+    Ok(Document {
+        id: document_id.to_string(),
+        source: path.display().to_string(),
+        fingerprint: fingerprint(&std::fs::read(path)?),
+        title,
+        chunks: chunk(document_id, sections),
+    })
+}
+
+// Every readable document in a directory and the directories under it, or the file itself
+fn document_paths(path: &Path) -> Result<Vec<PathBuf>, AppError> {
+    if !path.is_dir() {
+        return Ok(vec![path.to_path_buf()]);
+    }
+
+    let mut entries: Vec<PathBuf> = std::fs::read_dir(path)?.map(|entry| entry.map(|entry| entry.path())).collect::<Result<_, _>>()?;
+    entries.sort();
+
+    let mut paths = Vec::new();
+    for entry in entries {
+        if entry.is_dir() {
+            paths.extend(document_paths(&entry)?);
+        } else if entry.extension().is_some_and(|extension| EXTENSIONS.contains(&extension.to_string_lossy().to_lowercase().as_str())) {
+            paths.push(entry);
+        }
+    }
+    Ok(paths)
+}
+
+// Word counts for every chunk, rebuilt whenever the documents change
+#[derive(Default)]
+struct KeywordIndex {
+    // (document, chunk) positions, in the same order as the counts
+    positions: Vec<(usize, usize)>,
+    term_counts: Vec<HashMap<String, usize>>,
+    lengths: Vec<usize>,
+    // How many chunks each word appears in
+    chunk_frequency: HashMap<String, usize>,
+    average_length: f64,
+}
+
+impl KeywordIndex {
+    fn build(documents: &[Document]) -> Self {
+        let mut index = KeywordIndex::default();
+
+        for (document_index, document) in documents.iter().enumerate() {
+            for (chunk_index, chunk) in document.chunks.iter().enumerate() {
+                // The title and heading say what a chunk is about as much as its text does
+                let words = terms(&format!("{} {} {}", document.title, chunk.heading.as_deref().unwrap_or_default(), chunk.text));
+
+                let mut counts = HashMap::new();
+                for word in &words {
+                    *counts.entry(word.clone()).or_insert(0) += 1;
+                }
+                for word in counts.keys() {
+                    *index.chunk_frequency.entry(word.clone()).or_insert(0) += 1;
+                }
+
+                index.positions.push((document_index, chunk_index));
+                index.lengths.push(words.len());
+                index.term_counts.push(counts);
+            }
+        }
+
+        index.average_length = index.lengths.iter().sum::<usize>() as f64 / index.lengths.len().max(1) as f64;
+        index
+    }
+
+    // BM25 score of every chunk sharing a word with the query, best first
+    fn search(&self, query: &str) -> Vec<((usize, usize), f64)> {
+        let mut query_terms = terms(query);
+        query_terms.sort();
+        query_terms.dedup();
+
+        let chunk_count = self.positions.len() as f64;
+        let mut scores: Vec<((usize, usize), f64)> = self.term_counts
+            .iter()
+            .enumerate()
+            .map(|(index, counts)| {
+                let length_ratio = self.lengths[index] as f64 / self.average_length.max(1.0);
+                let score = query_terms.iter().filter_map(|term| {
+                    let frequency = *counts.get(term)? as f64;
+                    let containing = self.chunk_frequency[term] as f64;
+                    let rarity = ((chunk_count - containing + 0.5) / (containing + 0.5) + 1.0).ln();
+                    Some(rarity * frequency * (K1 + 1.0) / (frequency + K1 * (1.0 - B + B * length_ratio)))
+                }).sum::<f64>();
+                (self.positions[index], score)
+            })
+            .filter(|(_, score)| *score > 0.0)
+            .collect();
+
+        scores.sort_by(|a, b| b.1.total_cmp(&a.1));
+        scores
+    }
+}
+
+// A chunk the assistant said it used, kept with the reply so staff can check the answer against it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Citation {
+    pub chunk_id: String,
+    pub title: String,
+    pub source: String,
+}
+
+pub struct SearchHit<'a> {
+    pub document: &'a Document,
+    pub chunk: &'a Chunk,
+    pub score: f64,
+}
+
+// What gets embedded for a chunk, the title and heading say what it's about as much as its text does
+fn embedding_text(document: &Document, chunk: &Chunk) -> String {
+    format!("{}\n{}\n{}", document.title, chunk.heading.as_deref().unwrap_or_default(), chunk.text)
+}
+
+fn setting<T: std::str::FromStr>(name: &str, default: T) -> Result<T, AppError> {
+    match std::env::var(name) {
+        Ok(value) => value.parse().map_err(|_| AppError::Config(format!("{} '{}' is not a number", name, value))),
+        Err(_) => Ok(default),
+    }
+}
+
+fn citation_pattern() -> Regex {
+    Regex::new(r"[ \t]*\[\s*([\w-]+-\d+(?:\s*[,;]\s*[\w-]+-\d+)*)\s*\]").unwrap()
+}
+
+// The business's own documents, FAQs, menus, policies and price lists, kept chunked in knowledge.json in its
+// data directory. The chunks most relevant to each customer message go into the request alongside the prompt.
+pub struct KnowledgeBase {
+    path: PathBuf,
+    documents: Vec<Document>,
+    index: KeywordIndex,
+    // None when embeddings are turned off, search is then by keyword alone
+    vectors: Option<VectorIndex>,
+    // KNOWLEDGE_TOP_K, how many chunks go into each request
+    top_k: usize,
+    // KNOWLEDGE_SEMANTIC_WEIGHT, how much of a chunk's score comes from meaning rather than shared words
+    semantic_weight: f64,
+    // KNOWLEDGE_MIN_SIMILARITY, below this a chunk sharing no words with the message isn't considered at all.
+    // Unrelated text still scores around 0.7 with text-embedding-ada-002.
+    min_similarity: f32,
+    // A bracketed list of chunk IDs in a reply
+    citation_pattern: Regex,
+}
+
+impl KnowledgeBase {
+    pub fn open(path: &Path, embedding_model: Option<&str>) -> Result<Self, AppError> {
+        let documents: Vec<Document> = if path.exists() {
+            serde_json::from_str(&std::fs::read_to_string(path)?)
+                .map_err(|e| AppError::Config(format!("{}: {}", path.display(), e)))?
+        } else {
+            Vec::new()
+        };
+        let vectors = match embedding_model {
+            Some(model) => Some(VectorIndex::open(&path.with_file_name("knowledge_vectors.json"), model)?),
+            None => None,
+        };
+
+        Ok(KnowledgeBase {
+            path: path.to_path_buf(),
+            index: KeywordIndex::build(&documents),
+            documents,
+            vectors,
+            top_k: setting("KNOWLEDGE_TOP_K", 3)?,
+            semantic_weight: setting("KNOWLEDGE_SEMANTIC_WEIGHT", 0.7f64)?.clamp(0.0, 1.0),
+            min_similarity: setting("KNOWLEDGE_MIN_SIMILARITY", 0.8)?,
+            citation_pattern: citation_pattern(),
+        })
+    }
+
+    pub fn documents(&self) -> &[Document] {
+        &self.documents
+    }
+
+    fn save(&mut self) -> Result<(), AppError> {
+        self.index = KeywordIndex::build(&self.documents);
+
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(&self.path, serde_json::to_string_pretty(&self.documents)?)?;
+
+        if let Some(vectors) = &mut self.vectors {
+            let keys = self.documents
+                .iter()
+                .flat_map(|document| document.chunks.iter().map(|chunk| fingerprint(embedding_text(document, chunk).as_bytes())))
+                .collect();
+            vectors.retain(&keys);
+            vectors.save()?;
+        }
+
+        Ok(())
+    }
+
+    // Adds a file, or every readable file in a directory, replacing whatever was read from the same place before
+    pub fn add(&mut self, path: &Path) -> Result<Vec<&Document>, AppError> {
+        let mut added = Vec::new();
+
+        for path in document_paths(path)? {
+            let source = path.display().to_string();
+            let existing = self.documents.iter().position(|document| document.source == source);
+
+            // Chunk IDs are cited back to staff, so they're kept short and readable
+            let stem = crate::slug(&path.file_stem().unwrap_or_default().to_string_lossy());
+            let taken = |id: &str| self.documents.iter().enumerate().any(|(index, document)| Some(index) != existing && document.id == id);
+            let document_id = (1..)
+                .map(|n| if n == 1 { stem.clone() } else { format!("{}-{}", stem, n) })
+                .find(|id| !taken(id))
+                .unwrap();
+
+            let document = read_document(&path, &document_id)?;
+            let index = match existing {
+                Some(index) => {
+                    self.documents[index] = document;
+                    index
+                }
+                None => {
+                    self.documents.push(document);
+                    self.documents.len() - 1
+                }
+            };
+            added.push(index);
+        }
+
+        self.save()?;
+        Ok(added.into_iter().map(|index| &self.documents[index]).collect())
+    }
+
+    pub fn remove(&mut self, source: &str) -> Result<bool, AppError> {
+        let count = self.documents.len();
+        self.documents.retain(|document| document.source != source);
+        if self.documents.len() == count {
+            return Ok(false);
+        }
+
+        self.save()?;
+        Ok(true)
+    }
+
+    // Re-reads every document whose file changed since it was added and drops those whose file is gone,
+    // returning what changed. A changed file that can't be read keeps its previous version, so one bad file
+    // doesn't take the rest of the knowledge base with it.
+    pub fn refresh(&mut self) -> Result<Vec<String>, AppError> {
+        let mut changes = Vec::new();
+        let mut documents = Vec::with_capacity(self.documents.len());
+
+        for document in &self.documents {
+            let path = Path::new(&document.source);
+            let Ok(contents) = std::fs::read(path) else {
+                changes.push(format!("Removed {}, {} is no longer there", document.title, document.source));
+                continue;
+            };
+
+            if fingerprint(&contents) == document.fingerprint {
+                documents.push(document.clone());
+                continue;
+            }
+
+            match read_document(path, &document.id) {
+                Ok(updated) => {
+                    changes.push(format!("Updated {} ({} chunks)", updated.title, updated.chunks.len()));
+                    documents.push(updated);
+                }
+                Err(e) => {
+                    changes.push(format!("Kept the previous version of {}, {} could not be read: {}", document.title, document.source, e));
+                    documents.push(document.clone());
+                }
+            }
+        }
+
+        self.documents = documents;
+        self.index = KeywordIndex::build(&self.documents);
+
+        if !changes.is_empty() {
+            self.save()?;
+        }
+        Ok(changes)
+    }
+
+    // Embeds every chunk that doesn't have a vector yet, returning how many there were
+    pub async fn embed_missing(&mut self, openai_helper: &OpenAIHelper, session: &str) -> Result<usize, AppError> {
+        let Some(vectors) = &mut self.vectors else {
+            return Ok(0);
+        };
+
+        let mut missing = Vec::new();
+        for document in &self.documents {
+            for chunk in &document.chunks {
+                let text = embedding_text(document, chunk);
+                let key = fingerprint(text.as_bytes());
+                if vectors.get(&key).is_none() && !missing.iter().any(|(missing_key, _)| *missing_key == key) {
+                    missing.push((key, text));
+                }
+            }
+        }
+        if missing.is_empty() {
+            return Ok(0);
+        }
+
+        let texts: Vec<String> = missing.iter().map(|(_, text)| text.clone()).collect();
+        let embedded = openai_helper.embed(&texts, session).await?.unwrap_or_default();
+        let count = embedded.len();
+        for ((key, _), vector) in missing.into_iter().zip(embedded) {
+            vectors.insert(key, vector);
+        }
+        vectors.save()?;
+
+        Ok(count)
+    }
+
+    // Ranks chunks by both shared words (BM25) and meaning (cosine similarity of embeddings), falling back to
+    // words alone when there are no embeddings or the query can't be embedded
+    pub async fn search(&self, query: &str, limit: usize, openai_helper: &OpenAIHelper, session: &str) -> Result<Vec<SearchHit<'_>>, AppError> {
+        let query_vector = match &self.vectors {
+            Some(_) if !query.trim().is_empty() && !self.documents.is_empty() => {
+                match openai_helper.embed(&[query.to_string()], session).await {
+                    Ok(vectors) => vectors.and_then(|vectors| vectors.into_iter().next()),
+                    Err(e @ (AppError::LlmTransport(_) | AppError::LlmRequest(_) | AppError::LlmContent(_) | AppError::SpendCap(_))) => {
+                        eprintln!("Could not embed the message, searching by keyword only: {}", e);
+                        None
+                    }
+                    Err(e) => return Err(e),
+                }
+            }
+            _ => None,
+        };
+        let semantic_weight = if query_vector.is_some() { self.semantic_weight } else { 0.0 };
+
+        // BM25 scores have no fixed scale, the best match counts as 1
+        let keyword = self.index.search(query);
+        let best_keyword = keyword.first().map_or(1.0, |(_, score)| *score);
+        let mut scores: HashMap<(usize, usize), f64> = keyword
+            .into_iter()
+            .map(|(position, score)| (position, (1.0 - semantic_weight) * score / best_keyword))
+            .collect();
+
+        if let (Some(query_vector), Some(vectors)) = (&query_vector, &self.vectors) {
+            for (document_index, document) in self.documents.iter().enumerate() {
+                for (chunk_index, chunk) in document.chunks.iter().enumerate() {
+                    let Some(vector) = vectors.get(&fingerprint(embedding_text(document, chunk).as_bytes())) else {
+                        continue;
+                    };
+                    let similarity = cosine_similarity(query_vector, vector);
+                    let position = (document_index, chunk_index);
+
+                    if similarity >= self.min_similarity || scores.contains_key(&position) {
+                        *scores.entry(position).or_insert(0.0) += semantic_weight * similarity as f64;
+                    }
+                }
+            }
+        }
+
+        let mut ranked: Vec<((usize, usize), f64)> = scores.into_iter().collect();
+        ranked.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+
+        Ok(ranked
+            .into_iter()
+            .take(limit)
+            .map(|((document, chunk), score)| SearchHit {
+                document: &self.documents[document],
+                chunk: &self.documents[document].chunks[chunk],
+                score,
+            })
+            .collect())
+    }
+
+    // Puts the excerpts relevant to the customer's latest message right after the system prompt, replacing the
+    // previous turn's, or takes them out when nothing matches
+    pub async fn apply(&self, conversation: &mut Vec<ChatCompletionRequestMessage>, query: &str, openai_helper: &OpenAIHelper, session: &str) -> Result<(), AppError> {
+        let existing = conversation.iter().position(|message| {
+            message.role == Role::System
+                && message.content.as_deref().is_some_and(|content| content.starts_with(REFERENCE_MARKER))
+        });
+
+        let hits = self.search(query, self.top_k, openai_helper, session).await?;
+        if hits.is_empty() {
+            if let Some(index) = existing {
+                conversation.remove(index);
+            }
+            return Ok(());
+        }
+
+        let excerpts: Vec<String> = hits
+            .iter()
+            .map(|hit| match &hit.chunk.heading {
+                Some(heading) if *heading != hit.document.title => format!("[{}] {} - {}:\n{}", hit.chunk.id, hit.document.title, heading, hit.chunk.text),
+                _ => format!("[{}] {}:\n{}", hit.chunk.id, hit.document.title, hit.chunk.text),
+            })
+            .collect();
+        let message = ChatCompletionRequestMessageArgs::default()
+            .role(Role::System)
+            .content(format!(
+                "{} Excerpts from the business's own documents that may help with the customer's latest message. Use them where they answer it, they're more up to date than anything else you've been told. Don't make up details they don't give. Straight after anything you take from an excerpt, put its ID in square brackets, e.g. [{}]. The customer won't see these.\n\n{}",
+                REFERENCE_MARKER, hits[0].chunk.id, excerpts.join("\n\n")
+            ))
+            .build()?;
+
+        match existing {
+            Some(index) => conversation[index] = message,
+            None => conversation.insert(1.min(conversation.len()), message),
+        }
+
+        Ok(())
+    }
+
+    // Takes the chunk IDs the model cited out of its reply, e.g. "[faq-1]" or "[faq-1, prices-2]". Brackets are
+    // only removed when everything in them looks like one of our chunk IDs, made up chunk numbers included, but
+    // only chunks that exist are kept as citations.
+    pub fn take_citations(&self, reply: &str) -> (String, Vec<Citation>) {
+        let is_ours = |id: &str| id.rsplit_once('-').is_some_and(|(document_id, _)| self.documents.iter().any(|document| document.id == document_id));
+
+        let mut citations: Vec<Citation> = Vec::new();
+        let stripped = self.citation_pattern.replace_all(reply, |captures: &regex::Captures| {
+            let ids: Vec<&str> = captures[1].split([',', ';']).map(str::trim).collect();
+            if !ids.iter().all(|id| is_ours(id)) {
+                return captures[0].to_string();
+            }
+
+            for id in ids {
+                let found = self.documents.iter().find_map(|document| {
+                    document.chunks.iter().find(|chunk| chunk.id == id).map(|chunk| (document, chunk))
+                });
+                match found {
+                    Some((document, chunk)) if !citations.iter().any(|citation| citation.chunk_id == chunk.id) => {
+                        citations.push(Citation { chunk_id: chunk.id.clone(), title: document.title.clone(), source: document.source.clone() });
+                    }
+                    Some(_) => {}
+                    None => eprintln!("The reply cited {}, which isn't in the knowledge base", id),
+                }
+            }
+            String::new()
+        });
+
+        (stripped.to_string(), citations)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn document(id: &str, title: &str, markdown: &str) -> Document {
+        Document {
+            id: id.to_string(),
+            source: format!("{}.md", id),
+            fingerprint: String::new(),
+            title: title.to_string(),
+            chunks: chunk(id, markdown_sections(markdown)),
+        }
+    }
+
+    fn knowledge_base(documents: Vec<Document>) -> KnowledgeBase {
+        KnowledgeBase {
+            path: PathBuf::new(),
+            index: KeywordIndex::build(&documents),
+            documents,
+            vectors: None,
+            top_k: 3,
+            semantic_weight: 0.7,
+            min_similarity: 0.8,
+            citation_pattern: citation_pattern(),
+        }
+    }
+
+    fn salon() -> KnowledgeBase {
+        knowledge_base(vec![
+            document("faq", "Salon FAQ", "# Parking\nParking is free behind the salon.\n\n# Cancellations\nPlease give 24 hours' notice if you need to cancel."),
+            document("notes", "Notes", "Dogs are welcome in the salon.\n\nWe have a long list of colour products, colour charts, colour consultations and colour treatments on offer for every hair type and length."),
+        ])
+    }
+
+    fn chunk_ids(knowledge: &KnowledgeBase, query: &str) -> Vec<String> {
+        knowledge.index.search(query)
+            .into_iter()
+            .map(|((document, chunk), _)| knowledge.documents[document].chunks[chunk].id.clone())
+            .collect()
+    }
+
+    #[test]
+    fn terms_drop_stop_words_and_endings() {
+        assert_eq!(terms("Where can I park? What are the prices for parking"), ["park", "price", "park"]);
+        assert_eq!(terms("Is the class a pass"), ["class", "pass"]);
+    }
+
+    #[test]
+    fn markdown_is_chunked_by_heading() {
+        let faq = document("faq", "Salon FAQ", "# Parking\nParking is free\nbehind the salon.\n\n- Car park\n- Street");
+        assert_eq!(faq.chunks.len(), 1);
+        assert_eq!(faq.chunks[0].id, "faq-1");
+        assert_eq!(faq.chunks[0].heading.as_deref(), Some("Parking"));
+        assert_eq!(faq.chunks[0].text, "Parking is free behind the salon.\n- Car park\n- Street");
+
+        let long = document("long", "Long", &"word ".repeat(CHUNK_WORDS + 10));
+        assert_eq!(long.chunks.len(), 2);
+    }
+
+    #[test]
+    fn keyword_search_ranks_by_shared_words() {
+        let knowledge = salon();
+
+        assert_eq!(chunk_ids(&knowledge, "Is there parking?"), ["faq-1"]);
+        assert_eq!(chunk_ids(&knowledge, "How do I cancel? How much notice?"), ["faq-2"]);
+        assert!(chunk_ids(&knowledge, "opening times").is_empty());
+    }
+
+    #[test]
+    fn keyword_search_weighs_rare_words_and_short_chunks() {
+        let knowledge = salon();
+
+        // "salon" is in most chunks and "dogs" in one, so the chunk with "dogs" comes first
+        assert_eq!(chunk_ids(&knowledge, "dogs in the salon").first().map(String::as_str), Some("notes-1"));
+
+        // Repeating a word counts for less and less, a long chunk saying "colour" four times only just beats a short
+        // one saying it once
+        let colour = knowledge_base(vec![
+            document("short", "Short", "Colour from £40."),
+            document("long", "Long", "We have a long list of colour products, colour charts, colour consultations and colour treatments on offer for every hair type and length."),
+            document("other", "Other", "Parking is free."),
+        ]);
+        let scores = colour.index.search("colour");
+        assert_eq!(scores.iter().map(|(position, _)| *position).collect::<Vec<_>>(), [(1, 0), (0, 0)]);
+        assert!(scores[0].1 < scores[1].1 * 1.5);
+    }
+
+    #[test]
+    fn citations_are_taken_out_of_the_reply() {
+        let knowledge = salon();
+
+        let (reply, citations) = knowledge.take_citations("Parking is free behind the salon [faq-1]. Please give 24 hours' notice [faq-2, notes-9].");
+        assert_eq!(reply, "Parking is free behind the salon. Please give 24 hours' notice.");
+        // notes-9 looks like ours but doesn't exist, so it's dropped from the reply without being kept
+        assert_eq!(citations.iter().map(|citation| citation.chunk_id.as_str()).collect::<Vec<_>>(), ["faq-1", "faq-2"]);
+        assert_eq!((citations[0].title.as_str(), citations[0].source.as_str()), ("Salon FAQ", "faq.md"));
+    }
+
+    #[test]
+    fn only_our_chunk_ids_are_taken() {
+        let knowledge = salon();
+
+        let (reply, citations) = knowledge.take_citations("See section [10-2] and [other-1]. Dogs are welcome [ notes-1 ; faq-1 ] [notes-1].");
+        assert_eq!(reply, "See section [10-2] and [other-1]. Dogs are welcome.");
+        assert_eq!(citations.iter().map(|citation| citation.chunk_id.as_str()).collect::<Vec<_>>(), ["notes-1", "faq-1"]);
+
+        let (reply, citations) = knowledge.take_citations("A haircut is £30 [see price list].");
+        assert_eq!(reply, "A haircut is £30 [see price list].");
+        assert!(citations.is_empty());
+    }
+}
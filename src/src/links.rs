@@ -0,0 +1,282 @@
+use regex::{Captures, Regex};
+use serde_derive::{Serialize, Deserialize};
+
+use crate::error::AppError;
+use crate::read_input;
+
+// Numbers with fewer digits are more likely dates, prices or order numbers than phone numbers
+const MIN_PHONE_DIGITS: usize = 9;
+
+fn digits(text: &str) -> String {
+    text.chars().filter(char::is_ascii_digit).collect()
+}
+
+// "https://www.example.com/book/" and "example.com/book" are the same link as far as we're concerned
+fn normalise_url(url: &str) -> String {
+    let url = url.to_lowercase();
+    let url = url.split_once("://").map(|(_, rest)| rest.to_string()).unwrap_or(url);
+    url.trim_start_matches("www.").trim_end_matches('/').to_string()
+}
+
+fn split_list(text: &str) -> Vec<String> {
+    text.split(',').map(|entry| entry.trim().to_string()).filter(|entry| !entry.is_empty()).collect()
+}
+
+// The websites, email addresses and phone numbers the assistant may give out, kept in contacts.json in the
+// business's data directory
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ContactAllowlist {
+    // A domain allows every page on it and its subdomains, a link allows itself and anything under it
+    pub urls: Vec<String>,
+    // "@example.com" allows any address at that domain
+    pub emails: Vec<String>,
+    pub phones: Vec<String>,
+}
+
+impl ContactAllowlist {
+    pub fn collect() -> Result<Self, AppError> {
+        println!("Which websites can the assistant link customers to? Separate them with commas (e.g. \"example.com, bookings.example.com/salon\"):");
+        let urls = split_list(&read_input()?);
+
+        println!("Which email addresses can it give out? Separate them with commas, \"@example.com\" allows any address at example.com:");
+        let emails = split_list(&read_input()?);
+
+        println!("Which phone numbers can it give out? Separate them with commas:");
+        let phones = split_list(&read_input()?);
+
+        Ok(ContactAllowlist { urls, emails, phones })
+    }
+
+    pub fn allows_url(&self, url: &str) -> bool {
+        let url = normalise_url(url);
+        let host = url.split(['/', '?', '#']).next().unwrap_or_default();
+
+        self.urls.iter().map(|allowed| normalise_url(allowed)).any(|allowed| {
+            if allowed.contains('/') {
+                url == allowed || url.starts_with(&format!("{}/", allowed))
+            } else {
+                host == allowed || host.ends_with(&format!(".{}", allowed))
+            }
+        })
+    }
+
+    pub fn allows_email(&self, email: &str) -> bool {
+        let email = email.to_lowercase();
+
+        self.emails.iter().map(|allowed| allowed.to_lowercase()).any(|allowed| {
+            if allowed.starts_with('@') {
+                email.ends_with(&allowed)
+            } else {
+                email == allowed
+            }
+        })
+    }
+
+    // Compared on digits with leading zeros dropped, so "+44 20 7946 0958" matches "020 7946 0958"
+    pub fn allows_phone(&self, phone: &str) -> bool {
+        let phone = digits(phone);
+        let phone = phone.trim_start_matches('0');
+
+        self.phones.iter().map(|allowed| digits(allowed)).any(|allowed| {
+            let allowed = allowed.trim_start_matches('0');
+            let (shorter, longer) = if phone.len() < allowed.len() { (phone, allowed) } else { (allowed, phone) };
+            shorter.len() >= MIN_PHONE_DIGITS - 1 && longer.ends_with(shorter)
+        })
+    }
+
+    // Told to the model up front, so it has something to give out instead of making it up
+    pub fn prompt_section(&self) -> String {
+        [("Websites", &self.urls), ("Email addresses", &self.emails), ("Phone numbers", &self.phones)]
+            .iter()
+            .filter(|(_, entries)| !entries.is_empty())
+            .map(|(kind, entries)| format!("{}: {}", kind, entries.join(", ")))
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FilterMode {
+    // Replace with a short placeholder
+    Redact,
+    // Replace with the business's own website, email or number where it has one
+    Rewrite,
+}
+
+pub struct FilteredReply {
+    pub content: String,
+    pub removed: Vec<String>,
+}
+
+// Takes links, email addresses and phone numbers the business didn't allow out of assistant replies
+pub struct LinkFilter {
+    allowlist: ContactAllowlist,
+    mode: FilterMode,
+    // One pattern for all of them, so an email's domain isn't also caught as a link
+    pattern: Regex,
+    // Dates and times are runs of digits too
+    date_pattern: Regex,
+}
+
+impl LinkFilter {
+    // LINK_FILTER_MODE is "redact" or "rewrite", default rewrite
+    pub fn new(allowlist: ContactAllowlist) -> Result<Self, AppError> {
+        let mode = match std::env::var("LINK_FILTER_MODE").as_deref() {
+            Ok("redact") => FilterMode::Redact,
+            Ok("rewrite") | Err(_) => FilterMode::Rewrite,
+            Ok(other) => return Err(AppError::Config(format!("Unknown LINK_FILTER_MODE '{}'", other))),
+        };
+
+        let pattern = Regex::new(concat!(
+            r"(?i)\[(?P<text>[^\]]+)\]\((?P<target>[^)\s]+)\)",
+            r"|(?P<email>\b[a-z0-9._%+-]+@[a-z0-9.-]+\.[a-z]{2,}\b)",
+            r"|(?P<url>\b(?:https?://|www\.)[^\s<>()\[\]]*[^\s<>()\[\].,;:!?'\x22]",
+            // Bare domains only with common endings, or "e.g." and "node.js" would count as links
+            r"|\b[a-z0-9-]+(?:\.[a-z0-9-]+)*\.(?:com|org|net|io|co|uk|ie|info|biz|app|shop|us|eu|de|fr|ca|au)\b(?:/[^\s<>()\[\]]*[^\s<>()\[\].,;:!?'\x22])?)",
+            r"|(?P<phone>\+?\(?\d[\d\s().-]{6,}\d)",
+        )).unwrap();
+
+        Ok(LinkFilter {
+            allowlist,
+            mode,
+            pattern,
+            date_pattern: Regex::new(r"\d{4}-\d{1,2}-\d{1,2}|\d{1,2}/\d{1,2}/\d{2,4}").unwrap(),
+        })
+    }
+
+    fn replacement(&self, allowed: &[String], placeholder: &str) -> String {
+        // "@example.com" allows addresses, it isn't one
+        match (self.mode, allowed.iter().find(|own| !own.starts_with('@'))) {
+            (FilterMode::Rewrite, Some(own)) => own.clone(),
+            _ => placeholder.to_string(),
+        }
+    }
+
+    // The customer's own contact details can always be said back to them
+    pub fn filter(&self, reply: &str, customer_contacts: &[String]) -> FilteredReply {
+        let mut removed = Vec::new();
+        let customer = ContactAllowlist {
+            emails: customer_contacts.to_vec(),
+            phones: customer_contacts.to_vec(),
+            ..ContactAllowlist::default()
+        };
+
+        let content = self.pattern.replace_all(reply, |captures: &Captures| {
+            let found = captures[0].to_string();
+
+            let replacement = if let (Some(text), Some(target)) = (captures.name("text"), captures.name("target")) {
+                // Markdown links keep their text, there's no sensible way to rewrite where they point
+                (!self.allowlist.allows_url(target.as_str())).then(|| text.as_str().to_string())
+            } else if captures.name("email").is_some() {
+                (!self.allowlist.allows_email(&found) && !customer.allows_email(&found)).then(|| self.replacement(&self.allowlist.emails, "[email removed]"))
+            } else if captures.name("url").is_some() {
+                (!self.allowlist.allows_url(&found)).then(|| self.replacement(&self.allowlist.urls, "[link removed]"))
+            } else {
+                let is_time = reply[captures.get(0).map_or(reply.len(), |m| m.end())..].starts_with(':');
+                let is_phone = digits(&found).len() >= MIN_PHONE_DIGITS && !is_time && !self.date_pattern.is_match(&found);
+                (is_phone && !self.allowlist.allows_phone(&found) && !customer.allows_phone(&found))
+                    .then(|| self.replacement(&self.allowlist.phones, "[number removed]"))
+            };
+
+            match replacement {
+                Some(replacement) => {
+                    removed.push(found);
+                    replacement
+                }
+                None => found,
+            }
+        });
+
+        FilteredReply {
+            content: content.into_owned(),
+            removed,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn allowlist() -> ContactAllowlist {
+        ContactAllowlist {
+            urls: vec!["example.com".to_string(), "bookings.partner.co.uk/salon".to_string()],
+            emails: vec!["hello@example.com".to_string(), "@staff.example.com".to_string()],
+            phones: vec!["020 7946 0958".to_string()],
+        }
+    }
+
+    fn filter(mode: FilterMode) -> LinkFilter {
+        LinkFilter { mode, ..LinkFilter::new(allowlist()).unwrap() }
+    }
+
+    fn redacted(reply: &str) -> String {
+        filter(FilterMode::Redact).filter(reply, &[]).content
+    }
+
+    #[test]
+    fn allowed_links_stay_and_others_are_removed() {
+        let allowed = [
+            "Book at https://www.example.com/book/.",
+            "See example.com or shop.example.com/gifts",
+            "Partners: https://bookings.partner.co.uk/salon/123",
+        ];
+        for reply in allowed {
+            assert_eq!(redacted(reply), reply);
+        }
+
+        assert_eq!(redacted("Try https://evil.com/deal, it's cheaper."), "Try [link removed], it's cheaper.");
+        assert_eq!(redacted("Also www.example.com.evil.net works"), "Also [link removed] works");
+        assert_eq!(redacted("The other page bookings.partner.co.uk/other"), "The other page [link removed]");
+        // Not links, though they have dots in them
+        assert_eq!(redacted("We use node.js, e.g. for the site."), "We use node.js, e.g. for the site.");
+
+        let rewritten = filter(FilterMode::Rewrite).filter("Visit evil.com today!", &[]);
+        assert_eq!(rewritten.content, "Visit example.com today!");
+        assert_eq!(rewritten.removed, vec!["evil.com"]);
+    }
+
+    #[test]
+    fn allowed_emails_stay_and_others_are_removed() {
+        assert_eq!(redacted("Email Hello@Example.com or jo@staff.example.com."), "Email Hello@Example.com or jo@staff.example.com.");
+        assert_eq!(redacted("Email bookings@gmail.com instead."), "Email [email removed] instead.");
+        // An email's domain isn't filtered again as a link
+        assert_eq!(redacted("Write to someone@evil.com"), "Write to [email removed]");
+
+        // "@staff.example.com" isn't an address to rewrite to, so the first real one is used
+        assert_eq!(filter(FilterMode::Rewrite).filter("Email sam@gmail.com", &[]).content, "Email hello@example.com");
+        // The customer's own address can be read back to them
+        assert_eq!(filter(FilterMode::Redact).filter("We'll write to sam@gmail.com", &["sam@gmail.com".to_string()]).content, "We'll write to sam@gmail.com");
+    }
+
+    #[test]
+    fn allowed_phone_numbers_stay_and_others_are_removed() {
+        assert_eq!(redacted("Call +44 20 7946 0958 or (020) 7946-0958."), "Call +44 20 7946 0958 or (020) 7946-0958.");
+        assert_eq!(redacted("Call 07700 900123 for offers."), "Call [number removed] for offers.");
+        assert_eq!(filter(FilterMode::Rewrite).filter("Call 07700 900123", &[]).content, "Call 020 7946 0958");
+        assert_eq!(filter(FilterMode::Redact).filter("We'll text 07700 900123", &["+44 7700 900123".to_string()]).content, "We'll text 07700 900123");
+    }
+
+    #[test]
+    fn times_dates_and_short_numbers_are_not_phone_numbers() {
+        let replies = [
+            "We're open 09:00 - 17:00 and 18:00-21:30.",
+            "Your booking is on 2026-10-12 at 10:30.",
+            "That was 12/10/2026, order 123456.",
+            "A haircut is £25 and takes 30 minutes.",
+            "Between 10 30 and 11 45",
+        ];
+        for reply in replies {
+            assert_eq!(redacted(reply), reply);
+        }
+    }
+
+    #[test]
+    fn markdown_links_keep_their_text() {
+        assert_eq!(redacted("[Book online](https://example.com/book) now"), "[Book online](https://example.com/book) now");
+
+        let filtered = filter(FilterMode::Rewrite).filter("[Cheaper here](https://evil.com/deal) and [ours](example.com)", &[]);
+        assert_eq!(filtered.content, "Cheaper here and [ours](example.com)");
+        assert_eq!(filtered.removed, vec!["[Cheaper here](https://evil.com/deal)"]);
+    }
+}
@@ -0,0 +1,361 @@
+use async_openai::error::{ApiError, OpenAIError};
+use async_openai::types::{CreateChatCompletionRequest, CreateChatCompletionResponse, CreateEmbeddingRequest, CreateEmbeddingResponse};
+use rand::Rng;
+use reqwest::header::{HeaderMap, RETRY_AFTER};
+use reqwest::StatusCode;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_derive::Deserialize;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::error::AppError;
+
+const DEFAULT_API_BASE: &str = "https://api.openai.com/v1";
+
+#[derive(Debug, Deserialize)]
+struct WrappedError {
+    error: ApiError,
+}
+
+struct Failure {
+    error: OpenAIError,
+    retryable: bool,
+    // The API refused the request as it was, no provider will take it either
+    rejected: bool,
+    retry_after: Option<Duration>,
+}
+
+impl Failure {
+    // Network failures and timeouts are always worth another go
+    fn transport(error: reqwest::Error) -> Self {
+        Failure { error: OpenAIError::Reqwest(error), retryable: true, rejected: false, retry_after: None }
+    }
+}
+
+// Reads an unsigned setting for a provider, e.g. LLM_OPENAI_MAX_ATTEMPTS, then the shared LLM_MAX_ATTEMPTS,
+// falling back to the default when unset or malformed
+fn env_setting(provider: &str, name: &str, default: u64) -> u64 {
+    [format!("LLM_{}_{}", provider.to_uppercase(), name), format!("LLM_{}", name)]
+        .iter()
+        .find_map(|key| std::env::var(key).ok().and_then(|value| value.parse().ok()))
+        .unwrap_or(default)
+}
+
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    pub max_attempts: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+    pub timeout: Duration,
+}
+
+impl RetryPolicy {
+    // MAX_ATTEMPTS, RETRY_BASE_MS, RETRY_MAX_MS and TIMEOUT_SECS override the defaults
+    pub fn from_env(provider: &str) -> Self {
+        RetryPolicy {
+            max_attempts: env_setting(provider, "MAX_ATTEMPTS", 4).max(1) as u32,
+            base_delay: Duration::from_millis(env_setting(provider, "RETRY_BASE_MS", 500)),
+            max_delay: Duration::from_millis(env_setting(provider, "RETRY_MAX_MS", 30_000)),
+            timeout: Duration::from_secs(env_setting(provider, "TIMEOUT_SECS", 60)),
+        }
+    }
+
+    // Exponential backoff with full jitter, attempt starts at 1
+    pub fn delay_for(&self, attempt: u32) -> Duration {
+        let exponential = self.base_delay.saturating_mul(2u32.saturating_pow(attempt - 1)).min(self.max_delay);
+        let jittered = rand::thread_rng().gen_range(0..=exponential.as_millis() as u64);
+
+        Duration::from_millis(jittered)
+    }
+
+    // Whether a failed attempt gets another go, a server asking us to wait longer than we're willing to is treated
+    // like any other hard failure
+    fn should_retry(&self, failure: &Failure, attempt: u32) -> bool {
+        let waits_too_long = failure.retry_after.is_some_and(|delay| delay > self.max_delay);
+
+        failure.retryable && !failure.rejected && !waits_too_long && attempt < self.max_attempts
+    }
+}
+
+// Client-side token bucket, each provider has one that every session shares so together they stay under its limit
+pub struct RateLimiter {
+    capacity: f64,
+    refill_per_second: f64,
+    state: Mutex<(f64, Instant)>,
+}
+
+impl RateLimiter {
+    pub fn new(requests_per_minute: u64, burst: u64) -> Self {
+        let capacity = burst.max(1) as f64;
+
+        RateLimiter {
+            capacity,
+            refill_per_second: requests_per_minute.max(1) as f64 / 60.0,
+            state: Mutex::new((capacity, Instant::now())),
+        }
+    }
+
+    // REQUESTS_PER_MINUTE and BURST override the defaults
+    pub fn from_env(provider: &str) -> Self {
+        RateLimiter::new(env_setting(provider, "REQUESTS_PER_MINUTE", 60), env_setting(provider, "BURST", 5))
+    }
+
+    // Waits until a request may be sent
+    pub async fn acquire(&self) {
+        loop {
+            let wait = {
+                let mut state = self.state.lock().unwrap();
+                let (tokens, last_refill) = &mut *state;
+
+                let now = Instant::now();
+                *tokens = (*tokens + now.duration_since(*last_refill).as_secs_f64() * self.refill_per_second).min(self.capacity);
+                *last_refill = now;
+
+                if *tokens >= 1.0 {
+                    *tokens -= 1.0;
+                    return;
+                }
+
+                Duration::from_secs_f64((1.0 - *tokens) / self.refill_per_second)
+            };
+
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
+// Retry-After is either a number of seconds or an HTTP date
+fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let value = headers.get(RETRY_AFTER)?.to_str().ok()?.trim();
+
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+
+    let date = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+    (date.with_timezone(&chrono::Utc) - chrono::Utc::now()).to_std().ok()
+}
+
+fn is_retryable_status(status: StatusCode, error: &ApiError) -> bool {
+    // 429 also means "You exceeded your current quota", waiting won't help with that one
+    (status == StatusCode::TOO_MANY_REQUESTS && error.r#type.as_deref() != Some("insufficient_quota"))
+        || status.is_server_error()
+}
+
+// 4xx other than 429 is about the request, e.g. 400 context_length_exceeded or 401. A 429, even for the quota, is
+// about this provider's account and another provider may still take it.
+fn is_rejected_status(status: StatusCode) -> bool {
+    status.is_client_error() && status != StatusCode::TOO_MANY_REQUESTS
+}
+
+// Talks to an OpenAI-compatible API, retrying transient failures
+pub struct LlmClient {
+    http_client: reqwest::Client,
+    api_base: String,
+    api_key: Option<String>,
+    retry_policy: RetryPolicy,
+    rate_limiter: Arc<RateLimiter>,
+}
+
+impl LlmClient {
+    pub fn new(api_base: &str, api_key: Option<String>, retry_policy: RetryPolicy, rate_limiter: Arc<RateLimiter>) -> Self {
+        LlmClient {
+            http_client: reqwest::Client::new(),
+            api_base: api_base.trim_end_matches('/').to_string(),
+            api_key,
+            retry_policy,
+            rate_limiter,
+        }
+    }
+
+    // The "openai" provider reads OPENAI_API_KEY and OPENAI_API_BASE, any other provider reads
+    // LLM_<NAME>_API_BASE (required) and LLM_<NAME>_API_KEY (optional, local models rarely need one)
+    pub fn from_env(provider: &str) -> Result<Self, AppError> {
+        let (api_base, api_key) = if provider == "openai" {
+            let api_key = std::env::var("OPENAI_API_KEY")
+                .map_err(|_| AppError::Config("OPENAI_API_KEY is not set".to_string()))?;
+            let api_base = std::env::var("OPENAI_API_BASE").unwrap_or_else(|_| DEFAULT_API_BASE.to_string());
+
+            (api_base, Some(api_key))
+        } else {
+            let prefix = format!("LLM_{}", provider.to_uppercase());
+            let api_base = std::env::var(format!("{}_API_BASE", prefix))
+                .map_err(|_| AppError::Config(format!("{}_API_BASE is not set", prefix)))?;
+
+            (api_base, std::env::var(format!("{}_API_KEY", prefix)).ok())
+        };
+
+        Ok(LlmClient::new(&api_base, api_key, RetryPolicy::from_env(provider), Arc::new(RateLimiter::from_env(provider))))
+    }
+
+    pub async fn create_chat(&self, request: &CreateChatCompletionRequest) -> Result<CreateChatCompletionResponse, AppError> {
+        self.call("chat/completions", request).await
+    }
+
+    pub async fn create_embedding(&self, request: &CreateEmbeddingRequest) -> Result<CreateEmbeddingResponse, AppError> {
+        self.call("embeddings", request).await
+    }
+
+    async fn call<Request: Serialize, Response: DeserializeOwned>(&self, endpoint: &str, request: &Request) -> Result<Response, AppError> {
+        let mut attempt = 1;
+
+        loop {
+            self.rate_limiter.acquire().await;
+
+            let failure = match self.send(endpoint, request).await {
+                Ok(response) => return Ok(response),
+                Err(failure) => failure,
+            };
+
+            if failure.rejected {
+                return Err(AppError::LlmRequest(failure.error.to_string()));
+            }
+            if !self.retry_policy.should_retry(&failure, attempt) {
+                return Err(failure.error.into());
+            }
+
+            let delay = failure.retry_after.unwrap_or_else(|| self.retry_policy.delay_for(attempt));
+            eprintln!("LLM request failed ({}), retrying in {:.1}s", failure.error, delay.as_secs_f64());
+
+            tokio::time::sleep(delay).await;
+            attempt += 1;
+        }
+    }
+
+    async fn send<Request: Serialize, Response: DeserializeOwned>(&self, endpoint: &str, request: &Request) -> Result<Response, Failure> {
+        let mut builder = self.http_client
+            .post(format!("{}/{}", self.api_base, endpoint))
+            .timeout(self.retry_policy.timeout)
+            .json(request);
+
+        if let Some(api_key) = &self.api_key {
+            builder = builder.bearer_auth(api_key);
+        }
+
+        let response = builder.send().await.map_err(Failure::transport)?;
+
+        let status = response.status();
+        let headers = response.headers().clone();
+        let bytes = response.bytes().await.map_err(Failure::transport)?;
+
+        if !status.is_success() {
+            let error = serde_json::from_slice::<WrappedError>(&bytes)
+                .map(|wrapped| wrapped.error)
+                .unwrap_or_else(|_| ApiError {
+                    message: format!("{}: {}", status, String::from_utf8_lossy(&bytes)),
+                    r#type: None,
+                    param: None,
+                    code: None,
+                });
+
+            return Err(Failure {
+                retryable: is_retryable_status(status, &error),
+                rejected: is_rejected_status(status),
+                retry_after: retry_after(&headers),
+                error: OpenAIError::ApiError(error),
+            });
+        }
+
+        serde_json::from_slice(&bytes).map_err(|e| Failure {
+            error: OpenAIError::JSONDeserialize(e),
+            retryable: false,
+            rejected: false,
+            retry_after: None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+
+    fn api_error(r#type: Option<&str>) -> ApiError {
+        ApiError { message: "failed".to_string(), r#type: r#type.map(str::to_string), param: None, code: None }
+    }
+
+    fn policy() -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: 4,
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_millis(4_000),
+            timeout: Duration::from_secs(60),
+        }
+    }
+
+    fn failure(status: StatusCode, r#type: Option<&str>, retry_after: Option<Duration>) -> Failure {
+        let error = api_error(r#type);
+
+        Failure {
+            retryable: is_retryable_status(status, &error),
+            rejected: is_rejected_status(status),
+            retry_after,
+            error: OpenAIError::ApiError(error),
+        }
+    }
+
+    fn headers(retry_after: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(RETRY_AFTER, HeaderValue::from_str(retry_after).unwrap());
+        headers
+    }
+
+    #[test]
+    fn rate_limits_and_server_errors_are_retryable_but_quota_and_request_errors_are_not() {
+        assert!(is_retryable_status(StatusCode::TOO_MANY_REQUESTS, &api_error(Some("requests"))));
+        assert!(is_retryable_status(StatusCode::INTERNAL_SERVER_ERROR, &api_error(None)));
+        assert!(is_retryable_status(StatusCode::SERVICE_UNAVAILABLE, &api_error(None)));
+        assert!(!is_retryable_status(StatusCode::TOO_MANY_REQUESTS, &api_error(Some("insufficient_quota"))));
+        assert!(!is_retryable_status(StatusCode::BAD_REQUEST, &api_error(Some("invalid_request_error"))));
+        assert!(!is_retryable_status(StatusCode::UNAUTHORIZED, &api_error(None)));
+
+        assert!(is_rejected_status(StatusCode::BAD_REQUEST));
+        assert!(is_rejected_status(StatusCode::UNAUTHORIZED));
+        assert!(!is_rejected_status(StatusCode::TOO_MANY_REQUESTS));
+        assert!(!is_rejected_status(StatusCode::INTERNAL_SERVER_ERROR));
+    }
+
+    #[test]
+    fn retries_until_the_attempts_run_out_or_the_server_wants_too_long_a_wait() {
+        let policy = policy();
+        let server_error = failure(StatusCode::INTERNAL_SERVER_ERROR, None, None);
+
+        assert!(policy.should_retry(&server_error, 1));
+        assert!(policy.should_retry(&server_error, 3));
+        assert!(!policy.should_retry(&server_error, 4));
+
+        assert!(policy.should_retry(&failure(StatusCode::TOO_MANY_REQUESTS, None, Some(Duration::from_secs(4))), 1));
+        assert!(!policy.should_retry(&failure(StatusCode::TOO_MANY_REQUESTS, None, Some(Duration::from_secs(5))), 1));
+        assert!(!policy.should_retry(&failure(StatusCode::TOO_MANY_REQUESTS, Some("insufficient_quota"), None), 1));
+        assert!(!policy.should_retry(&failure(StatusCode::BAD_REQUEST, None, None), 1));
+    }
+
+    #[test]
+    fn the_backoff_doubles_each_attempt_up_to_the_maximum_delay() {
+        let policy = policy();
+
+        // Full jitter picks anywhere up to the exponential delay, so only the upper bound is fixed
+        for (attempt, limit) in [(1, 500), (2, 1_000), (3, 2_000), (4, 4_000), (5, 4_000), (40, 4_000)] {
+            let longest = (0..200).map(|_| policy.delay_for(attempt)).max().unwrap();
+
+            assert!(longest <= Duration::from_millis(limit), "attempt {} waited {:?}", attempt, longest);
+            assert!(longest > Duration::from_millis(limit / 2), "attempt {} never waited past {:?}", attempt, longest);
+        }
+    }
+
+    #[test]
+    fn retry_after_is_read_as_seconds_or_an_http_date() {
+        assert_eq!(retry_after(&headers("7")), Some(Duration::from_secs(7)));
+        assert_eq!(retry_after(&headers(" 0 ")), Some(Duration::ZERO));
+
+        let in_a_minute = (chrono::Utc::now() + chrono::Duration::seconds(60)).to_rfc2822();
+        let delay = retry_after(&headers(&in_a_minute)).unwrap();
+        assert!(delay > Duration::from_secs(55) && delay <= Duration::from_secs(60));
+
+        let a_minute_ago = (chrono::Utc::now() - chrono::Duration::seconds(60)).to_rfc2822();
+        assert_eq!(retry_after(&headers(&a_minute_ago)), None);
+        assert_eq!(retry_after(&headers("soon")), None);
+        assert_eq!(retry_after(&HeaderMap::new()), None);
+    }
+}
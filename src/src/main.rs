@@ -1,12 +1,74 @@
-use async_openai::{types::{ChatCompletionRequestMessageArgs, CreateChatCompletionRequestArgs, Role}, Client, config::OpenAIConfig};
+use async_openai::types::{ChatCompletionFunctions, ChatCompletionRequestMessage, ChatCompletionRequestMessageArgs, CreateChatCompletionRequestArgs, FunctionCall, Role};
 use colored::Colorize;
-use std::error::Error;
-use chrono::{Local, DateTime};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use chrono::{Datelike, NaiveDate, NaiveDateTime, TimeZone, Utc};
 use reqwest::{self};
+use serde::de::DeserializeOwned;
 use serde_derive::{Serialize, Deserialize};
-use dotenv;
+
+mod booking;
+mod calendar;
+mod catalog;
+mod clock;
+mod context;
+mod dates;
+mod directives;
+mod embeddings;
+mod draft;
+mod error;
+mod guard;
+mod hours;
+mod knowledge;
+mod links;
+mod llm;
+mod memory;
+mod persona;
+mod profile;
+mod provider;
+mod reminders;
+mod session;
+mod templates;
+mod tools;
+mod usage;
+mod website;
+
+use booking::{BookingConfig, BookingRequest, BookingService, Staff};
+use catalog::Catalog;
+use clock::BusinessClock;
+use context::ContextWindow;
+use dates::DateParser;
+use directives::DirectiveSet;
+use embeddings::Embedder;
+use draft::BookingDraft;
+use persona::Persona;
+use error::AppError;
+use guard::{GuardAction, PriceGuard, Violation};
+use hours::OpeningHours;
+use knowledge::{Citation, KnowledgeBase};
+use links::{ContactAllowlist, LinkFilter};
+use profile::{CustomerIdentity, CustomerProfile, ProfileStore, SessionNotes};
+use provider::ProviderChain;
+use reminders::{ReminderScheduler, Schedule, Sender};
+use session::{ChatSession, LogEntry};
+use templates::PromptTemplates;
+use tools::{ToolContext, ToolRegistry};
+use tera::Context;
+use usage::{Allowance, CallPurpose, UsageTracker};
+use website::{Page, WebsiteDraft};
 
 const GPT_VERSION: &str = "gpt-3.5-turbo";
+const REPLY_MAX_TOKENS: u16 = 512;
+// Calls made while setting the business up aren't part of any customer session
+const SETUP_SESSION: &str = "setup";
+// Tool calls the model can make in one turn before we give up on it answering
+const MAX_TOOL_CALLS: usize = 5;
+// Reply tokens for the two website calls. The site's text gets whatever is left of the model's context, so the
+// price list is kept to what a 4k model can answer with and still read a useful amount of the site.
+const WEBSITE_IMPORT_TOKENS: u16 = 768;
+const WEBSITE_SERVICES_TOKENS: u16 = 1536;
+// Less of the site than this isn't worth drafting from
+const MIN_WEBSITE_TOKENS: usize = 256;
 
 #[derive(Debug, Serialize, Deserialize)]
 struct SentimentPredictorResponse {
@@ -30,27 +92,28 @@ impl SentimentPredictor {
         }
     }
 
-    pub async fn train(&self) -> Result<(), Box<dyn Error>> {
+    pub async fn train(&self) -> Result<(), AppError> {
         if self.trained.load(std::sync::atomic::Ordering::Relaxed) {
-            return Err(Box::new(std::io::Error::new(std::io::ErrorKind::Other, "Already trained.")));
+            return Err(AppError::Sentiment("Already trained.".to_string()));
         }
 
         let url = format!("{}/train", self.base_url);
-        self.http_client.post(&url).send().await?;
+        self.http_client.post(&url).send().await.map_err(|e| AppError::Sentiment(e.to_string()))?;
 
         self.trained.store(true, std::sync::atomic::Ordering::Relaxed);
         Ok(())
     }
 
-    pub async fn analyse_sentiment(&self, text: &str) -> Result<String, Box<dyn Error>> {
+    pub async fn analyse_sentiment(&self, text: &str) -> Result<String, AppError> {
         let url = format!("{}/predict", self.base_url);
 
         let response = self.http_client.post(&url)
             .json(&serde_json::json!({"text": text}))
             .send()
-            .await?;
+            .await
+            .map_err(|e| AppError::Sentiment(e.to_string()))?;
 
-        let content: SentimentPredictorResponse = response.json().await?;
+        let content: SentimentPredictorResponse = response.json().await.map_err(|e| AppError::Sentiment(e.to_string()))?;
         Ok(content.prediction)
     }
 }
@@ -59,104 +122,373 @@ struct BusinessInfo {
     business_name: String,
     description: String,
     industry: String,
+    clock: BusinessClock,
+    // Drafted from the business's website by import-website, if it was run
+    website: Option<WebsiteDraft>,
 
     // Additional fields can be added as we identify more relevant information to gather
 }
 
+// The persona is asked for once and kept, the manager can redo it on later runs
+fn load_or_collect_persona(persona_path: &Path) -> Result<Persona, AppError> {
+    let saved_persona = match Persona::load(persona_path)? {
+        Some(persona) => {
+            println!("Would you like to change the assistant's name and tone of voice? (y/N)");
+            (!read_input()?.eq_ignore_ascii_case("y")).then_some(persona)
+        }
+        None => None,
+    };
+    let persona = match saved_persona {
+        Some(persona) => persona,
+        None => {
+            let persona = Persona::collect()?;
+            persona.save(persona_path)?;
+            persona
+        }
+    };
+
+    Ok(persona)
+}
+
+// What the business set up beyond the basics, asked for once and kept in its data directory
+struct BusinessSettings {
+    persona: Persona,
+    opening_hours: OpeningHours,
+    catalog: Catalog,
+    contacts: ContactAllowlist,
+}
+
+impl BusinessSettings {
+    fn load_or_collect(data_dir: &Path, website: Option<&WebsiteDraft>) -> Result<Self, AppError> {
+        Ok(BusinessSettings {
+            persona: load_or_collect_persona(&data_dir.join("persona.json"))?,
+            opening_hours: load_or_collect(
+                &data_dir.join("opening_hours.json"),
+                "Would you like to change your locations and opening hours? (y/N)",
+                OpeningHours::collect,
+            )?,
+            catalog: load_or_collect(
+                &data_dir.join("catalog.json"),
+                "Would you like to change your services and prices? (y/N)",
+                || Catalog::collect(website.map(WebsiteDraft::catalog)),
+            )?,
+            contacts: load_or_collect(
+                &data_dir.join("contacts.json"),
+                "Would you like to change the links, email addresses and phone numbers the assistant can give out? (y/N)",
+                ContactAllowlist::collect,
+            )?,
+        })
+    }
+}
+
+// Reads a single trimmed line from stdin
+fn read_input() -> Result<String, AppError> {
+    let mut input = String::new();
+    std::io::stdin().read_line(&mut input).map_err(|e| AppError::UserInput(e.to_string()))?;
+    Ok(input.trim().to_string())
+}
+
+// Shows what the website said and keeps it if the manager just presses Enter
+fn read_input_or(imported: Option<&str>) -> Result<String, AppError> {
+    if let Some(imported) = imported {
+        println!("From your website: {}\n(press Enter to keep this, or type a replacement)", imported);
+    }
+
+    match (read_input()?, imported) {
+        (answer, Some(imported)) if answer.is_empty() => Ok(imported.to_string()),
+        (answer, _) => Ok(answer),
+    }
+}
+
+// Asked when the description is too vague to write questions from, and looked for on the website by import-website
+const GENERIC_QUESTIONS: &[&str] = &[
+    "What are the primary products or services your business offers?",
+    "Who are your target customers or audience?",
+    "Do you have physical locations, or is your business primarily online?",
+    "If you do have a physical location, what is the address? (type \"NA if you do not\")",
+    "How do customers typically interact with your business?",
+    "What are the most common questions customers ask?",
+    "How long have you been in business?",
+    "What is your most popular product or service?"
+];
+
 impl BusinessInfo {
-    fn collect() -> Self {
+    fn collect() -> Result<Self, AppError> {
         println!("Please provide the brand name of your business:");
-        let mut business_name = String::new();
-        std::io::stdin().read_line(&mut business_name).expect("Failed to read line");
+        let business_name = read_input()?;
+        let data_dir = business_data_dir(&business_name);
+        let website: Option<WebsiteDraft> = load_saved(&data_dir.join("website.json"))?;
+        if let Some(website) = &website {
+            println!("We've drafted some answers from {} pages of your website, please check them as we go.", website.pages.len());
+        }
 
         println!("What industry is your business in? (e.g. \"Personal Care Services\", \"Retail Trade\", \"Construction\"):");
-        let mut industry = String::new();
-        std::io::stdin().read_line(&mut industry).expect("Failed to read line");
+        let industry = read_input_or(website.as_ref().and_then(|website| website.industry.as_deref()))?;
 
         println!("Please provide a detailed description of your business:");
-        let mut description = String::new();
-        std::io::stdin().read_line(&mut description).expect("Failed to read line");
-        
-        BusinessInfo {
-            business_name: business_name.trim().to_string(),
-            description: description.trim().to_string(),
-            industry: industry.trim().to_string(),
+        let description = read_input_or(website.as_ref().and_then(|website| website.description.as_deref()))?;
+
+        // A timezone saved with the booking settings is carried over, so it's only asked for again if it's wrong
+        if !data_dir.join("clock.json").exists() {
+            if let Some(clock) = load_clock(&data_dir)? {
+                std::fs::write(data_dir.join("clock.json"), serde_json::to_string_pretty(&clock)?)?;
+            }
         }
+        let clock = load_or_collect(
+            &data_dir.join("clock.json"),
+            "Would you like to change the business's timezone? (y/N)",
+            BusinessClock::collect,
+        )?;
+        
+        Ok(BusinessInfo {
+            business_name,
+            description,
+            industry,
+            clock,
+            website,
+        })
     }
 }
 
+// A chat reply and the provider that served it
+struct Reply {
+    content: String,
+    // Set when the model wants a tool called before it answers, content is usually empty then
+    function_call: Option<FunctionCall>,
+    provider: String,
+    model: String,
+    // Knowledge base chunks the model said it used, taken out of the content
+    citations: Vec<Citation>,
+    // The model ran out of tokens, so the content stops part way through
+    truncated: bool,
+}
+
 struct OpenAIHelper {
-    client: Client<OpenAIConfig>,
+    client: ProviderChain,
+    embedder: Option<Embedder>,
+    templates: PromptTemplates,
+    usage: Option<Mutex<UsageTracker>>,
 }
 
 impl OpenAIHelper {
-    fn new() -> Result<Self, Box<dyn Error>> {
-        dotenv::dotenv().unwrap();
-        let client = Client::new();
+    fn new() -> Result<Self, AppError> {
+        // The key may also come from the real environment, so a missing .env file is fine
+        dotenv::dotenv().ok();
+        let client = ProviderChain::from_env()?;
+        let embedder = Embedder::from_env()?;
+        let templates = PromptTemplates::from_env()?;
         Ok(OpenAIHelper {
             client,
+            embedder,
+            templates,
+            usage: None,
         })
     }
 
-    async fn is_vague(&self, business: &BusinessInfo) -> Result<bool, Box<dyn Error>> {        
+    fn track_usage(&mut self, tracker: UsageTracker) {
+        self.usage = Some(Mutex::new(tracker));
+    }
+
+    // The model the next call goes to, the downgrade model once the spend cap is hit. Errors if the cap blocks calls.
+    fn chat_model(&self) -> Result<String, AppError> {
+        let Some(usage) = &self.usage else {
+            return Ok(GPT_VERSION.to_string());
+        };
+
+        match usage.lock().unwrap().allowance()? {
+            Allowance::Allowed => Ok(GPT_VERSION.to_string()),
+            Allowance::Downgrade(model) => Ok(model),
+        }
+    }
+
+    // Sends the messages to the first healthy provider and returns the content of the first choice
+    async fn chat(&self, messages: Vec<ChatCompletionRequestMessage>, max_tokens: u16, purpose: CallPurpose, session: &str) -> Result<Reply, AppError> {
+        self.chat_with_functions(messages, Vec::new(), &self.chat_model()?, max_tokens, purpose, session).await
+    }
+
+    // As chat, on a model already chosen with chat_model so the messages could be sized for it
+    async fn chat_with_functions(&self, messages: Vec<ChatCompletionRequestMessage>, functions: Vec<ChatCompletionFunctions>, model: &str, max_tokens: u16, purpose: CallPurpose, session: &str) -> Result<Reply, AppError> {
+        let mut request = CreateChatCompletionRequestArgs::default();
+        request
+            .max_tokens(max_tokens)
+            .model(model)
+            .messages(messages);
+        if !functions.is_empty() {
+            request.functions(functions);
+        }
+        let request = request.build()?;
+
+        let served = self.client.create_chat(&request).await?;
+
+        if let (Some(usage), Some(tokens)) = (&self.usage, &served.response.usage) {
+            let recorded = usage.lock().unwrap().record(
+                session, purpose, &served.provider, &served.model, tokens.prompt_tokens, tokens.completion_tokens
+            );
+
+            // Losing a usage record shouldn't cost the customer their reply
+            if let Err(e) = recorded {
+                eprintln!("Could not record token usage: {}", e);
+            }
+        }
+
+        let choice = served.response.choices
+            .into_iter()
+            .next()
+            .filter(|choice| choice.message.content.is_some() || choice.message.function_call.is_some())
+            .ok_or_else(|| AppError::LlmContent("the response had no content".to_string()))?;
+
+        Ok(Reply {
+            content: choice.message.content.unwrap_or_default(),
+            function_call: choice.message.function_call,
+            provider: served.provider,
+            model: served.model,
+            citations: Vec::new(),
+            truncated: choice.finish_reason.as_deref() == Some("length"),
+        })
+    }
+
+    fn embedding_model(&self) -> Option<&str> {
+        self.embedder.as_ref().map(|embedder| embedder.model.as_str())
+    }
+
+    // One vector per text for the knowledge base, None when embeddings are turned off
+    async fn embed(&self, texts: &[String], session: &str) -> Result<Option<Vec<Vec<f32>>>, AppError> {
+        let Some(embedder) = &self.embedder else {
+            return Ok(None);
+        };
+        // A cheaper chat model doesn't make embeddings any cheaper, only a block stops them
+        if let Some(usage) = &self.usage {
+            usage.lock().unwrap().allowance()?;
+        }
+
+        let (vectors, tokens) = embedder.embed(texts).await?;
+
+        if let Some(usage) = &self.usage {
+            if let Err(e) = usage.lock().unwrap().record(session, CallPurpose::Embedding, &embedder.provider, &embedder.model, tokens, 0) {
+                eprintln!("Could not record token usage: {}", e);
+            }
+        }
+
+        Ok(Some(vectors))
+    }
+
+    // Single system prompt in, text out
+    async fn complete(&self, prompt: &str, max_tokens: u16, purpose: CallPurpose, session: &str) -> Result<String, AppError> {
+        let message = ChatCompletionRequestMessageArgs::default()
+            .role(Role::System)
+            .content(prompt)
+            .build()?;
+
+        Ok(self.chat(vec![message], max_tokens, purpose, session).await?.content)
+    }
+
+    // Single system prompt in, JSON out. JSON cut off by the token limit can't be parsed, so that's reported as such
+    // rather than as whatever parse error it happens to cause.
+    async fn complete_json<T: DeserializeOwned>(&self, prompt: &str, model: &str, max_tokens: u16, purpose: CallPurpose, session: &str) -> Result<T, AppError> {
+        let message = ChatCompletionRequestMessageArgs::default()
+            .role(Role::System)
+            .content(prompt)
+            .build()?;
+
+        let reply = self.chat_with_functions(vec![message], Vec::new(), model, max_tokens, purpose, session).await?;
+        if reply.truncated {
+            return Err(AppError::LlmContent(format!("the reply was cut off at {} tokens", max_tokens)));
+        }
+
+        // The model sometimes wraps JSON in a markdown code block
+        let json = reply.content.trim().trim_start_matches("```json").trim_matches('`').trim();
+
+        serde_json::from_str(json).map_err(|e| AppError::LlmContent(e.to_string()))
+    }
+
+    // A reminder or follow-up for one booking, the booking ID stands in for the session
+    async fn draft_notification(&self, context: &Context, booking_id: &str) -> Result<String, AppError> {
+        let prompt = self.templates.render(templates::NOTIFICATION, context)?;
+        let message = self.complete(&prompt, 160, CallPurpose::Notification, booking_id).await?;
+
+        match message.trim() {
+            "" => Err(AppError::LlmContent("the notification was empty".to_string())),
+            message => Ok(message.to_string()),
+        }
+    }
+
+    // Setup answers drafted from the text of the business's website, for the manager to confirm during setup. The
+    // services are asked for on their own, a full price list needs far more room than the rest.
+    async fn draft_from_website(&self, context_window: &ContextWindow, business_name: &str, pages: &[Page]) -> Result<WebsiteDraft, AppError> {
+        let mut context = Context::new();
+        context.insert("business_name", business_name);
+        context.insert("questions", &GENERIC_QUESTIONS.iter().map(|question| format!("- {}", question)).collect::<Vec<String>>().join("\n"));
+
+        let model = self.chat_model()?;
+        let import_prompt = self.website_prompt(templates::WEBSITE_IMPORT, &mut context, context_window, &model, pages, WEBSITE_IMPORT_TOKENS)?;
+        let mut draft: WebsiteDraft = self.complete_json(&import_prompt, &model, WEBSITE_IMPORT_TOKENS, CallPurpose::WebsiteImport, SETUP_SESSION).await?;
+
+        let model = self.chat_model()?;
+        let services_prompt = self.website_prompt(templates::WEBSITE_SERVICES, &mut context, context_window, &model, pages, WEBSITE_SERVICES_TOKENS)?;
+        draft.services = self.complete_json(&services_prompt, &model, WEBSITE_SERVICES_TOKENS, CallPurpose::WebsiteImport, SETUP_SESSION).await?;
+
+        Ok(draft)
+    }
+
+    // Renders one of the website prompts with as much of the site as fits the model's context next to the reply
+    fn website_prompt(&self, template: &str, context: &mut Context, context_window: &ContextWindow, model: &str, pages: &[Page], max_tokens: u16) -> Result<String, AppError> {
+        context.insert("pages", "");
+        let message = ChatCompletionRequestMessageArgs::default()
+            .role(Role::System)
+            .content(self.templates.render(template, context)?)
+            .build()?;
+
+        let room = context_window
+            .budget_for(model)
+            .saturating_sub(max_tokens as usize + context_window.count_messages(&[message]));
+        if room < MIN_WEBSITE_TOKENS {
+            return Err(AppError::Config(format!(
+                "{} has no room for the website next to a {} token reply, give it a larger budget in CONTEXT_BUDGETS", model, max_tokens
+            )));
+        }
+
+        context.insert("pages", &website::pages_text(pages, context_window, room));
+        self.templates.render(template, context)
+    }
+
+    async fn is_vague(&self, business: &BusinessInfo) -> Result<bool, AppError> {        
         if business.description.len() < 300 {
            return Ok(true);
         }
     
-        let vague_prompt = format!(
-            "You are a customer helper AI, designed to assist with customer service for a business called {}, in the industry {}. Your role is to ensure that customer queries are answered correctly, including questions about pricing, services/products offered, appointment bookings and queries regarding appointments as well as any general questions about the nature of the business. The manager of this business has provided you with the following description of the business: {}. By replying with a simple 'Yes' or 'No', please answer the following question: Is this description too basic and vague (where \"No\" means you would like more information about the business) for you to be able to perform your duties as a customer helper AI?",
-            business.business_name, business.industry, business.description
-        );
-    
-        let request = CreateChatCompletionRequestArgs::default()
-            .max_tokens(10u16)  // Keeping it short as we expect 'Yes' or 'No' response.
-            .model(GPT_VERSION)
-            .messages(vec![
-                ChatCompletionRequestMessageArgs::default()
-                    .role(Role::System)
-                    .content(&vague_prompt)
-                    .build()?
-            ])
-            .build()?;
+        let mut context = Context::new();
+        context.insert("business_name", &business.business_name);
+        context.insert("industry", &business.industry);
+        context.insert("description", &business.description);
+
+        let vague_prompt = self.templates.render(templates::VAGUENESS_CHECK, &context)?;
     
-        let response = self.client.chat().create(request).await?;
-        let ai_response = response.choices[0].message.content.clone().unwrap_or_else(String::new);
+        // Keeping it short as we expect 'Yes' or 'No' response.
+        let ai_response = self.complete(&vague_prompt, 10, CallPurpose::VaguenessCheck, SETUP_SESSION).await?;
 
         println!("AI said: {}", ai_response);
         
         Ok(ai_response.trim().to_lowercase() == "yes")  // True if vague, false if not
     }
     
-    async fn generate_questions(&self, business: &BusinessInfo) -> Result<(Vec<String>, String), Box<dyn Error>> {
-            let is_description_vague: bool = self.is_vague(&business).await?;
+    async fn generate_questions(&self, business: &BusinessInfo) -> Result<(Vec<String>, String), AppError> {
+            let is_description_vague: bool = self.is_vague(business).await?;
             let mut finalised_formatted_answers = String::new();
 
             if is_description_vague {
-                let generic_questions = vec![
-                    "What are the primary products or services your business offers?",
-                    "Who are your target customers or audience?",
-                    "Do you have physical locations, or is your business primarily online?",
-                    "If you do have a physical location, what is the address? (type \"NA if you do not\")",
-                    "How do customers typically interact with your business?",
-                    "What are the most common questions customers ask?",
-                    "How long have you been in business?",
-                    "What is your most popular product or service?"
-                ];
-
                 let mut answered_generic_questions = Vec::new();
 
                 println!("We'd just like to learn a bit more about your business before we get AI involved. Please answer the following questions:");
 
-                for question in &generic_questions {
+                for question in GENERIC_QUESTIONS {
                     println!("General Question:");
                     println!("Provide an answer or type 'NA' if the question is not relevant to your business:");    
 
                     println!("{}", question);
-                    let mut answer = String::new();
-                    std::io::stdin().read_line(&mut answer).expect("Failed to read line");
+                    let answer = read_input_or(business.website.as_ref().and_then(|website| website.answer(question)))?;
                     
                     if answer != "NA" {
-                        answered_generic_questions.push((question, answer.trim().to_string()));
+                        answered_generic_questions.push((question, answer));
                     }
                 }
 
@@ -167,31 +499,19 @@ impl OpenAIHelper {
                     .join("\n");
             }
             
+            let mut context = Context::new();
+            context.insert("business_name", &business.business_name);
+            context.insert("industry", &business.industry);
+            context.insert("description", &business.description);
+            context.insert("generic_answers", &finalised_formatted_answers);
+
             let initial_prompt = if is_description_vague {
-                format!(
-                    "You are a customer helper AI, designed to assist with customer service for a business named {}, which is in the industry {}. Your job is to learn and understand as much information about this business as possible so that you may help out as well as possible. Big parts of this are learning about what services the business provides, how a booking system (if any) works for the business, how long services take, how much money they cost, etc., it is your job to figure these out for the business. The business has provided you with this summary of their business: '{}'. Additionally, we have asked more questions to refine your knowledge of the business, which you can view here: '{}'. Based on this provided brief description (as well as the questions provided) of the business, what specific questions do you wish to ask the business to better understand it so that you may help out customers at a better level? IN YOUR ANSWER, please provide the questions in order, do not use numerical order (\"1.\", \"2.\", etc.), simply just provide the question like so: \"- Question?\". Please for now ensure a maximum of 15 questions, try to cover essiental information that may not have been specified before getting into other questions.",
-                    business.business_name, business.industry, business.description, finalised_formatted_answers
-                )
+                self.templates.render(templates::QUESTIONS_WITH_ANSWERS, &context)?
             } else {
-                format!(
-                    "You are a customer helper AI, designed to assist with customer service related to a business named {}, in the industry {}. Your job is to learn and understand as much information about this business as possible so that you may help out as well as possible. Big parts of this are learning about what services the business provides, how a booking system (if any) works for the business, how long services take, how much money they cost, etc., it is your job to figure these out for the business. The business has provided you with this summary of their business: '{}'. Based on this provided brief description of the business, what specific do you wish to ask the business to better understand it so that you may help out customers at a better level. IN YOUR ANSWER, please provide the questions in order, do not use numerical order (\"1.\", \"2.\", etc.), simply just provide the question like so: \"- Question?\". Please for now ensure a maximum of 15 questions, try to cover essiental information that may not have been specified before getting into other questions.",
-                    business.business_name, business.industry, business.description
-                    )
+                self.templates.render(templates::QUESTIONS, &context)?
             };
             
-            let request = CreateChatCompletionRequestArgs::default()
-                .max_tokens(512u16)
-                .model(GPT_VERSION)
-                .messages(vec![
-                    ChatCompletionRequestMessageArgs::default()
-                        .role(Role::System)
-                        .content(&initial_prompt)
-                        .build()?
-                ])
-                .build()?;
-            
-            let response = self.client.chat().create(request).await?;
-            let ai_response = response.choices[0].message.content.clone().unwrap_or_else(String::new);
+            let ai_response = self.complete(&initial_prompt, 512, CallPurpose::QuestionGeneration, SETUP_SESSION).await?;
                     
             Ok((
                 ai_response
@@ -203,28 +523,51 @@ impl OpenAIHelper {
             )) 
         }
         
-        async fn gather_answers(&self, questions: &[String]) -> Vec<(String, String)> {
+        async fn summarise_conversation(&self, previous_summary: Option<&str>, messages: &[ChatCompletionRequestMessage], session: &str) -> Result<String, AppError> {
+            let mut context = Context::new();
+            context.insert("previous_summary", previous_summary.unwrap_or(""));
+            context.insert("transcript", &memory::transcript(messages));
+
+            let summary_prompt = self.templates.render(templates::SUMMARY, &context)?;
+
+            let ai_response = self.complete(&summary_prompt, 256, CallPurpose::Summary, session).await?;
+
+            Ok(ai_response.trim().to_string())
+        }
+
+        async fn session_notes(&self, profile: &CustomerProfile, summary: Option<&str>, messages: &[ChatCompletionRequestMessage], session: &str) -> Result<SessionNotes, AppError> {
+            let mut context = Context::new();
+            context.insert("name", profile.name.as_deref().unwrap_or(""));
+            context.insert("preferences", &profile.preferences.join("; "));
+            context.insert("open_issues", &profile.open_issues.join("; "));
+            context.insert("summary", summary.unwrap_or(""));
+            context.insert("transcript", &memory::transcript(messages));
+
+            let notes_prompt = self.templates.render(templates::SESSION_NOTES, &context)?;
+
+            self.complete_json(&notes_prompt, &self.chat_model()?, 384, CallPurpose::SessionNotes, session).await
+        }
+
+        async fn gather_answers(&self, questions: &[String]) -> Result<Vec<(String, String)>, AppError> {
             let mut answers_vec = Vec::new();
         
             for (index, question) in questions.iter().enumerate() {
                 println!("AI Question ({} of {}): {}", index + 1, questions.len(), question);
                 println!("Provide an answer or type 'NA' if the question is not relevant:");
             
-                let mut input = String::new();
-                std::io::stdin().read_line(&mut input).expect("Failed to read line");
-                let answer = input.trim().to_string();
+                let answer = read_input()?;
             
                 if answer.to_uppercase() != "NA" {
                     answers_vec.push((question.clone(), answer));
                 }
             }
         
-            answers_vec
+            Ok(answers_vec)
         }
         
     }
 
-    fn generate_prompt(business: &BusinessInfo, answered_questions: &[(String, String)], generic_answers: String) -> String {    
+    fn generate_prompt(templates: &PromptTemplates, business: &BusinessInfo, settings: &BusinessSettings, directives: &DirectiveSet, answered_questions: &[(String, String)], generic_answers: String) -> Result<String, AppError> {    
         let formatted_answers = answered_questions
         .iter()
         .map(|(q, a)| format!("Q: {} A: {}", q, a))
@@ -232,25 +575,88 @@ impl OpenAIHelper {
         .join("\n");
 
     let total_answers = formatted_answers + "\n" + &generic_answers;
-    let time_now: DateTime<Local> = Local::now();
-
-    format!(
-        "The current date and time is {}, in the format YYYY-MM-DDTHH:MM:SS.SSSSSS±HH:MM. You are a customer helper AI, designed to assist with all customer service matters related to a business named {}, working in the industry {}. The description given for this business is \"{}\" by your manager.
-        You are currently enagaged with a customer or potential customer of the business, your role is to ensure that customer queries are answered correctly, including questions about pricing, services/products offered, appointment bookings and queries regarding appointments as well as any
-        general questions about the nature of the business. Here is a list of more exhaustive information, through questioning the manager, about the business to ensure you are clearly knowledgable about it: {}.
-        Other directives for you are as follows:
-        **Human**: Act natural and human, don't be too robotic or mechanical in your responses - you can reveal your identity if inquired about, but other than that act as natural as possible. Instead of using robotic words like \"assist\" use words like \"help\", etc.
-        **Stay Ontopic**: Don't leave the topic of the business, try to stay on topic and answer questions related to the business, if they try to move off topic, bring them back on topic.
-        **Professionalism**: Always maintain a courteous and professional demeanor. Your responses should reflect the business's values and standards.
-        **Transparency**: If a customer inquires about your nature, be honest. Inform them that you are an AI designed to assist and make their experience smoother.
-        **Constraints**: Ensure never to give out links to non-business provided material, if there is no context to a question (for example 'how can i buy x?') assume that they are trying to use or buy something from the business
-        **Training**: If the message [TRAINDATA] is given to you, this is for your training by a developer. Ensure that you listen to what is told and reply with nothing. This is to ensure that you are trained to respond to certain prompts in a certain way.
-        However, try to act as normal and human as possible, do not mention that you are an AI in any way whatsoever asides from if directly asked by the customer. 
-        Your ultimate goal is to ensure customer satisfaction by providing them with accurate, timely, and helpful responses. If you are confused and do not know an answer, 
-        feel free to direct the user to a human employee by simply letting them know that you have raised their query to an employee and that they will be in contact with them shortly.
-        Every interaction should instill confidence in the customer about the business's capability and professionalism. Feel free to proceed while adhering to these guidelines.",
-        time_now, business.business_name, business.industry, business.description, total_answers
-        )
+
+    let mut context = Context::new();
+    context.insert("business_name", &business.business_name);
+    context.insert("industry", &business.industry);
+    context.insert("description", &business.description);
+    context.insert("answers", &total_answers);
+    context.insert("persona", &settings.persona.prompt_section());
+    context.insert("opening_hours", &settings.opening_hours.prompt_section(business.clock.now().date_naive()));
+    context.insert("catalog", &settings.catalog.prompt_section());
+    context.insert("contacts", &settings.contacts.prompt_section());
+    context.insert("directives", &directives.enabled());
+
+    templates.render(templates::SYSTEM_PROMPT, &context)
+}
+
+// Lowercase, with anything but letters and digits turned into dashes, safe to use as a directory name
+fn slug(text: &str) -> String {
+    text.to_lowercase()
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+        .collect()
+}
+
+fn load_saved<T: DeserializeOwned>(path: &Path) -> Result<Option<T>, AppError> {
+    if !path.exists() {
+        return Ok(None);
+    }
+
+    serde_json::from_str(&std::fs::read_to_string(path)?)
+        .map(Some)
+        .map_err(|e| AppError::Config(format!("{}: {}", path.display(), e)))
+}
+
+// Setup answers asked for once and kept as JSON in the business's data directory, the manager can redo them on
+// later runs
+fn load_or_collect<T: serde::Serialize + DeserializeOwned>(path: &Path, change_question: &str, collect: impl FnOnce() -> Result<T, AppError>) -> Result<T, AppError> {
+    if let Some(saved) = load_saved(path)? {
+        println!("{}", change_question);
+        if !read_input()?.eq_ignore_ascii_case("y") {
+            return Ok(saved);
+        }
+    }
+
+    let collected = collect()?;
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(path, serde_json::to_string_pretty(&collected)?)?;
+
+    Ok(collected)
+}
+
+// The business's timezone from clock.json, or from booking.json for businesses set up before it was kept there.
+// None when it has neither.
+fn load_clock(data_dir: &Path) -> Result<Option<BusinessClock>, AppError> {
+    if let Some(clock) = load_saved(&data_dir.join("clock.json"))? {
+        return Ok(Some(clock));
+    }
+
+    let booking: Option<Option<BookingConfig>> = load_saved(&data_dir.join("booking.json"))?;
+    match booking.flatten().and_then(|config| config.timezone) {
+        Some(timezone) => BusinessClock::parse(&timezone).map(Some).map_err(|e| AppError::Config(format!("booking.json: {}", e))),
+        None => Ok(None),
+    }
+}
+
+// None when the business hasn't set up bookings
+async fn open_bookings(data_dir: &Path) -> Result<Option<BookingService>, AppError> {
+    let Some(Some(config)) = load_saved::<Option<BookingConfig>>(&data_dir.join("booking.json"))? else {
+        return Ok(None);
+    };
+
+    let clock = load_clock(data_dir)?.map_or_else(BusinessClock::default_from_env, Ok)?;
+    let opening_hours = load_saved(&data_dir.join("opening_hours.json"))?.unwrap_or_default();
+    let catalog = load_saved(&data_dir.join("catalog.json"))?.unwrap_or_default();
+
+    BookingService::connect(config, clock.timezone, opening_hours, catalog).await.map(Some)
+}
+
+// Where everything we keep on disk for a business lives, DATA_DIR defaults to ./data
+fn business_data_dir(business_name: &str) -> PathBuf {
+    PathBuf::from(std::env::var("DATA_DIR").unwrap_or_else(|_| "data".to_string())).join(slug(business_name))
 }
 
 fn update_prompt(current_prompt: String, user_prompt: String, new_reply: String) -> String {
@@ -261,17 +667,390 @@ fn update_prompt(current_prompt: String, user_prompt: String, new_reply: String)
     // Check if the header already exists in the current prompt
     if !current_prompt.contains(header) {
         // If not, add the header to the end of the current prompt
-        return format!("{}\n\n{}:\n{}", current_prompt, header, specific_reply);
+        format!("{}\n\n{}:\n{}", current_prompt, header, specific_reply)
     } 
     
     else {
         // If the header already exists, just append the new specific reply underneath it
-        return format!("{}\n{}", current_prompt, specific_reply);
+        format!("{}\n{}", current_prompt, specific_reply)
+    }
+}
+
+async fn chat_turn(openai_helper: &OpenAIHelper, context_window: &ContextWindow, price_guard: &PriceGuard, link_filter: &LinkFilter, tools: &ToolRegistry, session: &mut ChatSession, input: &str) -> Result<Reply, AppError> {
+    session.push(Role::User, input)?;
+    // Every request this turn goes to the same model, the one the conversation is sized for
+    let model = openai_helper.chat_model()?;
+    session.prepare(openai_helper, context_window, &model).await?;
+    tools.pin_draft(&mut session.conversation)?;
+
+    // The model may call tools before it answers, each call and its result stay in the conversation
+    let mut tool_calls = 0;
+    let mut reply = loop {
+        let reply = openai_helper.chat_with_functions(
+            session.conversation.clone(), tools.definitions(), &model, REPLY_MAX_TOKENS, CallPurpose::ChatTurn, &session.id
+        ).await?;
+
+        let Some(function_call) = reply.function_call else {
+            break reply;
+        };
+        if tool_calls == MAX_TOOL_CALLS {
+            return Err(AppError::LlmContent(format!("the model called {} tools without answering", MAX_TOOL_CALLS)));
+        }
+
+        let result = tools.call(&function_call).await?;
+        session.conversation.push(ChatCompletionRequestMessageArgs::default()
+            .role(Role::Assistant)
+            .function_call(function_call.clone())
+            .build()?
+        );
+        session.conversation.push(ChatCompletionRequestMessageArgs::default()
+            .role(Role::Function)
+            .name(function_call.name)
+            .content(result)
+            .build()?
+        );
+        tool_calls += 1;
+    };
+
+    let mut regenerations = 0;
+    let mut reply = loop {
+        let violations = price_guard.check(&reply.content);
+        if violations.is_empty() {
+            break reply;
+        }
+
+        let action = if regenerations < price_guard.max_regenerations { GuardAction::Regenerated } else { GuardAction::FellBack };
+        for violation in &violations {
+            eprintln!("Price check failed ({:?}): {:?}", action, violation);
+        }
+        if let Err(e) = price_guard.log(&session.id, &reply.content, &violations, action) {
+            eprintln!("Could not log the price check: {}", e);
+        }
+
+        // The fallback tells the customer the team will confirm the price, so they have to be told to
+        if let GuardAction::FellBack = action {
+            let reason = format!(
+                "The customer asked \"{}\" and the assistant couldn't give a price from the catalog: {}",
+                input, violations.iter().map(Violation::describe).collect::<Vec<String>>().join("; ")
+            );
+            reply.content = match tools.escalate(&reason) {
+                Ok(()) => guard::PRICE_FALLBACK.to_string(),
+                Err(e) => {
+                    eprintln!("Could not pass the price question on to the team: {}", e);
+                    guard::PRICE_FALLBACK_UNESCALATED.to_string()
+                }
+            };
+            break reply;
+        }
+
+        // Show the model what it said and what was wrong with it, without keeping either in the conversation
+        let mut messages = session.conversation.clone();
+        messages.push(ChatCompletionRequestMessageArgs::default().role(Role::Assistant).content(reply.content).build()?);
+        messages.push(ChatCompletionRequestMessageArgs::default().role(Role::System).content(price_guard.correction_hint(&violations)).build()?);
+
+        reply = openai_helper.chat_with_functions(messages, Vec::new(), &model, REPLY_MAX_TOKENS, CallPurpose::Correction, &session.id).await?;
+        regenerations += 1;
+    };
+
+    let (content, citations) = session.knowledge.take_citations(&reply.content);
+    reply.content = content;
+    reply.citations = citations;
+
+    let filtered = link_filter.filter(&reply.content, &tools.customer_contacts());
+    for removed in &filtered.removed {
+        eprintln!("Removed contact details the business didn't allow: {}", removed);
+    }
+    reply.content = filtered.content;
+
+    Ok(reply)
+}
+
+// Turns a failed chat turn into something we can tell the customer, only configuration errors end the chat
+fn degrade(error: AppError) -> Result<&'static str, AppError> {
+    eprintln!("{}", error);
+
+    match error {
+        AppError::Config(_) => Err(error),
+        AppError::LlmTransport(_) => Ok("Sorry, I'm having trouble connecting right now. Could you send that again in a moment?"),
+        AppError::LlmContent(_) => Ok("Sorry, I didn't quite catch that. Could you rephrase your question?"),
+        AppError::LlmRequest(_) => Ok("Sorry, I couldn't answer that one. Could you try asking it a different way?"),
+        AppError::SpendCap(_) => Ok("Sorry, I can't answer right now. A member of our team will get back to you as soon as possible."),
+        AppError::Booking(_) => Ok("Sorry, I couldn't sort that booking out. A member of our team will get back to you to arrange it."),
+        AppError::Sentiment(_) | AppError::Storage(_) | AppError::UserInput(_) | AppError::Notification(_) => Ok("Sorry, something went wrong on our side. Could you send that again?"),
+    }
+}
+
+// usage-report "<business name>" [YYYY-MM]
+fn print_usage_report(args: &[String]) -> Result<(), AppError> {
+    let business_name = args.first()
+        .ok_or_else(|| AppError::UserInput("usage-report needs a business name".to_string()))?;
+
+    let now = Utc::now();
+    let (year, month) = match args.get(1) {
+        Some(period) => period
+            .split_once('-')
+            .and_then(|(year, month)| Some((year.parse().ok()?, month.parse().ok()?)))
+            .ok_or_else(|| AppError::UserInput(format!("'{}' is not a YYYY-MM month", period)))?,
+        None => (now.year(), now.month()),
+    };
+
+    let tracker = UsageTracker::open(business_name, &business_data_dir(business_name))?;
+    println!("{}", tracker.monthly_report(year, month));
+
+    Ok(())
+}
+
+// bookings "<business name>" <command>, see BOOKINGS_USAGE
+const BOOKINGS_USAGE: &str = "bookings \"<business>\" staff | add-staff <name> [location] [service,...] | slots <service> [YYYY-MM-DD] [variant] | book <customer> <service> <YYYY-MM-DDTHH:MM> [variant] | reschedule <booking id> <YYYY-MM-DDTHH:MM> | cancel <booking id> | list <customer> | export-ics [staff] | import-ics <file.ics> [staff]";
+
+async fn run_bookings_command(args: &[String]) -> Result<(), AppError> {
+    let usage = || AppError::UserInput(format!("usage: {}", BOOKINGS_USAGE));
+    let arg = |index: usize| args.get(index).map(String::as_str);
+
+    let business_name = arg(0).ok_or_else(usage)?;
+    let data_dir = business_data_dir(business_name);
+    let bookings = open_bookings(&data_dir)
+        .await?
+        .ok_or_else(|| AppError::Config(format!("{} hasn't set up bookings", business_name)))?;
+
+    let parse_time = |text: &str| {
+        NaiveDateTime::parse_from_str(text, "%Y-%m-%dT%H:%M")
+            .ok()
+            .and_then(|at| bookings.timezone().from_local_datetime(&at).earliest())
+            .map(|at| at.with_timezone(&Utc))
+            .ok_or_else(|| AppError::UserInput(format!("'{}' should be a time like 2026-11-03T14:30", text)))
+    };
+    let parse_id = |text: &str| text.parse::<uuid::Uuid>().map_err(|_| AppError::UserInput(format!("'{}' isn't a booking ID", text)));
+    let customer_key = |text: &str| CustomerIdentity::parse(text).map(|identity| identity.key()).ok_or_else(usage);
+
+    match (arg(1), arg(2)) {
+        (Some("staff"), _) => {
+            for staff in bookings.staff().await? {
+                println!("{} {} ({}) {}", staff.staff_id, staff.name, staff.location.as_deref().unwrap_or("main location"), staff.services.join(", "));
+            }
+        }
+        (Some("add-staff"), Some(name)) => {
+            let services: Vec<String> = arg(4).map(|services| services.split(',').map(|service| service.trim().to_string()).collect()).unwrap_or_default();
+            let staff = bookings.add_staff(name, arg(3), &services).await?;
+            println!("Added {} ({})", staff.name, staff.staff_id);
+        }
+        (Some("slots"), Some(service)) => {
+            let from = match arg(3) {
+                Some(date) => NaiveDate::parse_from_str(date, "%Y-%m-%d").map_err(|_| usage())?,
+                None => Utc::now().with_timezone(&bookings.timezone()).date_naive(),
+            };
+            let request = BookingRequest { service, variant: arg(4), staff_id: None };
+
+            for slot in bookings.available_slots(&request, from, None).await? {
+                println!("{} - {} with {}", slot.starts_at.format("%a %-d %b %H:%M"), slot.ends_at.format("%H:%M"), slot.staff_name);
+            }
+        }
+        (Some("book"), Some(customer)) => {
+            let request = BookingRequest { service: arg(3).ok_or_else(usage)?, variant: arg(5), staff_id: None };
+            let booking = bookings.create(&customer_key(customer)?, None, &request, parse_time(arg(4).ok_or_else(usage)?)?).await?;
+            println!("Booked {:?}", booking);
+        }
+        (Some("reschedule"), Some(booking_id)) => {
+            let booking = bookings.reschedule(parse_id(booking_id)?, parse_time(arg(3).ok_or_else(usage)?)?, None).await?;
+            println!("Moved {:?}", booking);
+        }
+        (Some("cancel"), Some(booking_id)) => {
+            let booking = bookings.cancel(parse_id(booking_id)?).await?;
+            println!("Cancelled {:?}", booking);
+        }
+        (Some("list"), Some(customer)) => {
+            for booking in bookings.upcoming_for(&customer_key(customer)?).await? {
+                println!("{:?}", booking);
+            }
+        }
+        (Some("export-ics"), staff_name) => {
+            let staff = match staff_name {
+                Some(name) => Some(find_staff(&bookings, name).await?),
+                None => None,
+            };
+            let events = bookings.calendar_events(staff.as_ref().map(|staff| staff.staff_id)).await?;
+            let (calendar_name, file_name) = match &staff {
+                Some(staff) => (format!("{} - {}", business_name, staff.name), slug(&staff.name)),
+                None => (business_name.to_string(), "all".to_string()),
+            };
+
+            let path = data_dir.join("calendars").join(format!("{}.ics", file_name));
+            std::fs::create_dir_all(data_dir.join("calendars"))?;
+            std::fs::write(&path, calendar::export(&calendar_name, bookings.timezone(), &events))?;
+            println!("Wrote {} bookings to {}", events.len(), path.display());
+        }
+        (Some("import-ics"), Some(file)) => {
+            let staff_id = match arg(3) {
+                Some(name) => Some(find_staff(&bookings, name).await?.staff_id),
+                None => None,
+            };
+            let imported = calendar::import(&std::fs::read_to_string(file)?, bookings.timezone(), Utc::now())?;
+            // Importing the same file again replaces what it brought in last time. Keyed on the full path, so two
+            // calendars that happen to share a file name don't replace each other.
+            let source = std::fs::canonicalize(file)?.display().to_string();
+
+            for skipped in &imported.skipped {
+                println!("Skipped {}", skipped);
+            }
+            let count = bookings.import_busy(staff_id, &source, &imported.events).await?;
+            println!("Imported {} busy times from {}", count, source);
+        }
+        _ => return Err(usage()),
+    }
+
+    Ok(())
+}
+
+// knowledge "<business name>" <command>, see KNOWLEDGE_USAGE
+const KNOWLEDGE_USAGE: &str = "knowledge \"<business>\" list | add <file or directory>... | remove <source> | refresh | search <query>";
+
+async fn run_knowledge_command(args: &[String]) -> Result<(), AppError> {
+    let usage = || AppError::UserInput(format!("usage: {}", KNOWLEDGE_USAGE));
+
+    let business_name = args.first().ok_or_else(usage)?;
+    let data_dir = business_data_dir(business_name);
+
+    let mut openai_helper = OpenAIHelper::new()?;
+    openai_helper.track_usage(UsageTracker::open(business_name, &data_dir)?);
+    let mut knowledge = KnowledgeBase::open(&data_dir.join("knowledge.json"), openai_helper.embedding_model())?;
+
+    match (args.get(1).map(String::as_str), &args[2.min(args.len())..]) {
+        (Some("list"), _) => {
+            for document in knowledge.documents() {
+                println!("{} ({} chunks) {}", document.title, document.chunks.len(), document.source);
+            }
+        }
+        (Some("add"), paths) if !paths.is_empty() => {
+            for path in paths {
+                for document in knowledge.add(Path::new(path))? {
+                    println!("Added {} ({} chunks) from {}", document.title, document.chunks.len(), document.source);
+                }
+            }
+        }
+        (Some("remove"), [source]) => match knowledge.remove(source)? {
+            true => println!("Removed {}", source),
+            false => println!("Nothing was added from {}", source),
+        },
+        (Some("refresh"), _) => {
+            for change in knowledge.refresh()? {
+                println!("{}", change);
+            }
+        }
+        (Some("search"), query) if !query.is_empty() => {
+            for hit in knowledge.search(&query.join(" "), 5, &openai_helper, SETUP_SESSION).await? {
+                println!("[{}] {:.2} {}: {}", hit.chunk.id, hit.score, hit.document.title, hit.chunk.text);
+            }
+        }
+        _ => return Err(usage()),
+    }
+
+    // Whatever changed gets embedded now rather than on the next chat. The documents are saved either way and
+    // can be found by keyword until then.
+    match knowledge.embed_missing(&openai_helper, SETUP_SESSION).await {
+        Ok(0) => {}
+        Ok(embedded) => println!("Embedded {} chunks", embedded),
+        Err(e @ (AppError::LlmTransport(_) | AppError::LlmRequest(_) | AppError::LlmContent(_) | AppError::SpendCap(_))) => {
+            eprintln!("Could not embed the new chunks, they'll be embedded on the next refresh: {}", e);
+        }
+        Err(e) => return Err(e),
+    }
+
+    Ok(())
+}
+
+// reminders "<business name>" [--once], sends reminders and follow-ups as they come due until stopped
+async fn run_reminders(args: &[String]) -> Result<(), AppError> {
+    let business_name = args.first()
+        .ok_or_else(|| AppError::UserInput("usage: reminders \"<business>\" [--once]".to_string()))?;
+    let data_dir = business_data_dir(business_name);
+    let bookings = open_bookings(&data_dir)
+        .await?
+        .ok_or_else(|| AppError::Config(format!("{} hasn't set up bookings", business_name)))?;
+
+    let mut openai_helper = OpenAIHelper::new()?;
+    openai_helper.track_usage(UsageTracker::open(business_name, &data_dir)?);
+
+    let persona = Persona::load(&data_dir.join("persona.json"))?.unwrap_or_default();
+    let contacts: ContactAllowlist = load_saved(&data_dir.join("contacts.json"))?.unwrap_or_default();
+    let sender = Sender {
+        business_name: business_name.to_string(),
+        persona: persona.prompt_section(),
+        contacts: contacts.prompt_section(),
+        link_filter: LinkFilter::new(contacts)?,
+    };
+    let scheduler = ReminderScheduler::new(bookings, reminders::channel_from_env(&data_dir)?, Schedule::from_env()?, sender);
+
+    if args.get(1).map(String::as_str) == Some("--once") {
+        println!("Sent {} notifications", scheduler.run_once(&openai_helper).await?);
+        return Ok(());
     }
+    scheduler.run(&openai_helper).await
+}
+
+// import-website "<business name>" <directory of saved pages>, drafts the setup answers from the business's site and
+// keeps them in website.json for the manager to confirm the next time they run setup
+async fn import_website(args: &[String]) -> Result<(), AppError> {
+    let (Some(business_name), Some(directory)) = (args.first(), args.get(1)) else {
+        return Err(AppError::UserInput("usage: import-website \"<business>\" <directory>".to_string()));
+    };
+    let data_dir = business_data_dir(business_name);
+
+    let pages = website::read_snapshot(Path::new(directory))?;
+    if pages.is_empty() {
+        return Err(AppError::UserInput(format!("no pages with any text in {}", directory)));
+    }
+    println!("Read {} pages", pages.len());
+
+    let mut openai_helper = OpenAIHelper::new()?;
+    openai_helper.track_usage(UsageTracker::open(business_name, &data_dir)?);
+
+    let context_window = ContextWindow::from_env(REPLY_MAX_TOKENS)?;
+    let mut draft = openai_helper.draft_from_website(&context_window, business_name, &pages).await?;
+    draft.pages = pages.into_iter().map(|page| page.path).collect();
+
+    std::fs::create_dir_all(&data_dir)?;
+    std::fs::write(data_dir.join("website.json"), serde_json::to_string_pretty(&draft)?)?;
+
+    println!("Industry: {}", draft.industry.as_deref().unwrap_or("not found"));
+    println!("Description: {}", draft.description.as_deref().unwrap_or("not found"));
+    println!("Services: {}", draft.catalog().items.len());
+    println!(
+        "Answers: {} of {} general questions",
+        GENERIC_QUESTIONS.iter().filter(|question| draft.answer(question).is_some()).count(),
+        GENERIC_QUESTIONS.len()
+    );
+    println!("Run setup for {} to check these, answer 'y' when asked about services and prices to use the ones found.", business_name);
+
+    Ok(())
+}
+
+async fn find_staff(bookings: &BookingService, name: &str) -> Result<Staff, AppError> {
+    bookings.staff()
+        .await?
+        .into_iter()
+        .find(|staff| staff.name.eq_ignore_ascii_case(name))
+        .ok_or_else(|| AppError::UserInput(format!("nobody called {} works here", name)))
 }
 
 #[tokio::main]
-async fn main() -> Result<(), Box<dyn Error>> {
+async fn main() -> Result<(), AppError> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.first().map(String::as_str) == Some("usage-report") {
+        return print_usage_report(&args[1..]);
+    }
+    if args.first().map(String::as_str) == Some("bookings") {
+        return run_bookings_command(&args[1..]).await;
+    }
+    if args.first().map(String::as_str) == Some("knowledge") {
+        return run_knowledge_command(&args[1..]).await;
+    }
+    if args.first().map(String::as_str) == Some("reminders") {
+        return run_reminders(&args[1..]).await;
+    }
+    if args.first().map(String::as_str) == Some("import-website") {
+        return import_website(&args[1..]).await;
+    }
+
     let predictor = SentimentPredictor::new("http://localhost:8000");
 
     println!("Training sentiment predictor...");
@@ -282,85 +1061,147 @@ async fn main() -> Result<(), Box<dyn Error>> {
         Err(e) => eprintln!("Training error: {}", e), // If it's already trained, it will print this error.
     }
 
-    println!("{}", predictor.analyse_sentiment("I love this work!").await?);
-    println!("{}", predictor.analyse_sentiment("I hate this").await?);
+    for sample in ["I love this work!", "I hate this"] {
+        match predictor.analyse_sentiment(sample).await {
+            Ok(prediction) => println!("{}", prediction),
+            Err(e) => eprintln!("Prediction error: {}", e),
+        }
+    }
 
-    let openai_helper: OpenAIHelper = OpenAIHelper::new()?;
+    let mut openai_helper: OpenAIHelper = OpenAIHelper::new()?;
+    let context_window = ContextWindow::from_env(REPLY_MAX_TOKENS)?;
 
-    let business_info = BusinessInfo::collect();
+    let business_info = BusinessInfo::collect()?;
+    let data_dir = business_data_dir(&business_info.business_name);
+
+    openai_helper.track_usage(UsageTracker::open(&business_info.business_name, &data_dir)?);
+    openai_helper.templates.apply_overrides(&slug(&business_info.industry), &data_dir)?;
 
     let (questions, finalised_answers) = openai_helper.generate_questions(&business_info).await?;
 
-    let answered_questions_vec = openai_helper.gather_answers(&questions).await;
+    let answered_questions_vec = openai_helper.gather_answers(&questions).await?;
+
+    let directives_path = data_dir.join("directives.json");
+    let mut directives = DirectiveSet::load(&directives_path)?;
+
+    println!("Would you like to review how the assistant should behave? (y/N)");
+    if read_input()?.eq_ignore_ascii_case("y") {
+        directives.edit_interactively()?;
+        directives.save(&directives_path)?;
+    }
+
+    let settings = BusinessSettings::load_or_collect(&data_dir, business_info.website.as_ref())?;
+    // Bookings need somewhere to keep them, so the question only comes up with a database configured
+    if std::env::var("DATABASE_URL").is_ok() {
+        load_or_collect(
+            &data_dir.join("booking.json"),
+            "Would you like to change how the assistant takes bookings? (y/N)",
+            BookingConfig::collect,
+        )?;
+    }
+    let price_guard = PriceGuard::new(settings.catalog.clone(), &data_dir)?;
+    let link_filter = LinkFilter::new(settings.contacts.clone())?;
     
     print!("{esc}[2J{esc}[1;1H", esc = 27 as char);
-    let mut generated_prompt = generate_prompt(&business_info, &answered_questions_vec, finalised_answers);
+    let mut generated_prompt = generate_prompt(&openai_helper.templates, &business_info, &settings, &directives, &answered_questions_vec, finalised_answers)?;
     println!("\n\nGenerated Prompt: {}", generated_prompt);
 
-    let mut conversation_log: Vec<(String, String)> = Vec::new();
+    let mut profile_store = ProfileStore::open(&data_dir.join("customers.json"))?;
 
-    let mut conversation = vec![
-        ChatCompletionRequestMessageArgs::default()
-            .role(Role::System)
-            .content(&generated_prompt)
-            .build()?
-    ];
+    println!("Customer email, phone number or chat ID (leave blank for an anonymous customer):");
+    let customer_id = read_input()?;
+
+    let identity = CustomerIdentity::parse(&customer_id);
+    let customer_key = identity.as_ref().map(CustomerIdentity::key);
+    // A chat ID isn't something we can reach the customer on
+    let contact = identity.clone().filter(|identity| !matches!(identity, CustomerIdentity::ChatId(_)));
+    let customer = identity.map(|identity| {
+        profile_store.find(&identity).unwrap_or_else(|| CustomerProfile::new(identity))
+    });
+
+    // The chat can go on without bookings if the database is down
+    let bookings = open_bookings(&data_dir).await.unwrap_or_else(|e| {
+        eprintln!("Bookings are unavailable: {}", e);
+        None
+    });
+    let tools = ToolRegistry::new(ToolContext {
+        clock: business_info.clock,
+        dates: DateParser::new(),
+        catalog: settings.catalog.clone(),
+        opening_hours: settings.opening_hours.clone(),
+        bookings,
+        customer: customer_key,
+        draft: Mutex::new(BookingDraft::new(customer.as_ref().and_then(|customer| customer.name.clone()), contact)),
+        escalations_path: data_dir.join("escalations.jsonl"),
+    });
+
+    // Documents edited since the last run are picked up here, the chat goes on with what we have if that fails
+    let mut knowledge = KnowledgeBase::open(&data_dir.join("knowledge.json"), openai_helper.embedding_model())?;
+    let refreshed = match knowledge.refresh() {
+        Ok(changes) => knowledge.embed_missing(&openai_helper, SETUP_SESSION).await.map(|_| changes),
+        Err(e) => Err(e),
+    };
+    match refreshed {
+        Ok(changes) => changes.iter().for_each(|change| println!("{}", change)),
+        Err(e) => eprintln!("Could not refresh the knowledge base: {}", e),
+    }
+    let mut session = ChatSession::new(&generated_prompt, customer, business_info.clock, settings.opening_hours.clone(), knowledge)?;
 
     let stdin = std::io::stdin();
     let mut input = String::new();
 
-    while stdin.read_line(&mut input).is_ok() {
+    while stdin.read_line(&mut input).is_ok_and(|read| read > 0) {
         let input_trim = input.trim();
+
+        if input_trim == "EXIT" {
+            break;
+        }
     
         if input_trim == "TRAIN" {
             println!("Entering training mode...");
             println!("Here are the previous prompts and replies:");
     
-            for (idx, (prompt, reply)) in conversation_log.iter().enumerate() {
-                println!("{}. Prompt: {}", idx + 1, prompt);
-                println!("   Reply: {}", reply);
+            for (idx, entry) in session.conversation_log.iter().enumerate() {
+                println!("{}. Prompt: {}", idx + 1, entry.prompt);
+                println!("   Reply ({}/{}): {}", entry.provider, entry.model, entry.reply);
+                for citation in &entry.citations {
+                    println!("   Source [{}]: {} ({})", citation.chunk_id, citation.title, citation.source);
+                }
             }
     
             println!("Select a number to edit the reply or type 'exit' to exit training mode.");
-            let mut choice = String::new();
-            stdin.read_line(&mut choice)?;
-            let choice = choice.trim();
+            let choice = read_input()?;
             if choice == "exit" {
                 input.clear();
                 continue;
             }
     
-            let choice: usize = choice.parse()?;
-            if choice > 0 && choice <= conversation_log.len() {
-                println!("Current reply: {}", conversation_log[choice - 1].1);
+            let choice: usize = choice.parse().unwrap_or(0);
+            if choice > 0 && choice <= session.conversation_log.len() {
+                println!("Current reply: {}", session.conversation_log[choice - 1].reply);
                 println!("Provide the desired reply:");
-                let mut new_reply = String::new();
-                stdin.read_line(&mut new_reply)?;
+                let new_reply = read_input()?;
             
                 // Update the generated prompt based on the new reply
                 generated_prompt = update_prompt(
                     generated_prompt.clone(),
-                    conversation_log[choice - 1].0.clone(),
+                    session.conversation_log[choice - 1].prompt.clone(),
                     new_reply.trim().to_string()
                 );
             
                 println!("Updated prompt: {}", generated_prompt);
 
                 let explicit_directive = format!("[TRAINDATA] For the prompt '{}', you must always reply with '{}'.", 
-                    conversation_log[choice - 1].0, 
+                    session.conversation_log[choice - 1].prompt, 
                     new_reply.trim()
                 );
 
                 println!("Training directive: {}", explicit_directive);
 
-                conversation.push(ChatCompletionRequestMessageArgs::default()
-                    .role(Role::System)
-                    .content(&explicit_directive)
-                    .build()?
-                );
+                session.push(Role::System, &explicit_directive)?;
 
                 // Update the conversation log with the new reply
-                conversation_log[choice - 1].1 = new_reply.trim().to_string();
+                session.conversation_log[choice - 1].reply = new_reply.trim().to_string();
 
             } else {
                 println!("Invalid choice.");
@@ -380,35 +1221,41 @@ async fn main() -> Result<(), Box<dyn Error>> {
         
         println!("> {} {}", sentiment_prediction, "User".blue().bold());
 
-        conversation.push(ChatCompletionRequestMessageArgs::default()
-            .role(Role::User)
-            .content(input_trim)
-            .build()?
-        );
-        
-    
-        let request = CreateChatCompletionRequestArgs::default()
-            .max_tokens(512u16)
-            .model(GPT_VERSION)
-            .messages(conversation.clone())
-            .build()?;
-    
-        let response = openai_helper.client.chat().create(request).await?;
-    
-        for choice in &response.choices {
-            if let Some(content) = &choice.message.content {
-                conversation_log.push((input_trim.to_string(), content.clone()));
-    
-                println!("{}> {}", "Assistant".green().bold(), content.cyan());
-                conversation.push(ChatCompletionRequestMessageArgs::default()
-                    .role(Role::Assistant)
-                    .content(content.clone())
-                    .build()?
-                );
+        match chat_turn(&openai_helper, &context_window, &price_guard, &link_filter, &tools, &mut session, input_trim).await {
+            Ok(reply) => {
+                println!("{}> {}", "Assistant".green().bold(), reply.content.cyan());
+                session.push(Role::Assistant, &reply.content)?;
+
+                session.conversation_log.push(LogEntry {
+                    prompt: input_trim.to_string(),
+                    reply: reply.content,
+                    provider: reply.provider,
+                    model: reply.model,
+                    citations: reply.citations,
+                });
+            }
+            Err(e) => {
+                session.forget_unanswered_turn();
+                println!("{}> {}", "Assistant".green().bold(), degrade(e)?.cyan());
             }
         }
     
         input.clear();
     }
-        Ok(())
+
+    match session.finish(&openai_helper).await {
+        Ok(Some(profile)) => {
+            if let Err(e) = profile_store.save(profile) {
+                eprintln!("Could not save the customer profile: {}", e);
+            }
+        }
+        Ok(None) => {}
+        Err(e) => eprintln!("Could not update the customer profile: {}", e),
+    }
+
+    for line in openai_helper.client.health_report() {
+        println!("{}", line);
+    }
+
+    Ok(())
 }
\ No newline at end of file
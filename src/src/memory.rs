@@ -0,0 +1,120 @@
+use async_openai::types::{ChatCompletionRequestMessage, ChatCompletionRequestMessageArgs, Role};
+
+use crate::context::ContextWindow;
+use crate::error::AppError;
+
+// Marks the system message holding the running summary, so it can be found and replaced on refresh
+pub const SUMMARY_MARKER: &str = "[SUMMARY]";
+
+// Renders the customer/assistant part of a conversation as plain text for the summarisation prompts
+pub fn transcript(messages: &[ChatCompletionRequestMessage]) -> String {
+    messages
+        .iter()
+        .filter_map(|message| {
+            let speaker = match message.role {
+                Role::User => "Customer",
+                Role::Assistant => "Assistant",
+                _ => return None,
+            };
+            message.content.as_ref().map(|content| format!("{}: {}", speaker, content))
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+pub struct ConversationMemory {
+    pub summary: Option<String>,
+    refresh_every: usize,
+    keep_recent: usize,
+    turns_since_refresh: usize,
+}
+
+impl ConversationMemory {
+    pub fn new(refresh_every: usize, keep_recent: usize) -> Self {
+        ConversationMemory {
+            summary: None,
+            refresh_every,
+            keep_recent,
+            turns_since_refresh: 0,
+        }
+    }
+
+    // SUMMARY_REFRESH_TURNS and SUMMARY_KEEP_RECENT can be set to tune how often older turns are folded in
+    pub fn from_env() -> Self {
+        let refresh_every = std::env::var("SUMMARY_REFRESH_TURNS").ok().and_then(|v| v.parse().ok()).unwrap_or(10);
+        let keep_recent = std::env::var("SUMMARY_KEEP_RECENT").ok().and_then(|v| v.parse().ok()).unwrap_or(6);
+
+        ConversationMemory::new(refresh_every, keep_recent)
+    }
+
+    pub fn record_turn(&mut self) {
+        self.turns_since_refresh += 1;
+    }
+
+    pub fn refresh_due(&self) -> bool {
+        self.turns_since_refresh >= self.refresh_every
+    }
+
+    pub fn update(&mut self, summary: String) {
+        self.summary = Some(summary);
+        self.turns_since_refresh = 0;
+    }
+
+    // Removes every unpinned message older than the last `keep_recent`, starting the kept part on a user message
+    pub fn take_stale(&self, conversation: &mut Vec<ChatCompletionRequestMessage>) -> Vec<ChatCompletionRequestMessage> {
+        let unpinned: Vec<usize> = conversation
+            .iter()
+            .enumerate()
+            .filter(|(_, message)| !ContextWindow::is_pinned(message))
+            .map(|(index, _)| index)
+            .collect();
+
+        if unpinned.len() <= self.keep_recent {
+            return Vec::new();
+        }
+
+        let Some(cut) = unpinned[unpinned.len() - self.keep_recent..]
+            .iter()
+            .copied()
+            .find(|&index| conversation[index].role == Role::User)
+        else {
+            return Vec::new();
+        };
+
+        let mut stale = Vec::new();
+        let mut index = 0;
+        while index < cut - stale.len() {
+            if ContextWindow::is_pinned(&conversation[index]) {
+                index += 1;
+            } else {
+                stale.push(conversation.remove(index));
+            }
+        }
+
+        stale
+    }
+
+    // Inserts the summary right after the generated prompt, replacing any previous one
+    pub fn apply(&self, conversation: &mut Vec<ChatCompletionRequestMessage>) -> Result<(), AppError> {
+        let Some(summary) = &self.summary else {
+            return Ok(());
+        };
+
+        let message = ChatCompletionRequestMessageArgs::default()
+            .role(Role::System)
+            .content(format!("{} Summary of the earlier part of this conversation with the customer: {}", SUMMARY_MARKER, summary))
+            .build()?;
+
+        let existing = conversation.iter().position(|message| {
+            message.role == Role::System
+                && message.content.as_deref().is_some_and(|content| content.starts_with(SUMMARY_MARKER))
+        });
+
+        match existing {
+            Some(index) => conversation[index] = message,
+            None => conversation.insert(1.min(conversation.len()), message),
+        }
+
+        Ok(())
+    }
+}
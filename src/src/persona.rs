@@ -0,0 +1,186 @@
+use serde_derive::{Serialize, Deserialize};
+use std::path::Path;
+
+use crate::error::AppError;
+use crate::read_input;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Formality {
+    Casual,
+    Friendly,
+    Formal,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EmojiUsage {
+    Never,
+    Occasional,
+    Frequent,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Spelling {
+    British,
+    American,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReplyLength {
+    Short,
+    Medium,
+    Detailed,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Persona {
+    pub assistant_name: Option<String>,
+    pub formality: Formality,
+    pub emoji: EmojiUsage,
+    pub spelling: Spelling,
+    pub reply_length: ReplyLength,
+    pub sample_replies: Vec<String>,
+}
+
+impl Default for Persona {
+    fn default() -> Self {
+        Persona {
+            assistant_name: None,
+            formality: Formality::Friendly,
+            emoji: EmojiUsage::Never,
+            spelling: Spelling::British,
+            reply_length: ReplyLength::Medium,
+            sample_replies: Vec::new(),
+        }
+    }
+}
+
+// Asks a numbered multiple choice question, blank or anything unrecognised keeps the default
+fn choose<T: Copy>(question: &str, options: &[(&str, T)], default: T) -> Result<T, AppError> {
+    println!("{}", question);
+    for (index, (label, _)) in options.iter().enumerate() {
+        println!("  {}. {}", index + 1, label);
+    }
+
+    let answer = read_input()?;
+    Ok(answer
+        .parse::<usize>()
+        .ok()
+        .and_then(|number| options.get(number.wrapping_sub(1)))
+        .map(|(_, value)| *value)
+        .unwrap_or(default))
+}
+
+impl Persona {
+    pub fn collect() -> Result<Self, AppError> {
+        let default = Persona::default();
+
+        println!("What should the assistant call itself? (leave blank for no name)");
+        let name = read_input()?;
+
+        let formality = choose("How formal should the assistant be?", &[
+            ("Casual", Formality::Casual),
+            ("Friendly", Formality::Friendly),
+            ("Formal", Formality::Formal),
+        ], default.formality)?;
+
+        let emoji = choose("Should the assistant use emoji?", &[
+            ("Never", EmojiUsage::Never),
+            ("Occasionally", EmojiUsage::Occasional),
+            ("Frequently", EmojiUsage::Frequent),
+        ], default.emoji)?;
+
+        let spelling = choose("Which spelling should the assistant use?", &[
+            ("British English", Spelling::British),
+            ("American English", Spelling::American),
+        ], default.spelling)?;
+
+        let reply_length = choose("How long should replies usually be?", &[
+            ("Short and to the point", ReplyLength::Short),
+            ("A few sentences", ReplyLength::Medium),
+            ("Detailed", ReplyLength::Detailed),
+        ], default.reply_length)?;
+
+        println!("Please provide some example replies written the way you'd like the assistant to sound, one per line (leave a blank line to finish):");
+        let mut sample_replies = Vec::new();
+        loop {
+            let sample = read_input()?;
+            if sample.is_empty() {
+                break;
+            }
+            sample_replies.push(sample);
+        }
+
+        Ok(Persona {
+            assistant_name: (!name.is_empty()).then_some(name),
+            formality,
+            emoji,
+            spelling,
+            reply_length,
+            sample_replies,
+        })
+    }
+
+    pub fn load(path: &Path) -> Result<Option<Self>, AppError> {
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        serde_json::from_str(&std::fs::read_to_string(path)?)
+            .map(Some)
+            .map_err(|e| AppError::Config(format!("{}: {}", path.display(), e)))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), AppError> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(path, serde_json::to_string_pretty(self)?)?;
+
+        Ok(())
+    }
+
+    // The voice instructions that go into the system prompt
+    pub fn prompt_section(&self) -> String {
+        let mut section = Vec::new();
+
+        if let Some(name) = &self.assistant_name {
+            section.push(format!("Your name is {}, introduce yourself by it when it feels natural.", name));
+        }
+
+        section.push(match self.formality {
+            Formality::Casual => "Write in a relaxed, casual tone, like a friendly member of staff chatting to a regular.",
+            Formality::Friendly => "Write in a warm and friendly but still polished tone.",
+            Formality::Formal => "Write in a formal, polite tone and avoid slang or contractions.",
+        }.to_string());
+
+        section.push(match self.emoji {
+            EmojiUsage::Never => "Never use emoji.",
+            EmojiUsage::Occasional => "You may use the occasional emoji where it feels natural, at most one per reply.",
+            EmojiUsage::Frequent => "Feel free to use emoji to keep replies upbeat.",
+        }.to_string());
+
+        section.push(match self.spelling {
+            Spelling::British => "Use British English spelling (e.g. \"colour\", \"organise\", \"centre\").",
+            Spelling::American => "Use American English spelling (e.g. \"color\", \"organize\", \"center\").",
+        }.to_string());
+
+        section.push(match self.reply_length {
+            ReplyLength::Short => "Keep replies short, one to three sentences, unless the customer asks for more detail.",
+            ReplyLength::Medium => "Keep replies to a short paragraph unless the question needs more.",
+            ReplyLength::Detailed => "Give thorough, detailed replies that anticipate follow-up questions.",
+        }.to_string());
+
+        if !self.sample_replies.is_empty() {
+            section.push(format!(
+                "Here are example replies written by the business, match their voice:\n{}",
+                self.sample_replies.iter().map(|sample| format!("- \"{}\"", sample)).collect::<Vec<String>>().join("\n")
+            ));
+        }
+
+        section.join(" ")
+    }
+}
@@ -0,0 +1,185 @@
+use chrono::{DateTime, Utc};
+use serde_derive::{Serialize, Deserialize};
+use std::path::{Path, PathBuf};
+
+use crate::error::AppError;
+
+// How many past conversation summaries are kept per customer, and how many of those go into the prompt
+const MAX_STORED_SUMMARIES: usize = 20;
+const MAX_PROMPT_SUMMARIES: usize = 3;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum CustomerIdentity {
+    Email(String),
+    Phone(String),
+    ChatId(String),
+}
+
+impl CustomerIdentity {
+    // Guesses the kind of identifier from what the customer typed, normalising it so lookups match
+    pub fn parse(input: &str) -> Option<Self> {
+        let input = input.trim();
+        if input.is_empty() {
+            return None;
+        }
+
+        if input.contains('@') {
+            return Some(CustomerIdentity::Email(input.to_lowercase()));
+        }
+
+        let digits: String = input.chars().filter(|c| c.is_ascii_digit()).collect();
+        let looks_like_phone = digits.len() >= 7
+            && input.chars().all(|c| c.is_ascii_digit() || " +-()".contains(c));
+
+        if looks_like_phone {
+            let prefix = if input.starts_with('+') { "+" } else { "" };
+            return Some(CustomerIdentity::Phone(format!("{}{}", prefix, digits)));
+        }
+
+        Some(CustomerIdentity::ChatId(input.to_string()))
+    }
+
+    // How the customer is recorded outside the profile store, e.g. on their bookings
+    pub fn key(&self) -> String {
+        match self {
+            CustomerIdentity::Email(email) => format!("email:{}", email),
+            CustomerIdentity::Phone(phone) => format!("phone:{}", phone),
+            CustomerIdentity::ChatId(chat_id) => format!("chat:{}", chat_id),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PastConversation {
+    pub ended_at: DateTime<Utc>,
+    pub summary: String,
+}
+
+// What we learnt about the customer by the end of a session
+#[derive(Debug, Default, Deserialize)]
+pub struct SessionNotes {
+    pub summary: String,
+    pub name: Option<String>,
+    #[serde(default)]
+    pub preferences: Vec<String>,
+    #[serde(default)]
+    pub open_issues: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CustomerProfile {
+    pub identities: Vec<CustomerIdentity>,
+    pub name: Option<String>,
+    pub preferences: Vec<String>,
+    pub open_issues: Vec<String>,
+    pub conversations: Vec<PastConversation>,
+}
+
+impl CustomerProfile {
+    pub fn new(identity: CustomerIdentity) -> Self {
+        CustomerProfile {
+            identities: vec![identity],
+            name: None,
+            preferences: Vec::new(),
+            open_issues: Vec::new(),
+            conversations: Vec::new(),
+        }
+    }
+
+    pub fn is_returning(&self) -> bool {
+        !self.conversations.is_empty()
+    }
+
+    pub fn record(&mut self, notes: SessionNotes) {
+        if notes.name.as_deref().is_some_and(|name| !name.trim().is_empty()) {
+            self.name = notes.name;
+        }
+
+        // The notes are built from the previous lists, so they replace them rather than add to them
+        self.preferences = notes.preferences;
+        self.open_issues = notes.open_issues;
+
+        self.conversations.push(PastConversation {
+            ended_at: Utc::now(),
+            summary: notes.summary,
+        });
+
+        if self.conversations.len() > MAX_STORED_SUMMARIES {
+            let excess = self.conversations.len() - MAX_STORED_SUMMARIES;
+            self.conversations.drain(..excess);
+        }
+    }
+
+    // Facts about a returning customer, appended to the system prompt
+    pub fn prompt_context(&self) -> String {
+        let mut context = String::from(
+            "You are speaking with a returning customer. Use what you know about them naturally, without reciting it back to them:"
+        );
+
+        if let Some(name) = &self.name {
+            context.push_str(&format!("\n- Name: {}", name));
+        }
+        if !self.preferences.is_empty() {
+            context.push_str(&format!("\n- Preferences: {}", self.preferences.join("; ")));
+        }
+        if !self.open_issues.is_empty() {
+            context.push_str(&format!("\n- Open issues to follow up on: {}", self.open_issues.join("; ")));
+        }
+
+        let recent = self.conversations.len().saturating_sub(MAX_PROMPT_SUMMARIES);
+        for conversation in &self.conversations[recent..] {
+            context.push_str(&format!(
+                "\n- Conversation on {}: {}",
+                conversation.ended_at.format("%Y-%m-%d"), conversation.summary
+            ));
+        }
+
+        context
+    }
+}
+
+// Customer profiles for a single business, kept as a JSON file on disk
+pub struct ProfileStore {
+    path: PathBuf,
+    profiles: Vec<CustomerProfile>,
+}
+
+impl ProfileStore {
+    pub fn open(path: &Path) -> Result<Self, AppError> {
+        let profiles = if path.exists() {
+            serde_json::from_str(&std::fs::read_to_string(path)?)?
+        } else {
+            Vec::new()
+        };
+
+        Ok(ProfileStore {
+            path: path.to_path_buf(),
+            profiles,
+        })
+    }
+
+    pub fn find(&self, identity: &CustomerIdentity) -> Option<CustomerProfile> {
+        self.profiles
+            .iter()
+            .find(|profile| profile.identities.contains(identity))
+            .cloned()
+    }
+
+    pub fn save(&mut self, profile: CustomerProfile) -> Result<(), AppError> {
+        let existing = self.profiles.iter().position(|stored| {
+            stored.identities.iter().any(|identity| profile.identities.contains(identity))
+        });
+
+        match existing {
+            Some(index) => self.profiles[index] = profile,
+            None => self.profiles.push(profile),
+        }
+
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(&self.path, serde_json::to_string_pretty(&self.profiles)?)?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,171 @@
+use async_openai::types::{CreateChatCompletionRequest, CreateChatCompletionResponse};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::error::AppError;
+use crate::llm::LlmClient;
+
+#[derive(Debug, Default)]
+struct ProviderHealth {
+    consecutive_failures: u32,
+    // Set while the circuit is open, requests are skipped until the cooldown has passed
+    opened_at: Option<Instant>,
+    successes: u64,
+    failures: u64,
+}
+
+pub struct Provider {
+    pub name: String,
+    // A provider with its own model (e.g. a local one) ignores the model asked for in the request
+    pub model: Option<String>,
+    client: LlmClient,
+    health: Mutex<ProviderHealth>,
+}
+
+impl Provider {
+    pub fn new(name: &str, model: Option<String>, client: LlmClient) -> Self {
+        Provider {
+            name: name.to_string(),
+            model,
+            client,
+            health: Mutex::new(ProviderHealth::default()),
+        }
+    }
+}
+
+// The response along with which provider and model produced it
+pub struct ServedResponse {
+    pub response: CreateChatCompletionResponse,
+    pub provider: String,
+    pub model: String,
+}
+
+// Ordered list of providers, requests go to the first healthy one and fall through on failure
+pub struct ProviderChain {
+    providers: Vec<Provider>,
+    failure_threshold: u32,
+    cooldown: Duration,
+}
+
+impl ProviderChain {
+    pub fn new(providers: Vec<Provider>, failure_threshold: u32, cooldown: Duration) -> Self {
+        ProviderChain {
+            providers,
+            failure_threshold: failure_threshold.max(1),
+            cooldown,
+        }
+    }
+
+    // LLM_PROVIDERS lists the chain in order (default "openai"), LLM_<NAME>_MODEL pins a provider to
+    // a model, otherwise it uses whichever model the request asks for. LLM_CIRCUIT_THRESHOLD and
+    // LLM_CIRCUIT_COOLDOWN_SECS tune the circuit breaker.
+    pub fn from_env() -> Result<Self, AppError> {
+        let names = std::env::var("LLM_PROVIDERS").unwrap_or_else(|_| "openai".to_string());
+
+        let mut providers = Vec::new();
+        for name in names.split(',').map(str::trim).filter(|name| !name.is_empty()) {
+            let model = std::env::var(format!("LLM_{}_MODEL", name.to_uppercase())).ok();
+
+            providers.push(Provider::new(name, model, LlmClient::from_env(name)?));
+        }
+
+        if providers.is_empty() {
+            return Err(AppError::Config("LLM_PROVIDERS does not name any provider".to_string()));
+        }
+
+        let setting = |name: &str, default: u64| {
+            std::env::var(name).ok().and_then(|value| value.parse().ok()).unwrap_or(default)
+        };
+
+        Ok(ProviderChain::new(
+            providers,
+            setting("LLM_CIRCUIT_THRESHOLD", 3) as u32,
+            Duration::from_secs(setting("LLM_CIRCUIT_COOLDOWN_SECS", 60)),
+        ))
+    }
+
+    // Closed circuits always pass, an open one lets a single trial request through once the cooldown is over
+    fn is_available(&self, provider: &Provider) -> bool {
+        let mut health = provider.health.lock().unwrap();
+
+        match health.opened_at {
+            None => true,
+            Some(opened_at) if opened_at.elapsed() >= self.cooldown => {
+                // Half-open, restart the cooldown so concurrent sessions don't all pile on
+                health.opened_at = Some(Instant::now());
+                true
+            }
+            Some(_) => false,
+        }
+    }
+
+    fn record_success(&self, provider: &Provider) {
+        let mut health = provider.health.lock().unwrap();
+        health.successes += 1;
+        health.consecutive_failures = 0;
+        health.opened_at = None;
+    }
+
+    fn record_failure(&self, provider: &Provider) {
+        let mut health = provider.health.lock().unwrap();
+        health.failures += 1;
+        health.consecutive_failures += 1;
+
+        if health.consecutive_failures >= self.failure_threshold {
+            health.opened_at = Some(Instant::now());
+        }
+    }
+
+    pub async fn create_chat(&self, request: &CreateChatCompletionRequest) -> Result<ServedResponse, AppError> {
+        let mut last_error = None;
+
+        for provider in &self.providers {
+            if !self.is_available(provider) {
+                continue;
+            }
+
+            let mut request = request.clone();
+            if let Some(model) = &provider.model {
+                request.model = model.clone();
+            }
+
+            match provider.client.create_chat(&request).await {
+                Ok(response) => {
+                    self.record_success(provider);
+                    return Ok(ServedResponse {
+                        response,
+                        provider: provider.name.clone(),
+                        model: request.model,
+                    });
+                }
+                // A rejected request (LlmRequest) is the request's fault, not the provider's, so it goes straight back
+                // rather than opening the circuit and being sent to every fallback in turn
+                Err(e @ (AppError::LlmTransport(_) | AppError::LlmContent(_))) => {
+                    eprintln!("Provider {} failed: {}", provider.name, e);
+                    self.record_failure(provider);
+                    last_error = Some(e);
+                }
+                Err(e) => return Err(e),
+            }
+        }
+
+        Err(last_error.unwrap_or_else(|| {
+            AppError::LlmTransport("every provider is unavailable, waiting for their circuits to close".to_string())
+        }))
+    }
+
+    // One line per provider, e.g. for printing at the end of a session
+    pub fn health_report(&self) -> Vec<String> {
+        self.providers
+            .iter()
+            .map(|provider| {
+                let health = provider.health.lock().unwrap();
+                format!(
+                    "{} ({}): {} ok, {} failed, circuit {}",
+                    provider.name, provider.model.as_deref().unwrap_or("requested model"), health.successes, health.failures,
+                    if health.opened_at.is_some() { "open" } else { "closed" }
+                )
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,397 @@
+use chrono::{DateTime, Duration, Utc};
+use serde_derive::{Serialize, Deserialize};
+use std::future::Future;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use tera::Context;
+
+use crate::booking::{Booking, BookingService};
+use crate::error::AppError;
+use crate::links::LinkFilter;
+use crate::OpenAIHelper;
+
+// Follow-ups that are this late aren't worth sending, e.g. after the scheduler was down for a while
+const FOLLOW_UP_WINDOW_DAYS: i64 = 7;
+
+const SENT: &str = "sent";
+// A longer reminder that came due at the same time as a shorter one, only the shorter one goes out
+const SKIPPED: &str = "skipped";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Notification {
+    pub booking_id: uuid::Uuid,
+    // "reminder_24h", "reminder_1h", "follow_up"
+    pub kind: String,
+    // The customer's identity key, "email:..." or "phone:..."
+    pub recipient: String,
+    pub message: String,
+    pub created_at: DateTime<Utc>,
+}
+
+type SendFuture<'a> = Pin<Box<dyn Future<Output = Result<(), AppError>> + Send + 'a>>;
+
+// Where notifications go. Email and SMS providers plug in here, the file and log channels are for trying it out.
+pub trait NotificationChannel: Send + Sync {
+    fn send<'a>(&'a self, notification: &'a Notification) -> SendFuture<'a>;
+}
+
+// Appends each notification to notifications.jsonl in the business's data directory
+pub struct FileChannel {
+    path: PathBuf,
+}
+
+impl NotificationChannel for FileChannel {
+    fn send<'a>(&'a self, notification: &'a Notification) -> SendFuture<'a> {
+        Box::pin(async move {
+            if let Some(parent) = self.path.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            let mut log = std::fs::OpenOptions::new().create(true).append(true).open(&self.path)?;
+            writeln!(log, "{}", serde_json::to_string(notification)?)?;
+            Ok(())
+        })
+    }
+}
+
+pub struct LogChannel;
+
+impl NotificationChannel for LogChannel {
+    fn send<'a>(&'a self, notification: &'a Notification) -> SendFuture<'a> {
+        Box::pin(async move {
+            println!("[{}] to {}: {}", notification.kind, notification.recipient, notification.message);
+            Ok(())
+        })
+    }
+}
+
+// POSTs the notification as JSON, for whatever actually sends the email or text
+pub struct WebhookChannel {
+    url: String,
+    http_client: reqwest::Client,
+}
+
+impl NotificationChannel for WebhookChannel {
+    fn send<'a>(&'a self, notification: &'a Notification) -> SendFuture<'a> {
+        Box::pin(async move {
+            self.http_client
+                .post(&self.url)
+                .json(notification)
+                .send()
+                .await
+                .and_then(|response| response.error_for_status())
+                .map_err(|e| AppError::Notification(e.to_string()))?;
+            Ok(())
+        })
+    }
+}
+
+// NOTIFY_CHANNEL is "file" (default), "log" or "webhook", which posts to NOTIFY_WEBHOOK_URL
+pub fn channel_from_env(data_dir: &Path) -> Result<Box<dyn NotificationChannel>, AppError> {
+    match std::env::var("NOTIFY_CHANNEL").as_deref() {
+        Ok("file") | Err(_) => Ok(Box::new(FileChannel { path: data_dir.join("notifications.jsonl") })),
+        Ok("log") => Ok(Box::new(LogChannel)),
+        Ok("webhook") => Ok(Box::new(WebhookChannel {
+            url: std::env::var("NOTIFY_WEBHOOK_URL")
+                .map_err(|_| AppError::Config("NOTIFY_WEBHOOK_URL must be set for the webhook channel".to_string()))?,
+            http_client: reqwest::Client::new(),
+        })),
+        Ok(other) => Err(AppError::Config(format!("Unknown NOTIFY_CHANNEL '{}'", other))),
+    }
+}
+
+// "24h", "90m", "2d", always more than zero
+fn parse_offset(text: &str) -> Result<Duration, AppError> {
+    let text = text.trim();
+    let invalid = || AppError::Config(format!("'{}' should be a time like 24h, 90m or 2d", text));
+    let (number, unit_minutes) = [("m", 1), ("h", 60), ("d", 24 * 60)]
+        .into_iter()
+        .find_map(|(suffix, unit_minutes)| text.strip_suffix(suffix).map(|number| (number, unit_minutes)))
+        .ok_or_else(invalid)?;
+    let number: i64 = number.parse().map_err(|_| invalid())?;
+    if number <= 0 {
+        return Err(AppError::Config(format!("'{}' should be more than zero", text)));
+    }
+
+    number.checked_mul(unit_minutes).and_then(Duration::try_minutes).ok_or_else(invalid)
+}
+
+// Whole hours where it can, so the default reminders are "reminder_24h" and "reminder_1h"
+fn offset_name(offset: Duration) -> String {
+    match (offset.num_hours(), offset.num_minutes()) {
+        (hours, minutes) if hours > 0 && minutes == hours * 60 => format!("{}h", hours),
+        (_, minutes) => format!("{}m", minutes),
+    }
+}
+
+// Roughly how long until the appointment, as the customer would say it
+fn describe_time_left(left: Duration) -> String {
+    match left.num_minutes().max(1) {
+        1 => "1 minute".to_string(),
+        minutes if minutes < 90 => format!("{} minutes", minutes),
+        minutes if minutes < 36 * 60 => format!("about {} hours", (minutes + 30) / 60),
+        minutes => format!("about {} days", (minutes + 12 * 60) / (24 * 60)),
+    }
+}
+
+pub struct Schedule {
+    // How long before the appointment, longest first
+    reminders: Vec<Duration>,
+    follow_up_after: Option<Duration>,
+    pub poll_interval: std::time::Duration,
+}
+
+impl Schedule {
+    // REMINDER_OFFSETS (default "24h,1h"), FOLLOW_UP_AFTER after the appointment ends (default "2h", "off" for
+    // none) and REMINDER_POLL_SECS between checks (default 60)
+    pub fn from_env() -> Result<Self, AppError> {
+        let mut reminders = std::env::var("REMINDER_OFFSETS")
+            .unwrap_or_else(|_| "24h,1h".to_string())
+            .split(',')
+            .filter(|offset| !offset.trim().is_empty())
+            .map(parse_offset)
+            .collect::<Result<Vec<Duration>, AppError>>()?;
+        reminders.sort_by_key(|offset| std::cmp::Reverse(*offset));
+
+        let follow_up_after = match std::env::var("FOLLOW_UP_AFTER").as_deref() {
+            Ok("off") => None,
+            Ok(after) => Some(parse_offset(after)?),
+            Err(_) => Some(Duration::hours(2)),
+        };
+
+        let poll_secs = match std::env::var("REMINDER_POLL_SECS") {
+            Ok(value) => value.parse().map_err(|_| AppError::Config(format!("REMINDER_POLL_SECS '{}' is not a number", value)))?,
+            Err(_) => 60,
+        };
+
+        Ok(Schedule { reminders, follow_up_after, poll_interval: std::time::Duration::from_secs(poll_secs) })
+    }
+
+    // The reminder to send for an appointment at starts_at, the shortest one that has come due, along with the
+    // longer ones it makes pointless. None until the longest comes due.
+    fn reminders_due(&self, starts_at: DateTime<Utc>, now: DateTime<Utc>) -> Option<(Duration, &[Duration])> {
+        let due = self.reminders.iter().take_while(|offset| starts_at - **offset <= now).count();
+        let (nearest, passed) = self.reminders[..due].split_last()?;
+
+        Some((*nearest, passed))
+    }
+}
+
+// Everything the messages are drafted from
+pub struct Sender {
+    pub business_name: String,
+    pub persona: String,
+    pub contacts: String,
+    pub link_filter: LinkFilter,
+}
+
+// Sends appointment reminders and follow-ups for one business as they come due
+pub struct ReminderScheduler {
+    bookings: BookingService,
+    channel: Box<dyn NotificationChannel>,
+    schedule: Schedule,
+    sender: Sender,
+}
+
+impl ReminderScheduler {
+    pub fn new(bookings: BookingService, channel: Box<dyn NotificationChannel>, schedule: Schedule, sender: Sender) -> Self {
+        ReminderScheduler { bookings, channel, schedule, sender }
+    }
+
+    fn fallback_message(&self, booking: &Booking, kind: &str, when: &str) -> String {
+        let greeting = booking.customer_name.as_deref().map_or("Hi".to_string(), |name| format!("Hi {}", name));
+        match kind {
+            "follow_up" => format!(
+                "{}, thanks for coming to {} for your {}. We hope you were happy with it, just reply if there's anything we can do.",
+                greeting, self.sender.business_name, booking.service
+            ),
+            _ => format!("{}, a reminder of your {} at {} {}. See you then!", greeting, booking.service, self.sender.business_name, when),
+        }
+    }
+
+    // The model writes it in the business's voice. If it can't, a plain message is better than none.
+    async fn draft(&self, helper: &OpenAIHelper, booking: &Booking, kind: &str) -> Result<String, AppError> {
+        let timezone = self.bookings.timezone();
+        let when = booking.starts_at.with_timezone(&timezone).format("on %A %-d %B at %H:%M").to_string();
+        // The kind only says which reminder this is, a booking made late gets the nearest one due rather than one
+        // sent at its usual time, so how long is left comes from the booking itself
+        let (purpose, time_left) = match kind {
+            "follow_up" => ("The appointment below has just happened. Thank them for coming and invite them to reply with any feedback.", String::new()),
+            _ => (
+                "Remind them of the appointment below. Ask them to reply if they need to change it.",
+                describe_time_left(booking.starts_at - Utc::now()),
+            ),
+        };
+        let staff = self.bookings.staff().await?
+            .into_iter()
+            .find(|staff| staff.staff_id == booking.staff_id)
+            .map(|staff| staff.name)
+            .unwrap_or_default();
+
+        let mut context = Context::new();
+        context.insert("business_name", &self.sender.business_name);
+        context.insert("purpose", &purpose);
+        context.insert("customer_name", booking.customer_name.as_deref().unwrap_or(""));
+        context.insert("service", &booking.service);
+        context.insert("staff", &staff);
+        context.insert("when", &when);
+        context.insert("time_left", &time_left);
+        context.insert("persona", &self.sender.persona);
+        context.insert("contacts", &self.sender.contacts);
+
+        match helper.draft_notification(&context, &booking.booking_id.to_string()).await {
+            Ok(message) => Ok(self.sender.link_filter.filter(&message, &[]).content),
+            Err(e @ (AppError::LlmTransport(_) | AppError::LlmRequest(_) | AppError::LlmContent(_) | AppError::SpendCap(_))) => {
+                eprintln!("Could not draft the {} for booking {}, sending a plain one: {}", kind, booking.booking_id, e);
+                Ok(self.fallback_message(booking, kind, &when))
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    // Claims the notification before sending so two schedulers can't both send it, and lets it go again if
+    // sending fails so the next pass retries it
+    async fn send(&self, helper: &OpenAIHelper, booking: &Booking, kind: &str) -> Result<bool, AppError> {
+        if !self.bookings.record_notification(booking.booking_id, kind, SENT).await? {
+            return Ok(false);
+        }
+
+        let sent = async {
+            let notification = Notification {
+                booking_id: booking.booking_id,
+                kind: kind.to_string(),
+                recipient: booking.customer.clone(),
+                message: self.draft(helper, booking, kind).await?,
+                created_at: Utc::now(),
+            };
+            self.channel.send(&notification).await
+        }.await;
+
+        if let Err(e) = sent {
+            self.bookings.forget_notification(booking.booking_id, kind).await?;
+            // One customer's message not getting through shouldn't hold up everyone else's
+            if let AppError::Notification(_) = e {
+                eprintln!("Could not send the {} for booking {}: {}", kind, booking.booking_id, e);
+                return Ok(false);
+            }
+            return Err(e);
+        }
+
+        Ok(true)
+    }
+
+    // One pass over the diary, returning how many notifications went out
+    pub async fn run_once(&self, helper: &OpenAIHelper) -> Result<usize, AppError> {
+        let now = Utc::now();
+        let mut sent = 0;
+
+        if let Some(longest) = self.schedule.reminders.first() {
+            for booking in self.bookings.confirmed_between(now, now + *longest).await? {
+                if booking.starts_at <= now {
+                    continue;
+                }
+
+                let Some((nearest, passed)) = self.schedule.reminders_due(booking.starts_at, now) else {
+                    continue;
+                };
+
+                for offset in passed {
+                    self.bookings.record_notification(booking.booking_id, &format!("reminder_{}", offset_name(*offset)), SKIPPED).await?;
+                }
+                if self.send(helper, &booking, &format!("reminder_{}", offset_name(nearest))).await? {
+                    sent += 1;
+                }
+            }
+        }
+
+        if let Some(after) = self.schedule.follow_up_after {
+            let latest_end = now - after;
+            for booking in self.bookings.confirmed_between(latest_end - Duration::days(FOLLOW_UP_WINDOW_DAYS), latest_end).await? {
+                if booking.ends_at <= latest_end && self.send(helper, &booking, "follow_up").await? {
+                    sent += 1;
+                }
+            }
+        }
+
+        Ok(sent)
+    }
+
+    // Keeps going until stopped, a failed pass is reported and tried again next time
+    pub async fn run(&self, helper: &OpenAIHelper) -> Result<(), AppError> {
+        loop {
+            match self.run_once(helper).await {
+                Ok(0) => {}
+                Ok(sent) => println!("Sent {} notifications", sent),
+                Err(e @ AppError::Config(_)) => return Err(e),
+                Err(e) => eprintln!("Reminder run failed: {}", e),
+            }
+            tokio::time::sleep(self.schedule.poll_interval).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn schedule(offsets: &[&str]) -> Schedule {
+        let mut reminders: Vec<Duration> = offsets.iter().map(|offset| parse_offset(offset).unwrap()).collect();
+        reminders.sort_by_key(|offset| std::cmp::Reverse(*offset));
+
+        Schedule { reminders, follow_up_after: None, poll_interval: std::time::Duration::from_secs(60) }
+    }
+
+    #[test]
+    fn offsets_parse_minutes_hours_and_days() {
+        assert_eq!(parse_offset("90m").unwrap(), Duration::minutes(90));
+        assert_eq!(parse_offset(" 24h ").unwrap(), Duration::hours(24));
+        assert_eq!(parse_offset("2d").unwrap(), Duration::days(2));
+
+        for invalid in ["", "h", "24", "24 h", "1.5h", "24H", "24ч", "ч", "-1h", "0m", "1w", "9223372036854775807d"] {
+            assert!(matches!(parse_offset(invalid), Err(AppError::Config(_))), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn offsets_are_named_in_whole_hours_where_they_can_be() {
+        assert_eq!(offset_name(Duration::hours(24)), "24h");
+        assert_eq!(offset_name(Duration::days(2)), "48h");
+        assert_eq!(offset_name(Duration::minutes(60)), "1h");
+        assert_eq!(offset_name(Duration::minutes(90)), "90m");
+        assert_eq!(offset_name(Duration::minutes(30)), "30m");
+    }
+
+    #[test]
+    fn time_left_is_rounded_the_way_a_person_would_say_it() {
+        assert_eq!(describe_time_left(Duration::seconds(20)), "1 minute");
+        assert_eq!(describe_time_left(Duration::minutes(-5)), "1 minute");
+        assert_eq!(describe_time_left(Duration::minutes(45)), "45 minutes");
+        assert_eq!(describe_time_left(Duration::minutes(89)), "89 minutes");
+        assert_eq!(describe_time_left(Duration::minutes(90)), "about 2 hours");
+        assert_eq!(describe_time_left(Duration::minutes(200)), "about 3 hours");
+        assert_eq!(describe_time_left(Duration::hours(23) + Duration::minutes(50)), "about 24 hours");
+        assert_eq!(describe_time_left(Duration::hours(36)), "about 2 days");
+        assert_eq!(describe_time_left(Duration::days(3) + Duration::hours(11)), "about 3 days");
+    }
+
+    #[test]
+    fn only_the_shortest_reminder_due_is_sent_and_longer_ones_are_skipped() {
+        let schedule = schedule(&["1h", "24h", "3d"]);
+        let starts_at = Utc.with_ymd_and_hms(2026, 10, 20, 10, 0, 0).unwrap();
+        let names = |due: Option<(Duration, &[Duration])>| due.map(|(nearest, passed)| {
+            (offset_name(nearest), passed.iter().map(|offset| offset_name(*offset)).collect::<Vec<String>>())
+        });
+
+        assert_eq!(names(schedule.reminders_due(starts_at, starts_at - Duration::days(4))), None);
+        assert_eq!(names(schedule.reminders_due(starts_at, starts_at - Duration::days(3))), Some(("72h".to_string(), vec![])));
+        assert_eq!(names(schedule.reminders_due(starts_at, starts_at - Duration::hours(20))), Some(("24h".to_string(), vec!["72h".to_string()])));
+        // Booked with half an hour to go, so only the last reminder goes out
+        assert_eq!(
+            names(schedule.reminders_due(starts_at, starts_at - Duration::minutes(30))),
+            Some(("1h".to_string(), vec!["72h".to_string(), "24h".to_string()]))
+        );
+
+        assert_eq!(names(self::schedule(&[]).reminders_due(starts_at, starts_at)), None);
+    }
+}
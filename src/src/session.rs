@@ -0,0 +1,129 @@
+use async_openai::types::{ChatCompletionRequestMessage, ChatCompletionRequestMessageArgs, Role};
+
+use crate::clock::BusinessClock;
+use crate::context::ContextWindow;
+use crate::error::AppError;
+use crate::hours::OpeningHours;
+use crate::knowledge::{Citation, KnowledgeBase};
+use crate::memory::ConversationMemory;
+use crate::profile::CustomerProfile;
+use crate::OpenAIHelper;
+
+pub struct LogEntry {
+    pub prompt: String,
+    pub reply: String,
+    // Which LLM provider and model served the reply
+    pub provider: String,
+    pub model: String,
+    // Knowledge base chunks the reply was based on
+    pub citations: Vec<Citation>,
+}
+
+pub struct ChatSession {
+    pub id: String,
+    pub conversation: Vec<ChatCompletionRequestMessage>,
+    pub conversation_log: Vec<LogEntry>,
+    pub memory: ConversationMemory,
+    pub customer: Option<CustomerProfile>,
+    pub clock: BusinessClock,
+    pub opening_hours: OpeningHours,
+    pub knowledge: KnowledgeBase,
+}
+
+impl ChatSession {
+    pub fn new(prompt: &str, customer: Option<CustomerProfile>, clock: BusinessClock, opening_hours: OpeningHours, knowledge: KnowledgeBase) -> Result<Self, AppError> {
+        let prompt = match customer.as_ref().filter(|customer| customer.is_returning()) {
+            Some(customer) => format!("{}\n{}", prompt, customer.prompt_context()),
+            None => prompt.to_string(),
+        };
+
+        Ok(ChatSession {
+            id: uuid::Uuid::new_v4().to_string(),
+            conversation: vec![
+                ChatCompletionRequestMessageArgs::default()
+                    .role(Role::System)
+                    .content(prompt)
+                    .build()?
+            ],
+            conversation_log: Vec::new(),
+            memory: ConversationMemory::from_env(),
+            customer,
+            clock,
+            opening_hours,
+            knowledge,
+        })
+    }
+
+    pub fn push(&mut self, role: Role, content: &str) -> Result<(), AppError> {
+        self.conversation.push(ChatCompletionRequestMessageArgs::default()
+            .role(role)
+            .content(content)
+            .build()?
+        );
+
+        Ok(())
+    }
+
+    // Drops the customer's last message and any tool calls made for it, so they can simply try again
+    pub fn forget_unanswered_turn(&mut self) {
+        while self.conversation.last().is_some_and(|message| {
+            message.role == Role::User || message.role == Role::Function || message.function_call.is_some()
+        }) {
+            self.conversation.pop();
+        }
+    }
+
+    // Folds older turns into the running summary and trims the conversation so the next request fits
+    pub async fn prepare(&mut self, openai_helper: &OpenAIHelper, context_window: &ContextWindow, model: &str) -> Result<(), AppError> {
+        self.memory.record_turn();
+        let now = self.clock.now();
+        self.clock.apply(&mut self.conversation, now, &self.opening_hours.status_at(now))?;
+
+        let latest = self.conversation.iter().rev().find(|message| message.role == Role::User).and_then(|message| message.content.clone());
+        self.knowledge.apply(&mut self.conversation, latest.as_deref().unwrap_or_default(), openai_helper, &self.id).await?;
+
+        let limit = context_window.limit_for(model);
+
+        let stale = if context_window.count_messages(&self.conversation) > limit {
+            // Trim well below the limit, otherwise we'd be summarising again on the very next turn
+            context_window.fit_within(limit * 3 / 4, &mut self.conversation)
+        } else if self.memory.refresh_due() {
+            self.memory.take_stale(&mut self.conversation)
+        } else {
+            Vec::new()
+        };
+
+        if !stale.is_empty() {
+            // Losing the older turns is better than not answering, so a failed summary doesn't fail the turn
+            match openai_helper.summarise_conversation(self.memory.summary.as_deref(), &stale, &self.id).await {
+                Ok(summary) => {
+                    self.memory.update(summary);
+                    self.memory.apply(&mut self.conversation)?;
+                }
+                Err(e @ (AppError::LlmTransport(_) | AppError::LlmRequest(_) | AppError::LlmContent(_))) => eprintln!("Could not summarise the conversation: {}", e),
+                Err(e) => return Err(e),
+            }
+        }
+
+        // The refreshed summary itself takes up space
+        context_window.fit(model, &mut self.conversation);
+
+        Ok(())
+    }
+
+    // Writes what we learnt this session onto the customer's profile, handing it back to be stored
+    pub async fn finish(&mut self, openai_helper: &OpenAIHelper) -> Result<Option<CustomerProfile>, AppError> {
+        let Some(mut customer) = self.customer.take() else {
+            return Ok(None);
+        };
+
+        if self.conversation_log.is_empty() {
+            return Ok(Some(customer));
+        }
+
+        let notes = openai_helper.session_notes(&customer, self.memory.summary.as_deref(), &self.conversation, &self.id).await?;
+        customer.record(notes);
+
+        Ok(Some(customer))
+    }
+}
@@ -0,0 +1,204 @@
+use std::path::{Path, PathBuf};
+use tera::{Context, Tera};
+
+use crate::directives::Directive;
+use crate::error::AppError;
+
+// Tera keeps the useful part of its errors (which variable, which line) in the source chain
+fn describe(error: &tera::Error) -> String {
+    let mut description = error.to_string();
+    let mut source = std::error::Error::source(error);
+
+    while let Some(cause) = source {
+        description.push_str(&format!(": {}", cause));
+        source = cause.source();
+    }
+
+    description
+}
+
+pub const VAGUENESS_CHECK: &str = "vagueness_check";
+pub const QUESTIONS: &str = "questions";
+pub const QUESTIONS_WITH_ANSWERS: &str = "questions_with_answers";
+pub const SYSTEM_PROMPT: &str = "system_prompt";
+pub const SUMMARY: &str = "summary";
+pub const SESSION_NOTES: &str = "session_notes";
+pub const NOTIFICATION: &str = "notification";
+pub const WEBSITE_IMPORT: &str = "website_import";
+pub const WEBSITE_SERVICES: &str = "website_services";
+
+// Variables holding a list rather than text. Validation gives each a one-item list, so the body of a loop over it
+// is rendered and a misspelt field inside the loop is caught too
+fn sample_list(variable: &str) -> Option<Vec<Directive>> {
+    match variable {
+        "directives" => Some(vec![Directive {
+            id: "sample".to_string(),
+            title: "Sample".to_string(),
+            text: "Sample".to_string(),
+            enabled: true,
+        }]),
+        _ => None,
+    }
+}
+
+// Every template and the variables it is rendered with, a template using anything else fails validation
+const TEMPLATES: &[(&str, &[&str])] = &[
+    (VAGUENESS_CHECK, &["business_name", "industry", "description"]),
+    (QUESTIONS, &["business_name", "industry", "description"]),
+    (QUESTIONS_WITH_ANSWERS, &["business_name", "industry", "description", "generic_answers"]),
+    (SYSTEM_PROMPT, &["business_name", "industry", "description", "answers", "catalog", "contacts", "opening_hours", "persona", "directives"]),
+    (SUMMARY, &["previous_summary", "transcript"]),
+    (SESSION_NOTES, &["name", "preferences", "open_issues", "summary", "transcript"]),
+    (NOTIFICATION, &["business_name", "purpose", "customer_name", "service", "staff", "when", "time_left", "persona", "contacts"]),
+    (WEBSITE_IMPORT, &["business_name", "questions", "pages"]),
+    (WEBSITE_SERVICES, &["business_name", "questions", "pages"]),
+];
+
+const BUILT_IN_VERSION: &str = "v1";
+
+// The v1 templates compiled into the binary, so it works wherever it's installed
+const BUILT_IN: &[(&str, &str)] = &[
+    (VAGUENESS_CHECK, include_str!("../templates/v1/vagueness_check.tera")),
+    (QUESTIONS, include_str!("../templates/v1/questions.tera")),
+    (QUESTIONS_WITH_ANSWERS, include_str!("../templates/v1/questions_with_answers.tera")),
+    (SYSTEM_PROMPT, include_str!("../templates/v1/system_prompt.tera")),
+    (SUMMARY, include_str!("../templates/v1/summary.tera")),
+    (SESSION_NOTES, include_str!("../templates/v1/session_notes.tera")),
+    (NOTIFICATION, include_str!("../templates/v1/notification.tera")),
+    (WEBSITE_IMPORT, include_str!("../templates/v1/website_import.tera")),
+    (WEBSITE_SERVICES, include_str!("../templates/v1/website_services.tera")),
+];
+
+// Prompt templates live in <PROMPT_TEMPLATES_DIR>/<PROMPT_TEMPLATE_VERSION>/<name>.tera. Without
+// PROMPT_TEMPLATES_DIR a templates directory next to the executable is used, and failing that the built-in v1
+// templates. An industry can override any of them in industries/<industry>/ under the version directory, and a
+// business in templates/ under its data directory, the most specific file wins.
+pub struct PromptTemplates {
+    // None for the built-in templates
+    version_dir: Option<PathBuf>,
+    tera: Tera,
+}
+
+impl PromptTemplates {
+    pub fn from_env() -> Result<Self, AppError> {
+        let root = match std::env::var("PROMPT_TEMPLATES_DIR") {
+            Ok(root) => Some(PathBuf::from(root)),
+            Err(_) => std::env::current_exe()
+                .ok()
+                .and_then(|executable| Some(executable.parent()?.join("templates")))
+                .filter(|root| root.is_dir()),
+        };
+        let version = std::env::var("PROMPT_TEMPLATE_VERSION").unwrap_or_else(|_| BUILT_IN_VERSION.to_string());
+
+        match root {
+            Some(root) => PromptTemplates::load(&root.join(version)),
+            None if version == BUILT_IN_VERSION => PromptTemplates::built_in(),
+            None => Err(AppError::Config(format!("PROMPT_TEMPLATES_DIR must be set to use prompt template version {}", version))),
+        }
+    }
+
+    pub fn load(version_dir: &Path) -> Result<Self, AppError> {
+        let mut templates = PromptTemplates {
+            version_dir: Some(version_dir.to_path_buf()),
+            tera: Tera::default(),
+        };
+
+        for (name, _) in TEMPLATES {
+            templates.add(name, &version_dir.join(format!("{}.tera", name)))?;
+        }
+
+        templates.validate()?;
+        Ok(templates)
+    }
+
+    fn built_in() -> Result<Self, AppError> {
+        let mut templates = PromptTemplates { version_dir: None, tera: Tera::default() };
+
+        for (name, source) in BUILT_IN {
+            templates.tera
+                .add_raw_template(name, source)
+                .map_err(|e| AppError::Config(format!("built-in prompt template {}: {}", name, describe(&e))))?;
+        }
+
+        templates.validate()?;
+        Ok(templates)
+    }
+
+    // Swaps in whatever the industry and business override, then validates again
+    pub fn apply_overrides(&mut self, industry_slug: &str, business_dir: &Path) -> Result<(), AppError> {
+        let industry_dir = self.version_dir.as_ref().map(|version_dir| version_dir.join("industries").join(industry_slug));
+        let override_dirs = industry_dir.into_iter().chain([business_dir.join("templates")]);
+
+        for dir in override_dirs {
+            for (name, _) in TEMPLATES {
+                let path = dir.join(format!("{}.tera", name));
+                if path.exists() {
+                    self.add(name, &path)?;
+                }
+            }
+        }
+
+        self.validate()
+    }
+
+    fn add(&mut self, name: &str, path: &Path) -> Result<(), AppError> {
+        let source = std::fs::read_to_string(path)
+            .map_err(|e| AppError::Config(format!("prompt template {}: {}", path.display(), e)))?;
+
+        self.tera
+            .add_raw_template(name, &source)
+            .map_err(|e| AppError::Config(format!("prompt template {}: {}", path.display(), describe(&e))))
+    }
+
+    // Renders every template with placeholder values, catching typos and unknown variables at startup
+    // instead of in the middle of a conversation
+    fn validate(&self) -> Result<(), AppError> {
+        for (name, variables) in TEMPLATES {
+            let mut context = Context::new();
+            for variable in *variables {
+                match sample_list(variable) {
+                    Some(sample) => context.insert(*variable, &sample),
+                    None => context.insert(*variable, ""),
+                }
+            }
+
+            self.tera.render(name, &context).map_err(|e| {
+                AppError::Config(format!("prompt template {} uses a variable it isn't given ({})", name, describe(&e)))
+            })?;
+        }
+
+        Ok(())
+    }
+
+    pub fn render(&self, name: &str, context: &Context) -> Result<String, AppError> {
+        self.tera
+            .render(name, context)
+            .map(|rendered| rendered.trim().to_string())
+            .map_err(|e| AppError::Config(format!("prompt template {}: {}", name, describe(&e))))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_built_in_templates_validate() {
+        assert!(PromptTemplates::built_in().is_ok());
+    }
+
+    #[test]
+    fn validation_renders_the_body_of_a_loop_over_a_list() {
+        let mut templates = PromptTemplates::built_in().unwrap();
+
+        templates.tera
+            .add_raw_template(SYSTEM_PROMPT, "{% for directive in directives %}{{ directive.title }}: {{ directive.text }}{% endfor %}")
+            .unwrap();
+        assert!(templates.validate().is_ok());
+
+        templates.tera
+            .add_raw_template(SYSTEM_PROMPT, "{% for directive in directives %}{{ directive.titel }}{% endfor %}")
+            .unwrap();
+        assert!(templates.validate().is_err());
+    }
+}
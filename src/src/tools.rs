@@ -0,0 +1,575 @@
+use async_openai::types::{ChatCompletionFunctions, ChatCompletionRequestMessage, FunctionCall};
+use chrono::{Duration, TimeZone, Utc};
+use chrono_tz::Tz;
+use serde_derive::{Serialize, Deserialize};
+use serde_json::{json, Value};
+use std::future::Future;
+use std::io::Write;
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::sync::Mutex;
+
+use crate::booking::{BookingRequest, BookingService};
+use crate::catalog::{format_price, Catalog};
+use crate::clock::BusinessClock;
+use crate::dates::{Confidence, DateParser, DateRange, TimeOfDay};
+use crate::draft::{BookingDraft, DraftStage};
+use crate::error::AppError;
+use crate::hours::OpeningHours;
+use crate::profile::CustomerIdentity;
+
+// Most slots handed back to the model at once, it only needs a few to offer the customer
+const MAX_SLOTS: usize = 10;
+
+// Everything a tool can see or change for the business and customer in the current chat
+pub struct ToolContext {
+    pub clock: BusinessClock,
+    pub dates: DateParser,
+    pub catalog: Catalog,
+    pub opening_hours: OpeningHours,
+    pub bookings: Option<BookingService>,
+    // The customer's identity key, anonymous customers can't book
+    pub customer: Option<String>,
+    // The booking being put together in this chat, tools run one at a time so the lock is never contended
+    pub draft: Mutex<BookingDraft>,
+    pub escalations_path: PathBuf,
+}
+
+type ToolFuture<'a> = Pin<Box<dyn Future<Output = Result<Value, AppError>> + Send + 'a>>;
+type Handler = for<'a> fn(&'a ToolContext, Value) -> ToolFuture<'a>;
+
+pub struct Tool {
+    pub name: &'static str,
+    pub description: &'static str,
+    // JSON schema for the arguments, see validate for what's supported
+    pub parameters: Value,
+    handler: Handler,
+}
+
+// Checks a value against the parts of JSON schema our tools use: type, enum, properties, required,
+// additionalProperties, items, minimum and maximum
+pub fn validate(schema: &Value, value: &Value, path: &str) -> Result<(), String> {
+    let at = if path.is_empty() { "arguments".to_string() } else { path.to_string() };
+
+    if let Some(expected) = schema.get("type").and_then(Value::as_str) {
+        let matches = match expected {
+            "object" => value.is_object(),
+            "array" => value.is_array(),
+            "string" => value.is_string(),
+            "integer" => value.is_i64() || value.is_u64(),
+            "number" => value.is_number(),
+            "boolean" => value.is_boolean(),
+            "null" => value.is_null(),
+            _ => true,
+        };
+        if !matches {
+            return Err(format!("{} should be of type {}", at, expected));
+        }
+    }
+
+    if let Some(allowed) = schema.get("enum").and_then(Value::as_array) {
+        if !allowed.contains(value) {
+            return Err(format!("{} should be one of {}", at, Value::Array(allowed.clone())));
+        }
+    }
+
+    if let Some(number) = value.as_f64() {
+        if schema.get("minimum").and_then(Value::as_f64).is_some_and(|minimum| number < minimum) {
+            return Err(format!("{} is below the minimum of {}", at, schema["minimum"]));
+        }
+        if schema.get("maximum").and_then(Value::as_f64).is_some_and(|maximum| number > maximum) {
+            return Err(format!("{} is above the maximum of {}", at, schema["maximum"]));
+        }
+    }
+
+    if let Some(object) = value.as_object() {
+        let properties = schema.get("properties").and_then(Value::as_object);
+
+        for required in schema.get("required").and_then(Value::as_array).into_iter().flatten().filter_map(Value::as_str) {
+            if !object.contains_key(required) {
+                return Err(format!("{} is missing '{}'", at, required));
+            }
+        }
+
+        for (key, property) in object {
+            let child = if path.is_empty() { key.clone() } else { format!("{}.{}", path, key) };
+            match properties.and_then(|properties| properties.get(key)) {
+                Some(property_schema) => validate(property_schema, property, &child)?,
+                None if schema.get("additionalProperties") == Some(&Value::Bool(false)) => {
+                    return Err(format!("{} has an unexpected '{}'", at, key));
+                }
+                None => {}
+            }
+        }
+    }
+
+    if let (Some(items), Some(array)) = (schema.get("items"), value.as_array()) {
+        for (index, item) in array.iter().enumerate() {
+            validate(items, item, &format!("{}[{}]", at, index))?;
+        }
+    }
+
+    Ok(())
+}
+
+fn string_arg<'a>(args: &'a Value, name: &str) -> Option<&'a str> {
+    args.get(name).and_then(Value::as_str).filter(|value| !value.trim().is_empty())
+}
+
+// The model passes on what the customer said, "next Tuesday afternoon", and we do the date arithmetic
+fn when(context: &ToolContext, args: &Value, name: &str) -> Result<Option<DateRange>, AppError> {
+    string_arg(args, name).map(|text| context.dates.parse(text, context.clock.now())).transpose()
+}
+
+fn understood(range: &DateRange, timezone: Tz) -> Value {
+    json!({
+        "meaning": range.describe(),
+        "from": range.start(timezone).format("%Y-%m-%dT%H:%M").to_string(),
+        "until": range.end(timezone).format("%Y-%m-%dT%H:%M").to_string(),
+        "confidence": range.confidence,
+    })
+}
+
+fn bookings(context: &ToolContext) -> Result<&BookingService, AppError> {
+    context.bookings.as_ref().ok_or_else(|| AppError::Booking("this business doesn't take bookings through chat".to_string()))
+}
+
+fn check_availability(context: &ToolContext, args: Value) -> ToolFuture<'_> {
+    Box::pin(async move {
+        let bookings = bookings(context)?;
+        let range = when(context, &args, "when")?;
+        let from = range.map_or_else(|| context.clock.now().date_naive(), |range| range.from);
+        let request = BookingRequest {
+            service: string_arg(&args, "service").unwrap_or_default(),
+            variant: string_arg(&args, "variant"),
+            staff_id: None,
+        };
+        let days = args.get("days").and_then(Value::as_u64).map(|days| days as u32).or(range.map(|range| range.days()));
+
+        let slots = bookings.available_slots(&request, from, days).await?;
+        // Nothing at the time they asked for, so offer the rest of those days instead
+        let matching: Vec<_> = slots.iter().filter(|slot| range.is_none_or(|range| range.contains(slot.starts_at.naive_local()))).collect();
+        let matches_requested_time = !matching.is_empty() || slots.is_empty();
+        let slots = if matches_requested_time { matching } else { slots.iter().collect() };
+
+        Ok(json!({
+            "requested": range.map(|range| understood(&range, context.clock.timezone)),
+            "matches_requested_time": matches_requested_time,
+            "total_available": slots.len(),
+            "slots": slots.iter().take(MAX_SLOTS).map(|slot| json!({
+                "start": slot.starts_at.format("%Y-%m-%dT%H:%M").to_string(),
+                "day": slot.starts_at.format("%A %-d %B").to_string(),
+                "end": slot.ends_at.format("%H:%M").to_string(),
+                "staff": slot.staff_name,
+            })).collect::<Vec<Value>>(),
+        }))
+    })
+}
+
+async fn staff_id(bookings: &BookingService, name: Option<&str>) -> Result<Option<uuid::Uuid>, AppError> {
+    let Some(name) = name else {
+        return Ok(None);
+    };
+
+    bookings.staff().await?
+        .into_iter()
+        .find(|staff| staff.name.eq_ignore_ascii_case(name))
+        .map(|staff| Some(staff.staff_id))
+        .ok_or_else(|| AppError::Booking(format!("nobody called {} works here", name)))
+}
+
+// Fills in whatever the customer just told us, checks it against the catalog and the diary, and says what to
+// ask next
+fn update_booking(context: &ToolContext, args: Value) -> ToolFuture<'_> {
+    Box::pin(async move {
+        let bookings = bookings(context)?;
+        let mut draft = context.draft.lock().unwrap().clone();
+        let mut notes = Vec::new();
+
+        if let Some(service) = string_arg(&args, "service") {
+            let item = context.catalog.find(service).ok_or_else(|| AppError::Booking(format!(
+                "{} isn't a service here, the services are {}",
+                service, context.catalog.items.iter().map(|item| item.name.as_str()).collect::<Vec<&str>>().join(", ")
+            )))?;
+            if draft.service.as_deref() != Some(item.name.as_str()) {
+                draft.variant = None;
+            }
+            draft.service = Some(item.name.clone());
+        }
+        if let Some(variant) = string_arg(&args, "variant") {
+            let item = draft.service.as_deref().and_then(|service| context.catalog.find(service))
+                .ok_or_else(|| AppError::Booking("record the service before the variant".to_string()))?;
+            item.duration_minutes(Some(variant))?;
+            draft.variant = Some(variant.to_string());
+        }
+        if let Some(staff) = string_arg(&args, "staff") {
+            staff_id(bookings, Some(staff)).await?;
+            draft.staff = Some(staff.to_string());
+        }
+        if let Some(range) = when(context, &args, "when")? {
+            if range.confidence == Confidence::Low {
+                notes.push(format!("not sure what they meant by that, it could be {}, check with them", range.describe()));
+            } else if range.from != range.to {
+                notes.push(format!("that covers {}, ask which day", range.describe()));
+            } else {
+                // "11am" on its own is a time for the day they've already picked
+                if range.dated || draft.date.is_none() {
+                    draft.date = Some(range.from);
+                }
+                if let TimeOfDay::At(time) = range.time {
+                    draft.time = Some(time);
+                }
+            }
+        }
+        if let Some(name) = string_arg(&args, "name") {
+            draft.name = Some(name.trim().to_string());
+        }
+        if let Some(contact) = string_arg(&args, "contact") {
+            match CustomerIdentity::parse(contact) {
+                Some(identity @ (CustomerIdentity::Email(_) | CustomerIdentity::Phone(_))) => draft.contact = Some(identity),
+                _ => notes.push(format!("'{}' isn't an email address or phone number", contact)),
+            }
+        }
+
+        // Catch a day or time that isn't free now, not after the customer has said yes to it
+        let mut free_times = Vec::new();
+        if let (Some(service), Some(date)) = (draft.service.clone(), draft.date) {
+            let request = BookingRequest {
+                service: &service,
+                variant: draft.variant.as_deref(),
+                staff_id: staff_id(bookings, draft.staff.as_deref()).await?,
+            };
+            let slots = bookings.available_slots(&request, date, Some(1)).await?;
+
+            if slots.is_empty() {
+                notes.push(format!("nothing is free on {}, ask about another day", date.format("%A %-d %B")));
+                draft.date = None;
+                draft.time = None;
+            } else if let Some(time) = draft.time.filter(|time| !slots.iter().any(|slot| slot.starts_at.time() == *time)) {
+                notes.push(format!("{} isn't free that day", time.format("%H:%M")));
+                draft.time = None;
+            }
+
+            if draft.date.is_some() && draft.time.is_none() {
+                free_times = slots.iter().map(|slot| slot.starts_at.format("%H:%M").to_string()).collect();
+                free_times.dedup();
+                free_times.truncate(MAX_SLOTS);
+            }
+        }
+
+        draft.advance();
+        *context.draft.lock().unwrap() = draft.clone();
+
+        Ok(json!({
+            "stage": draft.stage,
+            "so_far": draft.summary(),
+            "missing": draft.missing(),
+            "notes": notes,
+            "free_times": free_times,
+            "next": draft.next_step(),
+        }))
+    })
+}
+
+fn book_appointment(context: &ToolContext, args: Value) -> ToolFuture<'_> {
+    Box::pin(async move {
+        let bookings = bookings(context)?;
+        let draft = context.draft.lock().unwrap().clone();
+
+        if draft.stage != DraftStage::Confirming {
+            return Err(AppError::Booking(format!("the booking isn't ready yet. {}", draft.next_step())));
+        }
+        if args.get("confirmed") != Some(&Value::Bool(true)) {
+            return Err(AppError::Booking(format!("only book once the customer has said yes to: {}", draft.summary())));
+        }
+        let (Some(service), Some(date), Some(time), Some(contact)) = (&draft.service, draft.date, draft.time, &draft.contact) else {
+            return Err(AppError::Booking(draft.next_step()));
+        };
+
+        let starts_at = context.clock.timezone
+            .from_local_datetime(&date.and_time(time))
+            .earliest()
+            .ok_or_else(|| AppError::Booking(format!("{} doesn't exist on {}, the clocks change", time.format("%H:%M"), date)))?;
+        let request = BookingRequest {
+            service,
+            variant: draft.variant.as_deref(),
+            staff_id: staff_id(bookings, draft.staff.as_deref()).await?,
+        };
+
+        let booking = bookings.create(&contact.key(), draft.name.as_deref(), &request, starts_at.with_timezone(&Utc)).await?;
+        let staff = bookings.staff().await?.into_iter().find(|staff| staff.staff_id == booking.staff_id);
+        context.draft.lock().unwrap().reset();
+
+        Ok(json!({
+            "booked": true,
+            "booking_id": booking.booking_id,
+            "service": booking.service,
+            "variant": booking.variant,
+            "name": booking.customer_name,
+            "start": booking.starts_at.with_timezone(&context.clock.timezone).format("%A %-d %B %Y at %H:%M").to_string(),
+            "end": booking.ends_at.with_timezone(&context.clock.timezone).format("%H:%M").to_string(),
+            "staff": staff.map(|staff| staff.name),
+        }))
+    })
+}
+
+fn look_up_price(context: &ToolContext, args: Value) -> ToolFuture<'_> {
+    Box::pin(async move {
+        let service = string_arg(&args, "service").unwrap_or_default();
+        let Some(item) = context.catalog.find(service) else {
+            return Ok(json!({
+                "found": false,
+                "services": context.catalog.items.iter().map(|item| item.name.as_str()).collect::<Vec<&str>>(),
+            }));
+        };
+
+        let price = |price: Option<f64>| price.map(|price| format_price(price, &item.currency)).unwrap_or_else(|| "on request".to_string());
+        Ok(json!({
+            "found": true,
+            "service": item.name,
+            "description": item.description,
+            "price": price(item.price),
+            "duration_minutes": item.duration_minutes,
+            "variants": item.variants.iter().map(|variant| json!({
+                "name": variant.name,
+                "price": price(variant.price.or(item.price)),
+                "duration_minutes": variant.duration_minutes.or(item.duration_minutes),
+            })).collect::<Vec<Value>>(),
+        }))
+    })
+}
+
+fn look_up_opening_hours(context: &ToolContext, args: Value) -> ToolFuture<'_> {
+    Box::pin(async move {
+        let now = context.clock.now();
+        let date = when(context, &args, "date")?.map_or_else(|| now.date_naive(), |range| range.from);
+
+        let locations: Vec<Value> = context.opening_hours.locations
+            .iter()
+            .filter(|location| string_arg(&args, "location").is_none_or(|name| location.name.eq_ignore_ascii_case(name)))
+            .map(|location| {
+                let hours: Vec<String> = location.hours_on(date)
+                    .iter()
+                    .map(|range| format!("{}-{}", range.open.format("%H:%M"), range.close.format("%H:%M")))
+                    .collect();
+
+                json!({
+                    "location": location.name,
+                    "date": date.format("%A %-d %B %Y").to_string(),
+                    "open": !hours.is_empty(),
+                    "hours": hours,
+                    "next_opening": location.next_opening(date.and_hms_opt(0, 0, 0).unwrap_or_default().max(now.naive_local()))
+                        .map(|opening| opening.format("%A %-d %B at %H:%M").to_string()),
+                })
+            })
+            .collect();
+
+        Ok(json!({ "locations": locations, "now": context.opening_hours.status_at(now) }))
+    })
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Escalation {
+    id: String,
+    at: chrono::DateTime<Utc>,
+    customer: Option<String>,
+    reason: String,
+    urgency: String,
+}
+
+// Appends to escalations.jsonl in the business's data directory, which the team works through
+fn record_escalation(context: &ToolContext, reason: &str, urgency: &str) -> Result<Escalation, AppError> {
+    let escalation = Escalation {
+        id: uuid::Uuid::new_v4().to_string(),
+        at: Utc::now(),
+        customer: context.customer.clone(),
+        reason: reason.to_string(),
+        urgency: urgency.to_string(),
+    };
+
+    if let Some(parent) = context.escalations_path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let mut log = std::fs::OpenOptions::new().create(true).append(true).open(&context.escalations_path)?;
+    writeln!(log, "{}", serde_json::to_string(&escalation)?)?;
+
+    Ok(escalation)
+}
+
+fn escalate_to_human(context: &ToolContext, args: Value) -> ToolFuture<'_> {
+    Box::pin(async move {
+        let escalation = record_escalation(
+            context,
+            string_arg(&args, "reason").unwrap_or_default(),
+            string_arg(&args, "urgency").unwrap_or("normal"),
+        )?;
+
+        let reply_within = match escalation.urgency.as_str() {
+            "high" => Duration::hours(1),
+            _ => Duration::hours(24),
+        };
+        Ok(json!({
+            "escalated": true,
+            "reference": escalation.id,
+            "team_will_reply_within_hours": reply_within.num_hours(),
+        }))
+    })
+}
+
+// The tools offered to the model, only the booking ones when the business takes bookings
+pub struct ToolRegistry {
+    tools: Vec<Tool>,
+    context: ToolContext,
+}
+
+impl ToolRegistry {
+    pub fn new(context: ToolContext) -> Self {
+        let mut registry = ToolRegistry { tools: Vec::new(), context };
+
+        if registry.context.bookings.is_some() {
+            registry.register(Tool {
+                name: "check_availability",
+                description: "Find free appointment times for a service. Pass dates and times as the customer said them, they're worked out in the business's timezone.",
+                parameters: json!({
+                    "type": "object",
+                    "properties": {
+                        "service": { "type": "string", "description": "Service name exactly as in the catalog" },
+                        "variant": { "type": "string", "description": "Variant of the service, if the customer chose one" },
+                        "when": { "type": "string", "description": "When the customer wants to come, in their own words (\"next Tuesday afternoon\", \"the 3rd at half two\") or YYYY-MM-DD, defaults to today onwards" },
+                        "days": { "type": "integer", "minimum": 1, "maximum": 31, "description": "How many days to look across" }
+                    },
+                    "required": ["service"],
+                    "additionalProperties": false
+                }),
+                handler: check_availability,
+            });
+            registry.register(Tool {
+                name: "update_booking",
+                description: "Record booking details as the customer gives them, even one at a time. Says what's still missing, which times are free and what to ask next.",
+                parameters: json!({
+                    "type": "object",
+                    "properties": {
+                        "service": { "type": "string", "description": "Service name as in the catalog" },
+                        "variant": { "type": "string" },
+                        "staff": { "type": "string", "description": "Staff member's name, if the customer asked for someone" },
+                        "when": { "type": "string", "description": "The day and/or time in the customer's words (\"Friday at 3pm\") or YYYY-MM-DDTHH:MM" },
+                        "name": { "type": "string", "description": "Name the booking should be under" },
+                        "contact": { "type": "string", "description": "Customer's email address or phone number" }
+                    },
+                    "additionalProperties": false
+                }),
+                handler: update_booking,
+            });
+            registry.register(Tool {
+                name: "book_appointment",
+                description: "Make the booking recorded with update_booking. Only call this after reading the summary back to the customer and them saying yes.",
+                parameters: json!({
+                    "type": "object",
+                    "properties": {
+                        "confirmed": { "type": "boolean", "description": "true once the customer has said yes to the summary" }
+                    },
+                    "required": ["confirmed"],
+                    "additionalProperties": false
+                }),
+                handler: book_appointment,
+            });
+        }
+
+        registry.register(Tool {
+            name: "look_up_price",
+            description: "Get the price, duration and variants of a service from the business's catalog.",
+            parameters: json!({
+                "type": "object",
+                "properties": { "service": { "type": "string" } },
+                "required": ["service"],
+                "additionalProperties": false
+            }),
+            handler: look_up_price,
+        });
+        registry.register(Tool {
+            name: "look_up_opening_hours",
+            description: "Check whether the business is open on a date, its hours that day and when it next opens.",
+            parameters: json!({
+                "type": "object",
+                "properties": {
+                    "date": { "type": "string", "description": "YYYY-MM-DD or the customer's words (\"next Saturday\"), defaults to today" },
+                    "location": { "type": "string" }
+                },
+                "additionalProperties": false
+            }),
+            handler: look_up_opening_hours,
+        });
+        registry.register(Tool {
+            name: "escalate_to_human",
+            description: "Pass the conversation to a member of staff, when the customer asks for a person or you can't help.",
+            parameters: json!({
+                "type": "object",
+                "properties": {
+                    "reason": { "type": "string", "description": "What the customer needs, for the member of staff" },
+                    "urgency": { "type": "string", "enum": ["low", "normal", "high"] }
+                },
+                "required": ["reason"],
+                "additionalProperties": false
+            }),
+            handler: escalate_to_human,
+        });
+
+        registry
+    }
+
+    pub fn register(&mut self, tool: Tool) {
+        self.tools.retain(|existing| existing.name != tool.name);
+        self.tools.push(tool);
+    }
+
+    // Reminds the model of a booking it's part way through, or drops the reminder once there isn't one
+    pub fn pin_draft(&self, conversation: &mut Vec<ChatCompletionRequestMessage>) -> Result<(), AppError> {
+        self.context.draft.lock().unwrap().apply(conversation)
+    }
+
+    // Passes something the assistant couldn't handle on to the team, the same way the model's escalate_to_human does
+    pub fn escalate(&self, reason: &str) -> Result<(), AppError> {
+        record_escalation(&self.context, reason, "normal").map(|_| ())
+    }
+
+    // The customer's own email address or phone number, which the assistant can read back to them
+    pub fn customer_contacts(&self) -> Vec<String> {
+        match &self.context.draft.lock().unwrap().contact {
+            Some(CustomerIdentity::Email(contact) | CustomerIdentity::Phone(contact)) => vec![contact.clone()],
+            _ => Vec::new(),
+        }
+    }
+
+    pub fn definitions(&self) -> Vec<ChatCompletionFunctions> {
+        self.tools
+            .iter()
+            .map(|tool| ChatCompletionFunctions {
+                name: tool.name.to_string(),
+                description: Some(tool.description.to_string()),
+                parameters: Some(tool.parameters.clone()),
+            })
+            .collect()
+    }
+
+    // Always gives the model something to read, problems it can fix (bad arguments, a taken slot) come back as
+    // an error for it to act on. Only configuration and storage errors fail the turn.
+    pub async fn call(&self, function_call: &FunctionCall) -> Result<String, AppError> {
+        let Some(tool) = self.tools.iter().find(|tool| tool.name == function_call.name) else {
+            return Ok(json!({ "error": format!("there is no tool called {}", function_call.name) }).to_string());
+        };
+
+        let args: Value = match serde_json::from_str(if function_call.arguments.trim().is_empty() { "{}" } else { &function_call.arguments }) {
+            Ok(args) => args,
+            Err(e) => return Ok(json!({ "error": format!("the arguments aren't valid JSON: {}", e) }).to_string()),
+        };
+        if let Err(e) = validate(&tool.parameters, &args, "") {
+            return Ok(json!({ "error": format!("invalid arguments: {}", e) }).to_string());
+        }
+
+        match (tool.handler)(&self.context, args).await {
+            Ok(result) => Ok(result.to_string()),
+            Err(e @ (AppError::Config(_) | AppError::Storage(_))) => Err(e),
+            Err(e) => Ok(json!({ "error": e.to_string() }).to_string()),
+        }
+    }
+}
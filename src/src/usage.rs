@@ -0,0 +1,374 @@
+use chrono::{DateTime, Datelike, Utc};
+use serde_derive::{Serialize, Deserialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use crate::error::AppError;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CallPurpose {
+    VaguenessCheck,
+    QuestionGeneration,
+    ChatTurn,
+    // A chat reply asked for again after the first one failed a check
+    Correction,
+    Summary,
+    SessionNotes,
+    // Appointment reminders and follow-ups
+    Notification,
+    // Knowledge base chunks and the customer messages searched against them
+    Embedding,
+    // Drafting the business's setup answers from its website
+    WebsiteImport,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UsageRecord {
+    pub at: DateTime<Utc>,
+    pub business: String,
+    pub session: String,
+    pub purpose: CallPurpose,
+    pub provider: String,
+    pub model: String,
+    pub prompt_tokens: u32,
+    pub completion_tokens: u32,
+    // None when billing.json has no price for the model, so it can't count toward the cap
+    pub cost: Option<f64>,
+}
+
+// USD per 1,000 tokens
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct ModelPrice {
+    pub prompt_per_1k: f64,
+    pub completion_per_1k: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CapAction {
+    // Refuse further LLM calls until next month
+    Block,
+    // Keep going on the cheaper downgrade model
+    Downgrade,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BillingConfig {
+    pub prices: HashMap<String, ModelPrice>,
+    pub monthly_cap: Option<f64>,
+    pub cap_action: CapAction,
+    pub downgrade_model: Option<String>,
+}
+
+impl Default for BillingConfig {
+    fn default() -> Self {
+        let prices = HashMap::from([
+            ("gpt-3.5-turbo".to_string(), ModelPrice { prompt_per_1k: 0.0015, completion_per_1k: 0.002 }),
+            ("gpt-3.5-turbo-16k".to_string(), ModelPrice { prompt_per_1k: 0.003, completion_per_1k: 0.004 }),
+            ("gpt-4".to_string(), ModelPrice { prompt_per_1k: 0.03, completion_per_1k: 0.06 }),
+            ("gpt-4-32k".to_string(), ModelPrice { prompt_per_1k: 0.06, completion_per_1k: 0.12 }),
+            ("text-embedding-ada-002".to_string(), ModelPrice { prompt_per_1k: 0.0001, completion_per_1k: 0.0 }),
+        ]);
+
+        BillingConfig {
+            prices,
+            monthly_cap: None,
+            cap_action: CapAction::Block,
+            downgrade_model: None,
+        }
+    }
+}
+
+impl BillingConfig {
+    pub fn cost(&self, model: &str, prompt_tokens: u32, completion_tokens: u32) -> Option<f64> {
+        self.prices.get(model).map(|price| {
+            prompt_tokens as f64 / 1000.0 * price.prompt_per_1k
+                + completion_tokens as f64 / 1000.0 * price.completion_per_1k
+        })
+    }
+}
+
+// What the spend cap allows for the next call
+pub enum Allowance {
+    Allowed,
+    Downgrade(String),
+}
+
+// Usage for one business, appended to usage.jsonl in its data directory with prices and caps from billing.json
+pub struct UsageTracker {
+    business: String,
+    log_path: PathBuf,
+    config: BillingConfig,
+    records: Vec<UsageRecord>,
+    // Models we've already warned have no price, so the warning comes once a run rather than every call
+    unpriced_warned: HashSet<String>,
+}
+
+impl UsageTracker {
+    pub fn open(business: &str, data_dir: &Path) -> Result<Self, AppError> {
+        let config_path = data_dir.join("billing.json");
+        let config = if config_path.exists() {
+            serde_json::from_str(&std::fs::read_to_string(&config_path)?)
+                .map_err(|e| AppError::Config(format!("{}: {}", config_path.display(), e)))?
+        } else {
+            BillingConfig::default()
+        };
+
+        let log_path = data_dir.join("usage.jsonl");
+        let mut records = Vec::new();
+        if log_path.exists() {
+            for line in std::fs::read_to_string(&log_path)?.lines().filter(|line| !line.trim().is_empty()) {
+                records.push(serde_json::from_str(line)?);
+            }
+        }
+
+        Ok(UsageTracker {
+            business: business.to_string(),
+            log_path,
+            config,
+            records,
+            unpriced_warned: HashSet::new(),
+        })
+    }
+
+    pub fn record(&mut self, session: &str, purpose: CallPurpose, provider: &str, model: &str, prompt_tokens: u32, completion_tokens: u32) -> Result<(), AppError> {
+        let cost = self.config.cost(model, prompt_tokens, completion_tokens);
+        if cost.is_none() && self.unpriced_warned.insert(model.to_string()) {
+            eprintln!(
+                "No price for {} in billing.json, its usage is logged but can't count toward {}'s monthly cap",
+                model, self.business
+            );
+        }
+
+        let record = UsageRecord {
+            at: Utc::now(),
+            business: self.business.clone(),
+            session: session.to_string(),
+            purpose,
+            provider: provider.to_string(),
+            model: model.to_string(),
+            prompt_tokens,
+            completion_tokens,
+            cost,
+        };
+
+        if let Some(parent) = self.log_path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let mut log = std::fs::OpenOptions::new().create(true).append(true).open(&self.log_path)?;
+        writeln!(log, "{}", serde_json::to_string(&record)?)?;
+
+        self.records.push(record);
+        Ok(())
+    }
+
+    fn month_records(&self, year: i32, month: u32) -> impl Iterator<Item = &UsageRecord> {
+        self.records.iter().filter(move |record| record.at.year() == year && record.at.month() == month)
+    }
+
+    pub fn month_cost(&self, year: i32, month: u32) -> f64 {
+        self.month_records(year, month).filter_map(|record| record.cost).sum()
+    }
+
+    // Checked before every call, errors once the cap is hit and the business chose to block
+    pub fn allowance(&self) -> Result<Allowance, AppError> {
+        let Some(cap) = self.config.monthly_cap else {
+            return Ok(Allowance::Allowed);
+        };
+
+        let now = Utc::now();
+        let spent = self.month_cost(now.year(), now.month());
+        if spent < cap {
+            return Ok(Allowance::Allowed);
+        }
+
+        match (self.config.cap_action, &self.config.downgrade_model) {
+            (CapAction::Downgrade, Some(model)) => Ok(Allowance::Downgrade(model.clone())),
+            _ => Err(AppError::SpendCap(format!(
+                "{} has spent ${:.2} of its ${:.2} monthly cap", self.business, spent, cap
+            ))),
+        }
+    }
+
+    pub fn monthly_report(&self, year: i32, month: u32) -> String {
+        let mut by_purpose: BTreeMap<CallPurpose, (u64, u64, f64)> = BTreeMap::new();
+        let mut by_model: BTreeMap<&str, (u64, u64, f64)> = BTreeMap::new();
+        let mut sessions = HashSet::new();
+        let mut unpriced: BTreeMap<&str, u64> = BTreeMap::new();
+
+        for record in self.month_records(year, month) {
+            sessions.insert(record.session.as_str());
+            if record.cost.is_none() {
+                *unpriced.entry(record.model.as_str()).or_default() += (record.prompt_tokens + record.completion_tokens) as u64;
+            }
+
+            for totals in [
+                by_purpose.entry(record.purpose).or_default(),
+                by_model.entry(record.model.as_str()).or_default(),
+            ] {
+                totals.0 += record.prompt_tokens as u64;
+                totals.1 += record.completion_tokens as u64;
+                totals.2 += record.cost.unwrap_or_default();
+            }
+        }
+
+        let mut report = format!(
+            "Usage for {} in {}-{:02}: ${:.4} across {} sessions",
+            self.business, year, month, self.month_cost(year, month), sessions.len()
+        );
+        if let Some(cap) = self.config.monthly_cap {
+            report.push_str(&format!(" (cap ${:.2})", cap));
+        }
+
+        report.push_str("\nBy purpose:");
+        for (purpose, (prompt, completion, cost)) in &by_purpose {
+            report.push_str(&format!("\n  {:?}: {} prompt + {} completion tokens, ${:.4}", purpose, prompt, completion, cost));
+        }
+
+        report.push_str("\nBy model:");
+        for (model, (prompt, completion, cost)) in &by_model {
+            // Called out rather than shown as free
+            match unpriced.get(model) {
+                Some(tokens) => report.push_str(&format!(
+                    "\n  {}: {} prompt + {} completion tokens, ${:.4} with no price set for {} tokens",
+                    model, prompt, completion, cost, tokens
+                )),
+                None => report.push_str(&format!("\n  {}: {} prompt + {} completion tokens, ${:.4}", model, prompt, completion, cost)),
+            }
+        }
+        if !unpriced.is_empty() {
+            report.push_str("\nTokens with no price set aren't in the costs or the monthly cap, add their models to billing.json.");
+        }
+
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    // A fresh tracker logging to its own directory under the system temp dir
+    fn tracker(name: &str, config: BillingConfig) -> UsageTracker {
+        let data_dir = std::env::temp_dir().join(format!("usage-test-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&data_dir);
+        std::fs::create_dir_all(&data_dir).unwrap();
+        std::fs::write(data_dir.join("billing.json"), serde_json::to_string(&config).unwrap()).unwrap();
+
+        UsageTracker::open("Test Salon", &data_dir).unwrap()
+    }
+
+    fn capped(cap: f64, cap_action: CapAction, downgrade_model: Option<&str>) -> BillingConfig {
+        BillingConfig {
+            monthly_cap: Some(cap),
+            cap_action,
+            downgrade_model: downgrade_model.map(str::to_string),
+            ..BillingConfig::default()
+        }
+    }
+
+    fn record_at(at: DateTime<Utc>, model: &str, cost: Option<f64>) -> UsageRecord {
+        UsageRecord {
+            at,
+            business: "Test Salon".to_string(),
+            session: "session".to_string(),
+            purpose: CallPurpose::ChatTurn,
+            provider: "openai".to_string(),
+            model: model.to_string(),
+            prompt_tokens: 1000,
+            completion_tokens: 500,
+            cost,
+        }
+    }
+
+    #[test]
+    fn costs_come_from_the_model_price_and_unpriced_models_have_none() {
+        let config = BillingConfig::default();
+
+        let cost = config.cost("gpt-3.5-turbo", 2000, 1000).unwrap();
+        assert!((cost - 0.005).abs() < 1e-9);
+        assert_eq!(config.cost("gpt-3.5-turbo", 0, 0), Some(0.0));
+        assert_eq!(config.cost("some-new-model", 2000, 1000), None);
+    }
+
+    #[test]
+    fn records_are_logged_and_read_back() {
+        let mut usage = tracker("log", BillingConfig::default());
+        usage.record("session", CallPurpose::ChatTurn, "openai", "gpt-3.5-turbo", 1000, 500).unwrap();
+        usage.record("session", CallPurpose::Summary, "openai", "some-new-model", 1000, 500).unwrap();
+
+        let reopened = UsageTracker::open("Test Salon", usage.log_path.parent().unwrap()).unwrap();
+        assert_eq!(reopened.records.len(), 2);
+        assert_eq!(reopened.records[0].cost, usage.records[0].cost);
+        assert_eq!(reopened.records[1].cost, None);
+    }
+
+    #[test]
+    fn month_cost_counts_only_that_month_and_priced_usage() {
+        let mut usage = tracker("month", BillingConfig::default());
+        usage.records = vec![
+            record_at(Utc.with_ymd_and_hms(2026, 9, 30, 23, 59, 0).unwrap(), "gpt-3.5-turbo", Some(1.0)),
+            record_at(Utc.with_ymd_and_hms(2026, 10, 1, 0, 0, 0).unwrap(), "gpt-3.5-turbo", Some(2.0)),
+            record_at(Utc.with_ymd_and_hms(2026, 10, 18, 12, 0, 0).unwrap(), "gpt-4", Some(0.5)),
+            record_at(Utc.with_ymd_and_hms(2026, 10, 18, 12, 0, 0).unwrap(), "some-new-model", None),
+        ];
+
+        assert!((usage.month_cost(2026, 9) - 1.0).abs() < 1e-9);
+        assert!((usage.month_cost(2026, 10) - 2.5).abs() < 1e-9);
+        assert_eq!(usage.month_cost(2026, 11), 0.0);
+    }
+
+    #[test]
+    fn the_cap_blocks_or_downgrades_once_it_is_spent() {
+        let now = Utc::now();
+
+        let mut uncapped = tracker("uncapped", BillingConfig::default());
+        uncapped.records.push(record_at(now, "gpt-4", Some(1000.0)));
+        assert!(matches!(uncapped.allowance(), Ok(Allowance::Allowed)));
+
+        let mut under = tracker("under", capped(10.0, CapAction::Block, None));
+        under.records.push(record_at(now, "gpt-4", Some(9.99)));
+        assert!(matches!(under.allowance(), Ok(Allowance::Allowed)));
+
+        let mut blocked = tracker("blocked", capped(10.0, CapAction::Block, None));
+        blocked.records.push(record_at(now, "gpt-4", Some(10.0)));
+        assert!(matches!(blocked.allowance(), Err(AppError::SpendCap(_))));
+
+        let mut downgraded = tracker("downgraded", capped(10.0, CapAction::Downgrade, Some("gpt-3.5-turbo")));
+        downgraded.records.push(record_at(now, "gpt-4", Some(12.0)));
+        assert!(matches!(downgraded.allowance(), Ok(Allowance::Downgrade(model)) if model == "gpt-3.5-turbo"));
+
+        // Downgrading with nowhere to downgrade to blocks instead
+        let mut nowhere = tracker("nowhere", capped(10.0, CapAction::Downgrade, None));
+        nowhere.records.push(record_at(now, "gpt-4", Some(12.0)));
+        assert!(matches!(nowhere.allowance(), Err(AppError::SpendCap(_))));
+
+        // Unpriced usage can't be counted toward the cap
+        let mut unpriced = tracker("unpriced", capped(10.0, CapAction::Block, None));
+        unpriced.records.push(record_at(now, "some-new-model", None));
+        assert!(matches!(unpriced.allowance(), Ok(Allowance::Allowed)));
+    }
+
+    #[test]
+    fn the_report_totals_by_purpose_and_model_and_calls_out_unpriced_tokens() {
+        let mut usage = tracker("report", capped(20.0, CapAction::Block, None));
+        let at = Utc.with_ymd_and_hms(2026, 10, 18, 12, 0, 0).unwrap();
+        usage.records = vec![
+            record_at(at, "gpt-3.5-turbo", Some(1.25)),
+            record_at(at, "gpt-3.5-turbo", Some(0.75)),
+            record_at(at, "some-new-model", None),
+            record_at(Utc.with_ymd_and_hms(2026, 9, 1, 12, 0, 0).unwrap(), "gpt-4", Some(5.0)),
+        ];
+
+        let report = usage.monthly_report(2026, 10);
+        assert!(report.starts_with("Usage for Test Salon in 2026-10: $2.0000 across 1 sessions (cap $20.00)"), "{}", report);
+        assert!(report.contains("\n  ChatTurn: 3000 prompt + 1500 completion tokens, $2.0000"), "{}", report);
+        assert!(report.contains("\n  gpt-3.5-turbo: 2000 prompt + 1000 completion tokens, $2.0000"), "{}", report);
+        assert!(report.contains("\n  some-new-model: 1000 prompt + 500 completion tokens, $0.0000 with no price set for 1500 tokens"), "{}", report);
+        assert!(report.contains("add their models to billing.json"), "{}", report);
+        assert!(!report.contains("gpt-4"), "{}", report);
+    }
+}
@@ -0,0 +1,270 @@
+use serde_derive::{Serialize, Deserialize};
+use std::path::{Path, PathBuf};
+
+use crate::catalog::{Catalog, CatalogItem, Variant, DEFAULT_CURRENCY};
+use crate::context::ContextWindow;
+use crate::error::AppError;
+use crate::knowledge::{html_text, html_title};
+
+pub struct Page {
+    // Relative to the snapshot directory
+    pub path: String,
+    pub title: Option<String>,
+    pub text: String,
+}
+
+fn html_paths(directory: &Path) -> Result<Vec<PathBuf>, AppError> {
+    let mut paths = Vec::new();
+    for entry in std::fs::read_dir(directory)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            paths.extend(html_paths(&path)?);
+        } else if path.extension().is_some_and(|extension| matches!(extension.to_string_lossy().to_lowercase().as_str(), "html" | "htm")) {
+            paths.push(path);
+        }
+    }
+    Ok(paths)
+}
+
+// Every saved page with any text on it. The text is pulled out the same way as for the knowledge base, the idea
+// preprocess_review in the sentiment service uses: drop the markup, keep the words.
+pub fn read_snapshot(directory: &Path) -> Result<Vec<Page>, AppError> {
+    if !directory.is_dir() {
+        return Err(AppError::UserInput(format!("{} isn't a directory of saved pages", directory.display())));
+    }
+
+    let mut pages = Vec::new();
+    for path in html_paths(directory)? {
+        let html = String::from_utf8_lossy(&std::fs::read(&path)?).to_string();
+        let text = html_text(&html);
+        if text.is_empty() {
+            continue;
+        }
+
+        let relative = path.strip_prefix(directory).unwrap_or(&path).display().to_string();
+        pages.push(Page { path: relative, title: html_title(&html), text });
+    }
+
+    let is_home = |page: &Page| matches!(page.path.as_str(), "index.html" | "index.htm");
+    pages.sort_by_key(|page| (!is_home(page), page.path.matches(std::path::MAIN_SEPARATOR).count(), page.path.clone()));
+
+    Ok(pages)
+}
+
+// The pages as one document for the prompt, cut off once it reaches max_tokens. The home page and pages nearest the
+// top go in first, read_snapshot has already put them in that order.
+pub fn pages_text(pages: &[Page], context_window: &ContextWindow, max_tokens: usize) -> String {
+    let mut text = String::new();
+
+    for page in pages {
+        let separator = if text.is_empty() { "" } else { "\n\n" };
+        let header = format!("{}=== {} ({}) ===\n", separator, page.title.as_deref().unwrap_or("Untitled"), page.path);
+
+        let whole = format!("{}{}{}", text, header, page.text);
+        if context_window.count_text(&whole) <= max_tokens {
+            text = whole;
+            continue;
+        }
+
+        // Only part of this page fits, so keep as many of its words as will and stop there
+        let words: Vec<&str> = page.text.split_whitespace().collect();
+        let with_words = |count: usize| format!("{}{}{} ...", text, header, words[..count].join(" "));
+        let (mut fits, mut too_many) = (0, words.len());
+        while too_many - fits > 1 {
+            let middle = (fits + too_many) / 2;
+            match context_window.count_text(&with_words(middle)) <= max_tokens {
+                true => fits = middle,
+                false => too_many = middle,
+            }
+        }
+        if fits > 0 {
+            text = with_words(fits);
+        }
+        break;
+    }
+
+    text
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImportedService {
+    pub name: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub price: Option<f64>,
+    #[serde(default)]
+    pub currency: Option<String>,
+    #[serde(default)]
+    pub duration_minutes: Option<u32>,
+    #[serde(default)]
+    pub variants: Vec<Variant>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImportedAnswer {
+    pub question: String,
+    // None when the site doesn't say
+    #[serde(default)]
+    pub answer: Option<String>,
+}
+
+// What the model made of the business's website, kept in website.json in its data directory. Nothing in it is
+// used until the manager has confirmed it during setup.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct WebsiteDraft {
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub industry: Option<String>,
+    #[serde(default)]
+    pub services: Vec<ImportedService>,
+    #[serde(default)]
+    pub answers: Vec<ImportedAnswer>,
+    // The pages it was drafted from
+    #[serde(default)]
+    pub pages: Vec<String>,
+}
+
+impl WebsiteDraft {
+    pub fn catalog(&self) -> Catalog {
+        Catalog {
+            items: self.services
+                .iter()
+                .filter(|service| !service.name.trim().is_empty())
+                .map(|service| CatalogItem {
+                    name: service.name.trim().to_string(),
+                    description: service.description.clone().filter(|description| !description.trim().is_empty()),
+                    price: service.price.filter(|price| *price >= 0.0),
+                    currency: service.currency.as_deref().map(str::to_uppercase).unwrap_or_else(|| DEFAULT_CURRENCY.to_string()),
+                    duration_minutes: service.duration_minutes,
+                    variants: service.variants.clone(),
+                })
+                .collect(),
+        }
+    }
+
+    // The site's answer to one of the generic setup questions, if it had one
+    pub fn answer(&self, question: &str) -> Option<&str> {
+        self.answers
+            .iter()
+            .find(|answer| answer.question.trim().eq_ignore_ascii_case(question.trim()))
+            .and_then(|answer| answer.answer.as_deref())
+            .map(str::trim)
+            .filter(|answer| !answer.is_empty() && !answer.eq_ignore_ascii_case("NA"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::context::TruncationStrategy;
+
+    fn page(path: &str, title: Option<&str>, text: &str) -> Page {
+        Page { path: path.to_string(), title: title.map(str::to_string), text: text.to_string() }
+    }
+
+    fn context_window() -> ContextWindow {
+        ContextWindow::new(512, TruncationStrategy::DropOldestTurns).unwrap()
+    }
+
+    #[test]
+    fn snapshots_start_with_the_home_page_then_go_down_the_site() {
+        let directory = std::env::temp_dir().join(format!("website-test-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&directory);
+        std::fs::create_dir_all(directory.join("services/colour")).unwrap();
+
+        let html = |title: &str| format!("<html><head><title>{}</title></head><body><p>{} page</p></body></html>", title, title);
+        std::fs::write(directory.join("about.html"), html("About")).unwrap();
+        std::fs::write(directory.join("index.html"), html("Home")).unwrap();
+        std::fs::write(directory.join("services/colour/balayage.htm"), html("Balayage")).unwrap();
+        std::fs::write(directory.join("services/cuts.html"), html("Cuts")).unwrap();
+        std::fs::write(directory.join("contact.HTML"), html("Contact")).unwrap();
+        std::fs::write(directory.join("empty.html"), "<html><head><title>Empty</title></head><body></body></html>").unwrap();
+        std::fs::write(directory.join("styles.css"), "body { color: red }").unwrap();
+
+        let pages = read_snapshot(&directory).unwrap();
+        let paths: Vec<&str> = pages.iter().map(|page| page.path.as_str()).collect();
+        let separator = std::path::MAIN_SEPARATOR;
+        assert_eq!(paths, vec![
+            "index.html".to_string(),
+            "about.html".to_string(),
+            "contact.HTML".to_string(),
+            format!("services{}cuts.html", separator),
+            format!("services{}colour{}balayage.htm", separator, separator),
+        ]);
+        assert_eq!(pages[0].title.as_deref(), Some("Home"));
+        assert_eq!(pages[0].text, "Home page");
+
+        assert!(matches!(read_snapshot(&directory.join("index.html")), Err(AppError::UserInput(_))));
+    }
+
+    #[test]
+    fn pages_text_keeps_whole_pages_while_they_fit_and_cuts_the_next_one_short() {
+        let window = context_window();
+        let pages = vec![
+            page("index.html", Some("Home"), "Welcome to the salon."),
+            page("prices.html", None, &"cut and colour ".repeat(200)),
+            page("about.html", Some("About"), "Never reached."),
+        ];
+
+        let everything = pages_text(&pages, &window, 10_000);
+        assert!(everything.starts_with("=== Home (index.html) ===\nWelcome to the salon.\n\n=== Untitled (prices.html) ===\ncut and colour"));
+        assert!(everything.ends_with("=== About (about.html) ===\nNever reached."));
+
+        let budget = window.count_text("=== Home (index.html) ===\nWelcome to the salon.") + 50;
+        let cut = pages_text(&pages, &window, budget);
+        assert!(window.count_text(&cut) <= budget);
+        assert!(cut.starts_with("=== Home (index.html) ===\nWelcome to the salon.\n\n=== Untitled (prices.html) ===\ncut and colour"));
+        assert!(cut.ends_with(" ..."));
+        assert!(!cut.contains("About"));
+        // As much of the page as fits, not just a word or two
+        assert!(window.count_text(&cut) > budget - 10);
+
+        // Nothing is cut mid-header when even the first page doesn't fit
+        assert_eq!(pages_text(&pages, &window, 5), "");
+    }
+
+    fn draft() -> WebsiteDraft {
+        serde_json::from_str(r#"{
+            "description": "A salon.",
+            "services": [
+                {"name": " Haircut ", "description": " ", "price": 25.0, "currency": "usd", "duration_minutes": 30},
+                {"name": "Colour", "price": -1.0, "variants": [{"name": "Full head", "price": 80.0}]},
+                {"name": "  "}
+            ],
+            "answers": [
+                {"question": "What are your opening hours?", "answer": " 9 till 5 "},
+                {"question": "Do you offer parking?", "answer": "NA"},
+                {"question": "Do you take walk-ins?", "answer": null}
+            ]
+        }"#).unwrap()
+    }
+
+    #[test]
+    fn the_catalog_keeps_named_services_and_cleans_them_up() {
+        let catalog = draft().catalog();
+
+        assert_eq!(catalog.items.len(), 2);
+        assert_eq!(catalog.items[0].name, "Haircut");
+        assert_eq!(catalog.items[0].description, None);
+        assert_eq!(catalog.items[0].price, Some(25.0));
+        assert_eq!(catalog.items[0].currency, "USD");
+        assert_eq!(catalog.items[0].duration_minutes, Some(30));
+
+        // A negative price isn't a price, and no currency means the default one
+        assert_eq!(catalog.items[1].price, None);
+        assert_eq!(catalog.items[1].currency, DEFAULT_CURRENCY);
+        assert_eq!(catalog.items[1].variants[0].price, Some(80.0));
+    }
+
+    #[test]
+    fn answers_are_matched_loosely_and_blank_ones_are_no_answer() {
+        let draft = draft();
+
+        assert_eq!(draft.answer("what are your opening hours? "), Some("9 till 5"));
+        assert_eq!(draft.answer("Do you offer parking?"), None);
+        assert_eq!(draft.answer("Do you take walk-ins?"), None);
+        assert_eq!(draft.answer("Is there wifi?"), None);
+    }
+}